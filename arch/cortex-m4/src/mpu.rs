@@ -115,16 +115,28 @@ impl kernel::mpu::MPU for MPU {
         }
     }
 
+    fn capabilities(&self) -> kernel::mpu::Capabilities {
+        let regs = unsafe { &*self.0 };
+        kernel::mpu::Capabilities {
+            num_regions: regs.mpu_type.get().data_regions.get() as usize,
+            // Regions of 256 bytes and up divide into eight subregions
+            min_region_size: 32,
+            num_subregions: 8,
+        }
+    }
+
     fn set_mpu(&self,
                region_num: u32,
                start_addr: u32,
                len: u32,
+               subregion_disable: u8,
                execute: kernel::mpu::ExecutePermission,
                access: kernel::mpu::AccessPermission) {
         let regs = unsafe { &*self.0 };
         regs.region_base_address.set(region_num | 1 << 4 | start_addr);
+        let srd = subregion_disable as u32;
         let xn = execute as u32;
         let ap = access as u32;
-        regs.region_attributes_and_size.set(1 | len << 1 | ap << 24 | xn << 28);
+        regs.region_attributes_and_size.set(1 | len << 1 | srd << 8 | ap << 24 | xn << 28);
     }
 }
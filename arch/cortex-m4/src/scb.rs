@@ -37,3 +37,14 @@ pub unsafe fn reset() {
     let reset = (0x5FA << 16) | (aircr & (0x7 << 8)) | (1 << 2);
     (*scb).aircr.set(reset);
 }
+
+/// Sets or clears SCR.SLEEPDEEP, which selects whether `wfi` enters the
+/// chip's deep sleep state.
+pub unsafe fn set_sleepdeep(deep: bool) {
+    let scr = (*scb).scr.get();
+    if deep {
+        (*scb).scr.set(scr | 1 << 2);
+    } else {
+        (*scb).scr.set(scr & !(1 << 2));
+    }
+}
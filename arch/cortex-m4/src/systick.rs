@@ -1,48 +1,96 @@
 use kernel;
 use kernel::common::volatile_cell::VolatileCell;
 
-pub struct SysTick {
+struct Registers {
     control: VolatileCell<u32>,
     reload: VolatileCell<u32>,
     value: VolatileCell<u32>,
     calibration: VolatileCell<u32>,
 }
 
+/// The SysTick counter is 24 bits wide.
+const MAX_RELOAD: u32 = 0xffffff;
+
+pub struct SysTick {
+    regs: &'static Registers,
+    /// Frequency of the core clock in Hz, or 0 to use the factory calibration
+    /// value.
+    hertz: u32,
+}
+
 #[no_mangle]
 pub static mut OVERFLOW_FIRED: VolatileCell<usize> = VolatileCell::new(0);
 
-const BASE_ADDR: *const SysTick = 0xE000E010 as *const SysTick;
+const BASE_ADDR: *const Registers = 0xE000E010 as *const Registers;
 
 impl SysTick {
-    pub unsafe fn new() -> &'static SysTick {
-        &*BASE_ADDR
+    /// Creates a SysTick that converts between ticks and time using the
+    /// factory calibration value (TENMS) of the core.
+    ///
+    /// The calibration value only holds for the clock the core was
+    /// calibrated against, so prefer `new_with_calibration` when the core
+    /// clock is known.
+    pub unsafe fn new() -> SysTick {
+        SysTick {
+            regs: &*BASE_ADDR,
+            hertz: 0,
+        }
+    }
+
+    /// Creates a SysTick for a core clocked at `clock_speed` Hz.
+    pub unsafe fn new_with_calibration(clock_speed: u32) -> SysTick {
+        SysTick {
+            regs: &*BASE_ADDR,
+            hertz: clock_speed,
+        }
+    }
+
+    fn ticks_per_ms(&self) -> u32 {
+        if self.hertz == 0 {
+            let tenms = self.regs.calibration.get() & 0xffffff;
+            tenms / 10
+        } else {
+            self.hertz / 1000
+        }
+    }
+
+    fn ticks_to_us(&self, ticks: u32) -> u32 {
+        let ticks_per_ms = self.ticks_per_ms();
+        if ticks_per_ms == 0 {
+            return 0;
+        }
+        (ticks / ticks_per_ms) * 1000 + (ticks % ticks_per_ms) * 1000 / ticks_per_ms
     }
 }
 
 impl kernel::SysTick for SysTick {
     fn set_timer(&self, us: u32) {
-        let tenms = self.calibration.get() & 0xffffff;
-        let reload = tenms * us / 10000;
+        let ticks_per_ms = self.ticks_per_ms();
 
-        self.value.set(0);
-        self.reload.set(reload);
+        // Split the conversion to avoid overflowing 32 bits
+        let reload = ticks_per_ms.saturating_mul(us / 1000)
+            .saturating_add(ticks_per_ms * (us % 1000) / 1000);
+
+        self.regs.value.set(0);
+        self.regs.reload.set(if reload > MAX_RELOAD { MAX_RELOAD } else { reload });
     }
 
     fn value(&self) -> u32 {
-        let tenms = self.calibration.get() & 0xffffff;
-        let value = self.value.get() & 0xffffff;
+        self.ticks_to_us(self.regs.value.get() & 0xffffff)
+    }
 
-        value * 10000 / tenms
+    fn interval(&self) -> u32 {
+        self.ticks_to_us(self.regs.reload.get() & 0xffffff)
     }
 
     fn overflowed(&self) -> bool {
-        self.control.get() & 1 << 16 != 0
+        self.regs.control.get() & 1 << 16 != 0
     }
 
     fn reset(&self) {
-        self.control.set(0);
-        self.reload.set(0);
-        self.value.set(0);
+        self.regs.control.set(0);
+        self.regs.reload.set(0);
+        self.regs.value.set(0);
         unsafe {
             OVERFLOW_FIRED.set(0);
         }
@@ -50,9 +98,9 @@ impl kernel::SysTick for SysTick {
 
     fn enable(&self, with_interrupt: bool) {
         if with_interrupt {
-            self.control.set(0b111);
+            self.regs.control.set(0b111);
         } else {
-            self.control.set(0b101);
+            self.regs.control.set(0b101);
         }
     }
 
@@ -0,0 +1,222 @@
+//! Apps that run as host code behind the system call interface.
+//!
+//! A hosted app is a state machine. Every time the kernel switches to the
+//! process, the app's `step` is called with the reason it was resumed and
+//! returns the next system call it makes. The system call is placed in the
+//! process's stack frame exactly where the exception entry of a Cortex-M
+//! would put it, so the kernel handles it with the same code it uses on
+//! hardware.
+
+use kernel::process;
+use systick;
+use chip;
+
+/// Why an app was resumed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Resume {
+    /// The process starts. The arguments are those of the initial function
+    /// call: the start of app memory, the app memory break and the kernel
+    /// memory break.
+    Start([usize; 4]),
+    /// The last system call returned this value.
+    Returned(isize),
+    /// The kernel called a callback the app subscribed, with the low bit of
+    /// `pc` cleared.
+    Callback { pc: usize, args: [usize; 4] },
+    /// The app finished computing.
+    Computed,
+}
+
+/// What an app does next.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Syscall {
+    Yield,
+    /// `callback` is the address passed to the kernel. It is handed back as
+    /// the `pc` of `Resume::Callback`, so it must be even and non-zero.
+    Subscribe {
+        driver: usize,
+        minor: usize,
+        callback: usize,
+        appdata: usize,
+    },
+    Command {
+        driver: usize,
+        minor: usize,
+        arg: usize,
+    },
+    /// `ptr` must point into the process's memory.
+    Allow {
+        driver: usize,
+        minor: usize,
+        ptr: *mut u8,
+        len: usize,
+    },
+    Memop { op: usize, arg: usize },
+    Exit(usize),
+    /// Runs for this many microseconds of simulated time without making a
+    /// system call. The app can be preempted while computing.
+    Compute(u32),
+    /// Faults, as if the app accessed memory it does not own.
+    Fault,
+}
+
+pub trait App {
+    fn step(&mut self, resume: Resume) -> Syscall;
+}
+
+impl<F: FnMut(Resume) -> Syscall> App for F {
+    fn step(&mut self, resume: Resume) -> Syscall {
+        self(resume)
+    }
+}
+
+/// Simulated time each system call takes, in microseconds.
+pub const SYSCALL_COST_US: u64 = 1;
+
+struct Entry {
+    mem_base: usize,
+    entry_pc: usize,
+    app: Box<App>,
+    compute_left: u32,
+}
+
+static mut APPS: Option<Vec<Entry>> = None;
+
+/// Each element holds the encoding of an `svc` instruction. A process that
+/// makes system call `n` is left with its PC just after element `n`, where
+/// the kernel looks for the SVC number.
+static mut SVC_INSTRUCTIONS: [u16; 256] = [0; 256];
+
+/// Runs `app` as the process whose memory starts at `mem_base` and whose
+/// entry point is `entry_pc`.
+pub unsafe fn register(mem_base: *const u8, entry_pc: usize, app: Box<App>) {
+    for (n, instruction) in SVC_INSTRUCTIONS.iter_mut().enumerate() {
+        *instruction = 0xdf00 | n as u16;
+    }
+    if APPS.is_none() {
+        APPS = Some(Vec::new());
+    }
+    let apps = APPS.as_mut().unwrap();
+    apps.retain(|entry| entry.mem_base != mem_base as usize);
+    apps.push(Entry {
+        mem_base: mem_base as usize,
+        entry_pc: entry_pc,
+        app: app,
+        compute_left: 0,
+    });
+}
+
+/// Forgets every registered app.
+pub unsafe fn clear() {
+    APPS = None;
+}
+
+fn svc_return_pc(svc: u8) -> usize {
+    unsafe { &SVC_INSTRUCTIONS[svc as usize] as *const u16 as usize + 2 }
+}
+
+fn is_svc_return_pc(pc: usize) -> bool {
+    unsafe {
+        let start = SVC_INSTRUCTIONS.as_ptr() as usize;
+        pc > start && pc <= start + 2 * SVC_INSTRUCTIONS.len()
+    }
+}
+
+/// Advances simulated time while the app computes, stopping early if the
+/// SysTick or another interrupt preempts it. Returns false if the app was
+/// preempted.
+fn compute(compute_left: &mut u32) -> bool {
+    let now = systick::now();
+    let end = now + *compute_left as u64;
+    let preempt_at = [systick::deadline(), chip::next_interrupt()]
+        .iter()
+        .filter_map(|&t| t)
+        .filter(|&t| t < end)
+        .min();
+    match preempt_at {
+        Some(at) => {
+            let at = if at > now { at } else { now };
+            systick::advance(at - now);
+            *compute_left -= (at - now) as u32;
+            if systick::deadline().map_or(false, |deadline| deadline <= at) {
+                systick::fire();
+            }
+            false
+        }
+        None => {
+            systick::advance(*compute_left as u64);
+            *compute_left = 0;
+            true
+        }
+    }
+}
+
+/// Stands in for the context switch of a Cortex-M: runs the app until it
+/// makes a system call, faults or is preempted.
+#[no_mangle]
+pub unsafe extern "C" fn switch_to_user(user_stack: *const u8,
+                                        mem_base: *const u8,
+                                        _process_regs: &mut [usize; 8])
+                                        -> *mut u8 {
+    let entry = match APPS.as_mut()
+        .and_then(|apps| apps.iter_mut().find(|entry| entry.mem_base == mem_base as usize)) {
+        Some(entry) => entry,
+        None => panic!("No hosted app for process memory at {:?}", mem_base),
+    };
+
+    // r0, r1, r2, r3, r12, lr, pc, xpsr as stacked on exception entry
+    let frame = user_stack as *mut usize;
+    let pc = *frame.offset(6) & !1;
+    let args = [*frame, *frame.offset(1), *frame.offset(2), *frame.offset(3)];
+
+    let mut resume = if pc == entry.entry_pc {
+        entry.compute_left = 0;
+        Resume::Start(args)
+    } else if entry.compute_left > 0 {
+        Resume::Computed
+    } else if is_svc_return_pc(pc) {
+        Resume::Returned(args[0] as isize)
+    } else {
+        Resume::Callback {
+            pc: pc,
+            args: args,
+        }
+    };
+
+    loop {
+        if entry.compute_left > 0 {
+            if !compute(&mut entry.compute_left) {
+                return user_stack as *mut u8;
+            }
+            resume = Resume::Computed;
+        }
+
+        let (svc, regs) = match entry.app.step(resume) {
+            Syscall::Compute(us) => {
+                entry.compute_left = us;
+                continue;
+            }
+            Syscall::Fault => {
+                process::APP_FAULT = 1;
+                process::SYSCALL_FIRED = 1;
+                return user_stack as *mut u8;
+            }
+            Syscall::Yield => (0, [0; 4]),
+            Syscall::Subscribe { driver, minor, callback, appdata } => {
+                (1, [driver, minor, callback, appdata])
+            }
+            Syscall::Command { driver, minor, arg } => (2, [driver, minor, arg, 0]),
+            Syscall::Allow { driver, minor, ptr, len } => (3, [driver, minor, ptr as usize, len]),
+            Syscall::Memop { op, arg } => (4, [op, arg, 0, 0]),
+            Syscall::Exit(status) => (5, [status, 0, 0, 0]),
+        };
+
+        for (i, reg) in regs.iter().enumerate() {
+            *frame.offset(i as isize) = *reg;
+        }
+        *frame.offset(6) = svc_return_pc(svc);
+        systick::advance(SYSCALL_COST_US);
+        process::SYSCALL_FIRED = 1;
+        return user_stack as *mut u8;
+    }
+}
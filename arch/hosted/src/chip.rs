@@ -0,0 +1,95 @@
+//! A simulated chip.
+//!
+//! There is no peripheral hardware. Instead, tests and simulated peripherals
+//! schedule interrupt handlers at points in simulated time. A handler runs
+//! when the kernel services interrupts after its time has come, just like the
+//! bottom half of an interrupt on a real chip.
+
+use kernel;
+use systick::{self, SysTick};
+
+struct Interrupt {
+    at: u64,
+    handler: Box<FnMut()>,
+}
+
+static mut INTERRUPTS: Option<Vec<Interrupt>> = None;
+
+/// Schedules `handler` to run as an interrupt `delay_us` microseconds of
+/// simulated time from now.
+pub fn schedule_interrupt<F: FnMut() + 'static>(delay_us: u64, handler: F) {
+    unsafe {
+        if INTERRUPTS.is_none() {
+            INTERRUPTS = Some(Vec::new());
+        }
+        INTERRUPTS.as_mut().unwrap().push(Interrupt {
+            at: systick::now() + delay_us,
+            handler: Box::new(handler),
+        });
+    }
+}
+
+/// The time of the earliest scheduled interrupt.
+pub fn next_interrupt() -> Option<u64> {
+    unsafe { INTERRUPTS.as_ref().and_then(|interrupts| interrupts.iter().map(|i| i.at).min()) }
+}
+
+/// Drops every scheduled interrupt.
+pub fn clear_interrupts() {
+    unsafe {
+        INTERRUPTS = None;
+    }
+}
+
+pub struct HostedChip {
+    mpu: (),
+    systick: SysTick,
+}
+
+impl HostedChip {
+    pub const fn new() -> HostedChip {
+        HostedChip {
+            mpu: (),
+            systick: SysTick::new(),
+        }
+    }
+}
+
+impl kernel::Chip for HostedChip {
+    type MPU = ();
+    type SysTick = SysTick;
+
+    fn service_pending_interrupts(&mut self) {
+        // Handlers may schedule further interrupts, so take them one at a time
+        // in the order they became due.
+        loop {
+            let now = systick::now();
+            let next = unsafe {
+                INTERRUPTS.as_mut().and_then(|interrupts| {
+                    let due = interrupts.iter()
+                        .enumerate()
+                        .filter(|&(_, i)| i.at <= now)
+                        .min_by_key(|&(_, i)| i.at)
+                        .map(|(idx, _)| idx);
+                    due.map(|idx| interrupts.remove(idx))
+                })
+            };
+            match next {
+                Some(mut interrupt) => (interrupt.handler)(),
+                None => break,
+            }
+        }
+    }
+
+    fn has_pending_interrupts(&self) -> bool {
+        next_interrupt().map_or(false, |at| at <= systick::now())
+    }
+
+    fn mpu(&self) -> &() {
+        &self.mpu
+    }
+
+    fn systick(&self) -> &SysTick {
+        &self.systick
+    }
+}
@@ -0,0 +1,227 @@
+//! A simulated architecture for running the kernel on a development machine.
+//!
+//! Processes are host code (see `app`) that make system calls through the same
+//! stack frames and `switch_to_user` interface the Cortex-M architectures use,
+//! so the scheduler, IPC, containers and capsules run unmodified. Time is
+//! simulated, which makes runs deterministic.
+//!
+//! The kernel keeps its state in globals, so only one `Simulation` may exist
+//! at a time. The tests of this crate take a lock around each simulation, so
+//! `cargo test` runs them one at a time. Tests elsewhere must do the same or
+//! run with `cargo test -- --test-threads=1`.
+//!
+//! The IPC driver is available to apps as driver number 0xff.
+//!
+//! ```rust,ignore
+//! let mut sim = unsafe { Simulation::new(2, RoundRobin::new(DEFAULT_TIMESLICE_US)) };
+//! // Capsules that use containers must be created before loading apps
+//! let console = ...;
+//! sim.platform.add_driver(0, &console);
+//!
+//! let appid = unsafe {
+//!     sim.load("hello", Box::new(|resume| match resume {
+//!         Resume::Start(_) => Syscall::Command { driver: 0, minor: 0, arg: 0 },
+//!         _ => Syscall::Exit(0),
+//!     }))
+//! };
+//! sim.run_until_idle();
+//! ```
+
+#![crate_name = "hosted"]
+
+extern crate kernel;
+
+pub mod app;
+pub mod chip;
+pub mod platform;
+pub mod systick;
+
+#[cfg(test)]
+mod tests;
+
+use app::App;
+use chip::HostedChip;
+use kernel::{AppId, Chip, Scheduler};
+use kernel::deferred_call;
+use kernel::ipc::IPC;
+use kernel::mpu::MPU;
+use kernel::process::{self, FaultResponse, Process};
+use platform::HostedPlatform;
+
+/// Stack, app heap and kernel heap sizes of simulated apps, in bytes.
+pub const APP_STACK_LEN: u32 = 2048;
+pub const APP_HEAP_LEN: u32 = 1024;
+pub const KERNEL_HEAP_LEN: u32 = 1024;
+
+/// Callback queue depth of simulated apps.
+pub const CALLBACK_QUEUE_LEN: usize = 10;
+
+/// The driver number apps use for IPC, as on the boards.
+pub const IPC_DRIVER_NUM: usize = 0xff;
+
+pub struct Simulation<'a, S: Scheduler> {
+    pub platform: HostedPlatform<'a>,
+    pub chip: HostedChip,
+    pub ipc: &'static IPC,
+    scheduler: S,
+    fault_response: FaultResponse,
+}
+
+impl<'a, S: Scheduler> Simulation<'a, S> {
+    /// Creates a simulation with room for `num_procs` processes.
+    ///
+    /// Containers used by the processes, including those of capsules, must be
+    /// created before apps are loaded.
+    pub unsafe fn new(num_procs: usize, scheduler: S) -> Simulation<'a, S> {
+        let processes: Vec<Option<Process<'static>>> = (0..num_procs).map(|_| None).collect();
+        process::PROCS = &mut *Box::into_raw(processes.into_boxed_slice());
+
+        let ipc = &*Box::into_raw(Box::new(IPC::new(num_procs)));
+        let mut platform = HostedPlatform::new();
+        platform.add_driver(IPC_DRIVER_NUM, ipc);
+
+        Simulation {
+            platform: platform,
+            chip: HostedChip::new(),
+            ipc: ipc,
+            scheduler: scheduler,
+            fault_response: FaultResponse::Panic,
+        }
+    }
+
+    /// Sets how the kernel responds to faults of apps loaded afterwards.
+    pub fn set_fault_response(&mut self, fault_response: FaultResponse) {
+        self.fault_response = fault_response;
+    }
+
+    /// Loads `app` as a new process named `name`.
+    ///
+    /// Returns `None` if every process slot is taken.
+    pub unsafe fn load(&mut self, name: &str, app: Box<App>) -> Option<AppId> {
+        let slot = match process::PROCS.iter().position(|p| p.is_none()) {
+            Some(slot) => slot,
+            None => return None,
+        };
+
+        let image = build_image(name);
+        let image_len = image.len() * 4;
+        let flash = Box::into_raw(image.into_boxed_slice()) as *const u8;
+        let capabilities = self.chip.mpu().capabilities();
+        let (_, memory_len) = match process::validate_image(flash,
+                                                            image_len,
+                                                            &capabilities,
+                                                            CALLBACK_QUEUE_LEN) {
+            Some(sizes) => sizes,
+            None => return None,
+        };
+        let memory: Vec<u64> = vec![0; memory_len / 8];
+        let memory = Box::into_raw(memory.into_boxed_slice()) as *mut u8;
+
+        // The entry point is the start of the text section
+        app::register(memory, flash as usize + HEADER_LEN, app);
+        let (process, _, _) = Process::create(flash,
+                                              memory,
+                                              memory_len,
+                                              &capabilities,
+                                              self.fault_response,
+                                              CALLBACK_QUEUE_LEN);
+        process.map(|process| {
+            let appid = AppId::new(slot, process.generation());
+            process::PROCS[slot] = Some(process);
+            appid
+        })
+    }
+
+    /// Runs one pass of the kernel main loop.
+    pub fn step(&mut self) {
+        unsafe {
+            kernel::run_once(&self.platform, &mut self.chip, &self.scheduler, self.ipc);
+        }
+    }
+
+    /// Runs the kernel until every process is blocked and no interrupt or
+    /// deferred call is pending. Interrupts scheduled for later are not waited
+    /// for.
+    pub fn run_until_idle(&mut self) {
+        while !self.idle() {
+            self.step();
+        }
+    }
+
+    /// Runs the kernel for `us` microseconds of simulated time. Whenever every
+    /// process is blocked, time skips ahead to the next interrupt.
+    pub fn run_for(&mut self, us: u64) {
+        let end = systick::now() + us;
+        while systick::now() < end {
+            self.step();
+            if self.idle() {
+                let next = chip::next_interrupt().map_or(end, |at| if at < end { at } else { end });
+                systick::advance(next - systick::now());
+            }
+        }
+    }
+
+    fn idle(&self) -> bool {
+        !self.chip.has_pending_interrupts() && !deferred_call::has_tasks() &&
+        process::processes_blocked()
+    }
+}
+
+impl<'a, S: Scheduler> Drop for Simulation<'a, S> {
+    fn drop(&mut self) {
+        unsafe {
+            for slot in process::PROCS.iter_mut() {
+                if let Some(mut process) = slot.take() {
+                    process.terminate(None);
+                }
+            }
+            process::PROCS = &mut [];
+            app::clear();
+            chip::clear_interrupts();
+        }
+    }
+}
+
+/// Size of a version 2 app header.
+const HEADER_LEN: usize = 20 * 4;
+
+/// Builds an app image with an empty text section. The app's code runs on
+/// the host, so the image only tells the kernel how much memory to give it.
+fn build_image(name: &str) -> Vec<u32> {
+    let text_len = 4;
+    let name_offset = (HEADER_LEN + text_len) as u32;
+    let name_len = name.len() as u32;
+    let total_size = (name_offset + name_len + 3) & !3;
+    // The kernel only starts processes at Thumb addresses
+    let entry_offset = HEADER_LEN as u32 | 1;
+
+    let mut header = [2,                 // version
+                      total_size,
+                      entry_offset,
+                      HEADER_LEN as u32, // rel_data_offset
+                      0,                 // rel_data_size
+                      HEADER_LEN as u32, // text_offset
+                      text_len as u32,   // text_size
+                      name_offset,       // got_offset
+                      0,                 // got_size
+                      name_offset,       // data_offset
+                      0,                 // data_size
+                      0,                 // bss_mem_offset
+                      0,                 // bss_size
+                      APP_STACK_LEN,
+                      APP_HEAP_LEN,
+                      KERNEL_HEAP_LEN,
+                      name_offset,       // pkg_name_offset
+                      name_len,          // pkg_name_size
+                      0,                 // callback_queue_len
+                      0 /* checksum */];
+    header[19] = header[..19].iter().fold(0, |checksum, field| checksum ^ field);
+
+    let mut image = vec![0u32; total_size as usize / 4];
+    image[..20].copy_from_slice(&header);
+    for (i, byte) in name.bytes().enumerate() {
+        let offset = name_offset as usize + i;
+        image[offset / 4] |= (byte as u32) << (8 * (offset % 4));
+    }
+    image
+}
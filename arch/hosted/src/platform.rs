@@ -0,0 +1,28 @@
+//! A platform whose drivers are registered at runtime.
+
+use kernel::{Driver, Platform};
+
+pub struct HostedPlatform<'a> {
+    drivers: Vec<(usize, &'a Driver)>,
+}
+
+impl<'a> HostedPlatform<'a> {
+    pub fn new() -> HostedPlatform<'a> {
+        HostedPlatform { drivers: Vec::new() }
+    }
+
+    /// Makes `driver` available to apps as driver number `driver_num`,
+    /// replacing any driver already registered with that number.
+    pub fn add_driver(&mut self, driver_num: usize, driver: &'a Driver) {
+        self.drivers.retain(|&(num, _)| num != driver_num);
+        self.drivers.push((driver_num, driver));
+    }
+}
+
+impl<'a> Platform for HostedPlatform<'a> {
+    fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
+        where F: FnOnce(Option<&Driver>) -> R
+    {
+        f(self.drivers.iter().find(|&&(num, _)| num == driver_num).map(|&(_, driver)| driver))
+    }
+}
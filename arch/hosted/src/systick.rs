@@ -0,0 +1,113 @@
+//! A SysTick that counts simulated time.
+//!
+//! Simulated time only moves when an app computes or makes a system call, or
+//! when the simulation skips ahead to the next interrupt while every process
+//! is blocked. Runs are therefore deterministic.
+
+use kernel;
+
+/// Simulated time since the simulation started, in microseconds.
+static mut NOW: u64 = 0;
+
+struct Timer {
+    start: u64,
+    reload: u32,
+    enabled: bool,
+    interrupt: bool,
+    fired: bool,
+}
+
+static mut TIMER: Timer = Timer {
+    start: 0,
+    reload: 0,
+    enabled: false,
+    interrupt: false,
+    fired: false,
+};
+
+/// The current simulated time in microseconds.
+pub fn now() -> u64 {
+    unsafe { NOW }
+}
+
+/// Moves simulated time forward by `us` microseconds.
+pub fn advance(us: u64) {
+    unsafe {
+        NOW += us;
+    }
+}
+
+/// The time at which the SysTick interrupt fires, if it is armed.
+pub fn deadline() -> Option<u64> {
+    unsafe {
+        if TIMER.enabled && TIMER.interrupt {
+            Some(TIMER.start + TIMER.reload as u64)
+        } else {
+            None
+        }
+    }
+}
+
+/// Marks the SysTick interrupt as fired, as the handler does on hardware.
+pub fn fire() {
+    unsafe {
+        TIMER.fired = true;
+    }
+}
+
+pub struct SysTick;
+
+impl SysTick {
+    pub const fn new() -> SysTick {
+        SysTick
+    }
+}
+
+impl kernel::SysTick for SysTick {
+    fn set_timer(&self, us: u32) {
+        unsafe {
+            TIMER.start = NOW;
+            TIMER.reload = us;
+        }
+    }
+
+    /// Like the hardware counter, the timer reloads when it reaches zero.
+    fn value(&self) -> u32 {
+        unsafe {
+            if TIMER.reload == 0 {
+                return 0;
+            }
+            let elapsed = NOW - TIMER.start;
+            TIMER.reload - (elapsed % TIMER.reload as u64) as u32
+        }
+    }
+
+    fn interval(&self) -> u32 {
+        unsafe { TIMER.reload }
+    }
+
+    fn overflowed(&self) -> bool {
+        unsafe { TIMER.enabled && NOW - TIMER.start >= TIMER.reload as u64 }
+    }
+
+    fn reset(&self) {
+        unsafe {
+            TIMER.start = NOW;
+            TIMER.reload = 0;
+            TIMER.enabled = false;
+            TIMER.interrupt = false;
+            TIMER.fired = false;
+        }
+    }
+
+    fn enable(&self, with_interrupt: bool) {
+        unsafe {
+            TIMER.enabled = true;
+            TIMER.interrupt = with_interrupt;
+        }
+    }
+
+    fn overflow_fired() -> bool {
+        unsafe { TIMER.fired }
+    }
+}
@@ -0,0 +1,424 @@
+//! Runs the scheduler, IPC and containers against simulated apps.
+
+use app::{self, App, Resume, Syscall};
+use chip;
+use kernel::{AppId, Container, Driver};
+use kernel::container::Owned;
+use kernel::loader::{LoadError, Loader};
+use kernel::mpu::MPU;
+use kernel::process::{self, FaultResponse, Process, RestartPolicy, State};
+use kernel::returncode::ReturnCode;
+use kernel::scheduler::{Cooperative, FixedPriority, RoundRobin};
+use std::cell::{Cell, RefCell};
+use std::ptr;
+use std::rc::Rc;
+use std::sync::{Mutex, MutexGuard, ONCE_INIT, Once};
+use {CALLBACK_QUEUE_LEN, HEADER_LEN, IPC_DRIVER_NUM, Simulation, build_image};
+
+/// Driver number of `Recorder` in the tests.
+const RECORDER_DRIVER_NUM: usize = 1;
+
+/// Timeslice short enough for the apps below to be preempted.
+const TIMESLICE_US: u32 = 1000;
+
+/// The kernel keeps its state in globals, so simulations must not run
+/// concurrently. Tests hold this lock for as long as their simulation exists.
+fn lock() -> MutexGuard<'static, ()> {
+    static INIT: Once = ONCE_INIT;
+    static mut LOCK: *const Mutex<()> = 0 as *const Mutex<()>;
+    unsafe {
+        INIT.call_once(|| LOCK = Box::into_raw(Box::new(Mutex::new(()))));
+        // A failed test must not fail every test after it
+        (*LOCK).lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn process_in(slot: usize) -> &'static Process<'static> {
+    unsafe { process::PROCS[slot].as_ref().expect("no process in slot") }
+}
+
+/// An app that makes the system calls in `syscalls`, then yields for good.
+fn script(syscalls: Vec<Syscall>) -> Box<App> {
+    let mut syscalls = syscalls.into_iter();
+    Box::new(move |_: Resume| syscalls.next().unwrap_or(Syscall::Yield))
+}
+
+/// Records which process issued each command, in order.
+struct Recorder {
+    commands: RefCell<Vec<(usize, usize)>>,
+}
+
+impl Recorder {
+    fn new() -> Recorder {
+        Recorder { commands: RefCell::new(Vec::new()) }
+    }
+}
+
+impl Driver for Recorder {
+    fn command(&self, minor_num: usize, _: usize, caller_id: AppId) -> ReturnCode {
+        self.commands.borrow_mut().push((caller_id.idx(), minor_num));
+        ReturnCode::SUCCESS
+    }
+}
+
+/// Issues command 0, computes for `compute_us` and issues command 1.
+fn compute_between_commands(compute_us: u32) -> Box<App> {
+    script(vec![Syscall::Command {
+                    driver: RECORDER_DRIVER_NUM,
+                    minor: 0,
+                    arg: 0,
+                },
+                Syscall::Compute(compute_us),
+                Syscall::Command {
+                    driver: RECORDER_DRIVER_NUM,
+                    minor: 1,
+                    arg: 0,
+                }])
+}
+
+#[test]
+fn round_robin_preempts_at_the_end_of_the_timeslice() {
+    let _lock = lock();
+    let recorder = Recorder::new();
+    let mut sim = unsafe { Simulation::new(2, RoundRobin::new(TIMESLICE_US)) };
+    sim.platform.add_driver(RECORDER_DRIVER_NUM, &recorder);
+    unsafe {
+        sim.load("first", compute_between_commands(TIMESLICE_US + 500)).unwrap();
+        sim.load("second", compute_between_commands(TIMESLICE_US + 500)).unwrap();
+    }
+
+    sim.run_until_idle();
+
+    assert_eq!(*recorder.commands.borrow(), [(0, 0), (1, 0), (0, 1), (1, 1)]);
+    assert_eq!(process_in(0).timeslice_expirations(), 1);
+}
+
+#[test]
+fn fixed_priority_runs_the_first_process_until_it_blocks() {
+    let _lock = lock();
+    let recorder = Recorder::new();
+    let mut sim = unsafe { Simulation::new(2, FixedPriority::new(TIMESLICE_US)) };
+    sim.platform.add_driver(RECORDER_DRIVER_NUM, &recorder);
+    unsafe {
+        sim.load("high", compute_between_commands(TIMESLICE_US + 500)).unwrap();
+        sim.load("low", compute_between_commands(TIMESLICE_US + 500)).unwrap();
+    }
+
+    sim.run_until_idle();
+
+    assert_eq!(*recorder.commands.borrow(), [(0, 0), (0, 1), (1, 0), (1, 1)]);
+}
+
+#[test]
+fn cooperative_accounts_cpu_time_without_preempting() {
+    let _lock = lock();
+    let recorder = Recorder::new();
+    let mut sim = unsafe { Simulation::new(2, Cooperative::new()) };
+    sim.platform.add_driver(RECORDER_DRIVER_NUM, &recorder);
+    unsafe {
+        sim.load("first", compute_between_commands(1000000)).unwrap();
+        sim.load("second", compute_between_commands(10)).unwrap();
+    }
+
+    sim.run_until_idle();
+
+    assert_eq!(*recorder.commands.borrow(), [(0, 0), (0, 1), (1, 0), (1, 1)]);
+    assert!(process_in(0).cpu_time_us() >= 1000000);
+    assert_eq!(process_in(0).timeslice_expirations(), 0);
+}
+
+#[test]
+fn cooperative_resumes_an_interrupted_process_first() {
+    let _lock = lock();
+    let recorder = Recorder::new();
+    let mut sim = unsafe { Simulation::new(2, Cooperative::new()) };
+    sim.platform.add_driver(RECORDER_DRIVER_NUM, &recorder);
+    unsafe {
+        sim.load("first", compute_between_commands(1000)).unwrap();
+        sim.load("second", compute_between_commands(10)).unwrap();
+    }
+    let serviced = Rc::new(Cell::new(false));
+    let handler_serviced = serviced.clone();
+    chip::schedule_interrupt(500, move || handler_serviced.set(true));
+
+    sim.run_until_idle();
+
+    assert!(serviced.get());
+    assert_eq!(*recorder.commands.borrow(), [(0, 0), (0, 1), (1, 0), (1, 1)]);
+}
+
+#[test]
+fn faulting_process_restarts_after_its_backoff() {
+    let _lock = lock();
+    let mut sim = unsafe { Simulation::new(1, RoundRobin::new(TIMESLICE_US)) };
+    sim.set_fault_response(FaultResponse::Restart(RestartPolicy {
+        max_restarts: 1,
+        backoff: 2,
+        panic_when_exhausted: false,
+    }));
+    let starts = Rc::new(Cell::new(0));
+    let app_starts = starts.clone();
+    unsafe {
+        sim.load("faulty",
+                  Box::new(move |resume: Resume| {
+                      if let Resume::Start(_) = resume {
+                          app_starts.set(app_starts.get() + 1);
+                      }
+                      Syscall::Fault
+                  }))
+            .unwrap();
+    }
+
+    sim.step();
+    assert_eq!(starts.get(), 1);
+    assert_eq!(process_in(0).current_state(), State::Fault);
+
+    // The backoff counts passes of the main loop
+    sim.step();
+    sim.step();
+    assert_eq!(starts.get(), 1);
+    sim.step();
+    assert_eq!(starts.get(), 2);
+    assert_eq!(process_in(0).restart_count(), 1);
+
+    // Out of restarts, the process stays faulted
+    for _ in 0..5 {
+        sim.step();
+    }
+    assert_eq!(starts.get(), 2);
+    assert_eq!(process_in(0).current_state(), State::Fault);
+    assert!(process::processes_blocked());
+}
+
+#[test]
+fn restart_requested_from_outside_does_not_count_against_the_limit() {
+    let _lock = lock();
+    let mut sim = unsafe { Simulation::new(1, RoundRobin::new(TIMESLICE_US)) };
+    let appid = unsafe { sim.load("app", script(vec![])).unwrap() };
+    sim.run_until_idle();
+
+    assert!(process::restart(appid, sim.ipc).is_ok());
+    sim.run_until_idle();
+
+    assert_eq!(process_in(0).restart_count(), 0);
+    assert_eq!(process_in(0).current_state(), State::Yielded);
+}
+
+#[test]
+fn terminated_process_has_no_live_appid() {
+    let _lock = lock();
+    let container: Container<Blocks> = unsafe { Container::create() };
+    let mut sim = unsafe { Simulation::new(1, RoundRobin::new(TIMESLICE_US)) };
+    let appid = unsafe { sim.load("app", script(vec![Syscall::Exit(0)])).unwrap() };
+    sim.run_until_idle();
+
+    assert_eq!(process_in(0).current_state(), State::Terminated);
+    assert!(!appid.is_live());
+    assert!(container.enter(appid, |_, _| ()).is_err());
+    assert_eq!(container.grant_bytes(appid), 0);
+}
+
+#[test]
+fn ipc_client_finds_and_notifies_a_service_with_a_shared_buffer() {
+    let _lock = lock();
+    let mut sim = unsafe { Simulation::new(2, RoundRobin::new(TIMESLICE_US)) };
+
+    const SERVICE_CALLBACK: usize = 0x100;
+    let notified = Rc::new(Cell::new(None));
+    let service_notified = notified.clone();
+    let service = move |resume: Resume| match resume {
+        Resume::Start(_) => {
+            Syscall::Subscribe {
+                driver: IPC_DRIVER_NUM,
+                minor: 0,
+                callback: SERVICE_CALLBACK,
+                appdata: 7,
+            }
+        }
+        Resume::Callback { pc: SERVICE_CALLBACK, args } => {
+            service_notified.set(Some(args));
+            Syscall::Yield
+        }
+        _ => Syscall::Yield,
+    };
+
+    const SHARED_LEN: usize = 64;
+    let found = Rc::new(Cell::new(None));
+    let client_found = found.clone();
+    let shared = Rc::new(Cell::new(ptr::null_mut()));
+    let client_shared = shared.clone();
+    let mut client_step = 0;
+    let client = move |resume: Resume| {
+        client_step += 1;
+        match (client_step, resume) {
+            (1, Resume::Start(args)) => {
+                // Use the bottom of the stack as the app's buffer
+                let buffer = args[0] as *mut u8;
+                unsafe {
+                    ptr::copy_nonoverlapping(b"service".as_ptr(), buffer, 7);
+                }
+                client_shared.set(buffer);
+                Syscall::Allow {
+                    driver: IPC_DRIVER_NUM,
+                    minor: 0,
+                    ptr: buffer,
+                    len: 7,
+                }
+            }
+            (2, Resume::Returned(id)) => {
+                client_found.set(Some(id));
+                Syscall::Allow {
+                    driver: IPC_DRIVER_NUM,
+                    minor: id as usize,
+                    ptr: client_shared.get(),
+                    len: SHARED_LEN,
+                }
+            }
+            (3, Resume::Returned(0)) => {
+                Syscall::Command {
+                    driver: IPC_DRIVER_NUM,
+                    minor: client_found.get().unwrap() as usize,
+                    arg: 0,
+                }
+            }
+            _ => Syscall::Yield,
+        }
+    };
+
+    unsafe {
+        sim.load("service", Box::new(service)).unwrap();
+        sim.load("client", Box::new(client)).unwrap();
+    }
+    sim.run_until_idle();
+
+    assert_eq!(found.get(), Some(1));
+    assert_eq!(notified.get(),
+               Some([2 /* client id */, SHARED_LEN, shared.get() as usize, 7]));
+}
+
+#[test]
+fn ipc_service_cannot_register_another_apps_package_name() {
+    let _lock = lock();
+    let mut sim = unsafe { Simulation::new(2, RoundRobin::new(TIMESLICE_US)) };
+
+    // Allow number of registering a service name
+    const REGISTER: usize = 0xff00;
+    let results = Rc::new(RefCell::new(Vec::new()));
+    let impostor_results = results.clone();
+    let buffer = Rc::new(Cell::new(ptr::null_mut()));
+    let mut impostor_step = 0;
+    let impostor = move |resume: Resume| {
+        impostor_step += 1;
+        match resume {
+            Resume::Start(args) => buffer.set(args[0] as *mut u8),
+            Resume::Returned(result) => impostor_results.borrow_mut().push(result),
+            _ => {}
+        }
+        let (minor, name): (usize, &[u8]) = match impostor_step {
+            1 => (REGISTER, b"victim"),
+            2 => (0, b"victim"),
+            3 => (REGISTER, b"impostor"),
+            _ => return Syscall::Yield,
+        };
+        unsafe {
+            ptr::copy_nonoverlapping(name.as_ptr(), buffer.get(), name.len());
+        }
+        Syscall::Allow {
+            driver: IPC_DRIVER_NUM,
+            minor: minor,
+            ptr: buffer.get(),
+            len: name.len(),
+        }
+    };
+
+    unsafe {
+        sim.load("victim", script(vec![])).unwrap();
+        sim.load("impostor", Box::new(impostor)).unwrap();
+    }
+    sim.run_until_idle();
+
+    // Clients looking for the victim still find it in slot 0
+    assert_eq!(*results.borrow(),
+               [isize::from(ReturnCode::EBUSY), 1, isize::from(ReturnCode::SUCCESS)]);
+}
+
+#[test]
+fn loader_starts_an_aligned_image_and_rejects_a_misaligned_one() {
+    let _lock = lock();
+    let recorder = Recorder::new();
+    let mut sim = unsafe { Simulation::new(2, RoundRobin::new(TIMESLICE_US)) };
+    sim.platform.add_driver(RECORDER_DRIVER_NUM, &recorder);
+
+    const MEMORY_LEN: usize = 8192;
+    let memory = Box::into_raw(vec![0u64; MEMORY_LEN / 8].into_boxed_slice()) as *mut u8;
+    let loader = Loader::new(memory,
+                             MEMORY_LEN,
+                             sim.chip.mpu().capabilities(),
+                             FaultResponse::Panic,
+                             CALLBACK_QUEUE_LEN);
+
+    // Copy the image one byte past a word boundary as well
+    let image = build_image("loaded");
+    let image_len = image.len() * 4;
+    let mut misaligned = vec![0u32; image.len() + 1];
+    unsafe {
+        ptr::copy_nonoverlapping(image.as_ptr() as *const u8,
+                                 (misaligned.as_mut_ptr() as *mut u8).offset(1),
+                                 image_len);
+    }
+    let flash = Box::into_raw(image.into_boxed_slice()) as *const u8;
+
+    let appid = unsafe {
+        let misaligned = (misaligned.as_ptr() as *const u8).offset(1);
+        assert_eq!(loader.load(misaligned, image_len), Err(LoadError::Misaligned));
+
+        // The loader places the first process at the start of app memory
+        app::register(memory,
+                      flash as usize + HEADER_LEN,
+                      script(vec![Syscall::Command {
+                                      driver: RECORDER_DRIVER_NUM,
+                                      minor: 0,
+                                      arg: 0,
+                                  }]));
+        loader.load(flash, image_len).unwrap()
+    };
+    sim.run_until_idle();
+
+    assert_eq!(appid.idx(), 0);
+    assert_eq!(process_in(0).package_name, "loaded");
+    assert_eq!(*recorder.commands.borrow(), [(0, 0)]);
+}
+
+#[derive(Default)]
+struct Blocks {
+    block: Option<Owned<[u8; 64]>>,
+}
+
+#[test]
+fn container_memory_is_freed_and_reused() {
+    let _lock = lock();
+    // Containers must exist before apps are loaded
+    let container: Container<Blocks> = unsafe { Container::create() };
+    let mut sim = unsafe { Simulation::new(1, RoundRobin::new(TIMESLICE_US)) };
+    let appid = unsafe { sim.load("app", script(vec![])).unwrap() };
+    assert_eq!(container.grant_bytes(appid), 0);
+
+    container.enter(appid, |blocks, allocator| {
+            blocks.block = allocator.alloc([0; 64]).ok();
+        })
+        .unwrap();
+    let allocated = container.grant_bytes(appid);
+    let free_memory = process_in(0).free_memory();
+    assert!(allocated >= 64);
+
+    container.enter(appid, |blocks, _| blocks.block = None).unwrap();
+    assert!(container.grant_bytes(appid) <= allocated - 64);
+
+    // The freed block is reused instead of growing the grant region
+    container.enter(appid, |blocks, allocator| {
+            blocks.block = allocator.alloc([0; 64]).ok();
+        })
+        .unwrap();
+    assert_eq!(container.grant_bytes(appid), allocated);
+    assert_eq!(process_in(0).free_memory(), free_memory);
+}
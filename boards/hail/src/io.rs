@@ -1,6 +1,6 @@
 use core::fmt::*;
+use kernel;
 use kernel::hil::uart::{self, UART};
-use kernel::process;
 use sam4l;
 
 pub struct Writer {
@@ -39,26 +39,8 @@ impl Write for Writer {
 pub unsafe extern "C" fn panic_fmt(args: Arguments, file: &'static str, line: u32) -> ! {
 
     let writer = &mut WRITER;
-    let _ = writer.write_fmt(format_args!("Kernel panic at {}:{}:\r\n\t\"", file, line));
-    let _ = write(writer, args);
-    let _ = writer.write_str("\"\r\n");
-
-    // Print fault status once
-    let procs = &mut process::PROCS;
-    if procs.len() > 0 {
-        procs[0].as_mut().map(|process| {
-            process.fault_str(writer);
-        });
-    }
-
-    // print data about each process
-    let _ = writer.write_fmt(format_args!("\r\n---| App Status |---\r\n"));
-    let procs = &mut process::PROCS;
-    for idx in 0..procs.len() {
-        procs[idx].as_mut().map(|process| {
-            process.statistics_str(writer);
-        });
-    }
+    kernel::report::panic_str(writer, args, file, line);
+    kernel::trace::dump(writer);
 
     // blink the panic signal
     let led = &sam4l::gpio::PA[13];
@@ -4,7 +4,7 @@
 
 extern crate capsules;
 extern crate cortexm4;
-#[macro_use(static_init)]
+#[macro_use(static_init, platform_drivers)]
 extern crate kernel;
 extern crate sam4l;
 
@@ -14,7 +14,7 @@ use capsules::timer::TimerDriver;
 use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use capsules::virtual_i2c::{I2CDevice, MuxI2C};
 use capsules::virtual_spi::{VirtualSpiMasterDevice, MuxSpiMaster};
-use kernel::{Chip, Platform};
+use kernel::Chip;
 use kernel::hil;
 use kernel::hil::Controller;
 use kernel::hil::spi::SpiMaster;
@@ -27,17 +27,22 @@ pub mod io;
 static mut spi_read_buf: [u8; 64] = [0; 64];
 static mut spi_write_buf: [u8; 64] = [0; 64];
 
-unsafe fn load_processes() -> &'static mut [Option<kernel::process::Process<'static>>] {
+// Number of concurrent processes this platform supports.
+const NUM_PROCS: usize = 2;
+
+unsafe fn load_processes(mpu: kernel::mpu::Capabilities)
+                         -> &'static mut [Option<kernel::process::Process<'static>>] {
     extern "C" {
         /// Beginning of the ROM region containing app images.
         static _sapps: u8;
     }
 
-    const NUM_PROCS: usize = 2;
-
     // how should the kernel respond when a process faults
     const FAULT_RESPONSE: kernel::process::FaultResponse = kernel::process::FaultResponse::Panic;
 
+    // callback queue depth for apps that do not set one in their header
+    const CALLBACK_QUEUE_LEN: usize = 10;
+
     #[link_section = ".app_memory"]
     static mut APP_MEMORY: [u8; 16384] = [0; 16384];
 
@@ -51,7 +56,9 @@ unsafe fn load_processes() -> &'static mut [Option<kernel::process::Process<'sta
             kernel::process::Process::create(apps_in_flash_ptr,
                                              app_memory_ptr,
                                              app_memory_size,
-                                             FAULT_RESPONSE);
+                                             &mpu,
+                                             FAULT_RESPONSE,
+                                             CALLBACK_QUEUE_LEN);
 
         if process.is_none() {
             break;
@@ -83,33 +90,25 @@ struct Hail {
     button: &'static capsules::button::Button<'static, sam4l::gpio::GPIOPin>,
     rng: &'static capsules::rng::SimpleRng<'static, sam4l::trng::Trng<'static>>,
     ipc: kernel::ipc::IPC,
+    discovery: kernel::discovery::Discovery,
 }
 
-impl Platform for Hail {
-    fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
-        where F: FnOnce(Option<&kernel::Driver>) -> R
-    {
-
-        match driver_num {
-            0 => f(Some(self.console)),
-            1 => f(Some(self.gpio)),
-
-            3 => f(Some(self.timer)),
-            4 => f(Some(self.spi)),
-            5 => f(Some(self.nrf51822)),
-            6 => f(Some(self.isl29035)),
-            7 => f(Some(self.adc)),
-            8 => f(Some(self.led)),
-            9 => f(Some(self.button)),
-            10 => f(Some(self.si7021)),
-            11 => f(Some(self.fxos8700)),
-
-            14 => f(Some(self.rng)),
-
-            0xff => f(Some(&self.ipc)),
-            _ => f(None),
-        }
-    }
+platform_drivers! {
+    Hail, DRIVERS, hail,
+    0 => ("console", 1, hail.console),
+    1 => ("gpio", 1, hail.gpio),
+    3 => ("timer", 1, hail.timer),
+    4 => ("spi", 1, hail.spi),
+    5 => ("nrf51822", 1, hail.nrf51822),
+    6 => ("isl29035", 1, hail.isl29035),
+    7 => ("adc", 1, hail.adc),
+    8 => ("led", 1, hail.led),
+    9 => ("button", 1, hail.button),
+    10 => ("si7021", 1, hail.si7021),
+    11 => ("fxos8700", 1, hail.fxos8700),
+    14 => ("rng", 1, hail.rng),
+    kernel::discovery::DRIVER_NUM => ("discovery", 1, &hail.discovery),
+    0xff => ("ipc", 1, &hail.ipc),
 }
 
 
@@ -205,8 +204,11 @@ pub unsafe fn reset_handler() {
         16);
     ast.configure(mux_alarm);
 
-    let sensors_i2c = static_init!(MuxI2C<'static>, MuxI2C::new(&sam4l::i2c::I2C1), 20);
+    let sensors_i2c = static_init!(MuxI2C<'static>, MuxI2C::new(&sam4l::i2c::I2C1), 28);
     sam4l::i2c::I2C1.set_master_client(sensors_i2c);
+    let i2c_call = kernel::deferred_call::DeferredCall::register(sensors_i2c)
+        .expect("out of deferred calls");
+    sensors_i2c.set_deferred_call(i2c_call);
 
     // SI7021 Temperature / Humidity Sensor, address: 0x40
     let si7021_i2c = static_init!(
@@ -356,7 +358,8 @@ pub unsafe fn reset_handler() {
         led: led,
         button: button,
         rng: rng,
-        ipc: kernel::ipc::IPC::new(),
+        ipc: kernel::ipc::IPC::new(NUM_PROCS),
+        discovery: kernel::discovery::Discovery::new(DRIVERS),
     };
 
     // Need to reset the nRF on boot
@@ -371,5 +374,9 @@ pub unsafe fn reset_handler() {
     let mut chip = sam4l::chip::Sam4l::new();
     chip.mpu().enable_mpu();
 
-    kernel::main(&hail, &mut chip, load_processes(), &hail.ipc);
+    let processes = load_processes(chip.mpu().capabilities());
+    let scheduler =
+        kernel::scheduler::RoundRobin::new(kernel::scheduler::DEFAULT_TIMESLICE_US);
+    kernel::watchdog::enable(&sam4l::wdt::WDT, 1000);
+    kernel::main(&hail, &mut chip, &scheduler, processes, &hail.ipc);
 }
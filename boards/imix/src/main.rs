@@ -3,10 +3,11 @@
 #![feature(const_fn,lang_items)]
 
 extern crate capsules;
-#[macro_use(static_init)]
+#[macro_use(static_init, platform_drivers)]
 extern crate kernel;
 extern crate sam4l;
 
+use capsules::alarm_systick::AlarmSysTick;
 use capsules::timer::TimerDriver;
 use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use capsules::virtual_i2c::{I2CDevice, MuxI2C};
@@ -40,30 +41,24 @@ struct Imix {
     button: &'static capsules::button::Button<'static, sam4l::gpio::GPIOPin>,
     spi: &'static capsules::spi::Spi<'static, VirtualSpiMasterDevice<'static, sam4l::spi::Spi>>,
     ipc: kernel::ipc::IPC,
+    discovery: kernel::discovery::Discovery,
     fxos8700_cq: &'static capsules::fxos8700_cq::Fxos8700cq<'static>,
 }
 
-impl kernel::Platform for Imix {
-    fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
-        where F: FnOnce(Option<&kernel::Driver>) -> R
-    {
-        match driver_num {
-            0 => f(Some(self.console)),
-            1 => f(Some(self.gpio)),
-
-            3 => f(Some(self.timer)),
-            4 => f(Some(self.spi)),
-            6 => f(Some(self.isl29035)),
-            7 => f(Some(self.adc)),
-            8 => f(Some(self.led)),
-            9 => f(Some(self.button)),
-            10 => f(Some(self.si7021)),
-            11 => f(Some(self.fxos8700_cq)),
-
-            0xff => f(Some(&self.ipc)),
-            _ => f(None),
-        }
-    }
+platform_drivers! {
+    Imix, DRIVERS, imix,
+    0 => ("console", 1, imix.console),
+    1 => ("gpio", 1, imix.gpio),
+    3 => ("timer", 1, imix.timer),
+    4 => ("spi", 1, imix.spi),
+    6 => ("isl29035", 1, imix.isl29035),
+    7 => ("adc", 1, imix.adc),
+    8 => ("led", 1, imix.led),
+    9 => ("button", 1, imix.button),
+    10 => ("si7021", 1, imix.si7021),
+    11 => ("fxos8700", 1, imix.fxos8700_cq),
+    kernel::discovery::DRIVER_NUM => ("discovery", 1, &imix.discovery),
+    0xff => ("ipc", 1, &imix.ipc),
 }
 
 unsafe fn set_pin_primary_functions() {
@@ -176,10 +171,24 @@ pub unsafe fn reset_handler() {
         12);
     virtual_alarm1.set_client(timer);
 
+    // Preempt processes with the AST so the core clock can stop in sleep
+    let systick_alarm = static_init!(
+        VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+        VirtualMuxAlarm::new(mux_alarm),
+        24);
+    let systick = static_init!(
+        AlarmSysTick<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
+        AlarmSysTick::new(systick_alarm),
+        16);
+    systick_alarm.set_client(systick);
+
     // # I2C Sensors
 
-    let mux_i2c = static_init!(MuxI2C<'static>, MuxI2C::new(&sam4l::i2c::I2C2), 20);
+    let mux_i2c = static_init!(MuxI2C<'static>, MuxI2C::new(&sam4l::i2c::I2C2), 28);
     sam4l::i2c::I2C2.set_master_client(mux_i2c);
+    let i2c_call = kernel::deferred_call::DeferredCall::register(mux_i2c)
+        .expect("out of deferred calls");
+    mux_i2c.set_deferred_call(i2c_call);
 
     // Configure the ISL29035, device address 0x44
     let isl29035_i2c = static_init!(I2CDevice, I2CDevice::new(mux_i2c, 0x44), 32);
@@ -311,36 +320,63 @@ pub unsafe fn reset_handler() {
         btn.set_client(button);
     }
 
-    let imix = Imix {
-        console: console,
-        timer: timer,
-        gpio: gpio,
-        si7021: si7021,
-        isl29035: isl29035,
-        adc: adc,
-        led: led,
-        button: button,
-        spi: spi_syscalls,
-        ipc: kernel::ipc::IPC::new(),
-        fxos8700_cq: fx0,
-    };
-
-    let mut chip = sam4l::chip::Sam4l::new();
+    let imix = static_init!(
+        Imix,
+        Imix {
+            console: console,
+            timer: timer,
+            gpio: gpio,
+            si7021: si7021,
+            isl29035: isl29035,
+            adc: adc,
+            led: led,
+            button: button,
+            spi: spi_syscalls,
+            ipc: kernel::ipc::IPC::new(NUM_PROCS),
+            discovery: kernel::discovery::Discovery::new(DRIVERS),
+            fxos8700_cq: fx0,
+        },
+        60);
+
+    // # SHELL
+
+    // On the USART0 header pins, so apps keep the console to themselves
+    let shell = static_init!(
+        capsules::shell::Shell<'static, sam4l::usart::USART, Imix>,
+        capsules::shell::Shell::new(&sam4l::usart::USART0,
+                                    115200,
+                                    &mut capsules::shell::RX_BUF,
+                                    &mut capsules::shell::LINE_BUF,
+                                    &mut capsules::shell::TX_BUF),
+        44);
+    hil::uart::UART::set_client(&sam4l::usart::USART0, shell);
+    shell.set_platform(imix, &imix.ipc);
+    shell.initialize();
+
+    let mut chip = sam4l::chip::Sam4l::new().with_systick(&*systick);
     chip.mpu().enable_mpu();
-    kernel::main(&imix, &mut chip, load_processes(), &imix.ipc);
+    let processes = load_processes(chip.mpu().capabilities());
+    let scheduler =
+        kernel::scheduler::RoundRobin::new(kernel::scheduler::DEFAULT_TIMESLICE_US);
+    kernel::main(imix, &mut chip, &scheduler, processes, &imix.ipc);
 }
 
-unsafe fn load_processes() -> &'static mut [Option<kernel::process::Process<'static>>] {
+// Number of concurrent processes this platform supports.
+const NUM_PROCS: usize = 2;
+
+unsafe fn load_processes(mpu: kernel::mpu::Capabilities)
+                         -> &'static mut [Option<kernel::process::Process<'static>>] {
     extern "C" {
         /// Beginning of the ROM region containing app images.
         static _sapps: u8;
     }
 
-    const NUM_PROCS: usize = 2;
-
     // how should the kernel respond when a process faults
     const FAULT_RESPONSE: kernel::process::FaultResponse = kernel::process::FaultResponse::Panic;
 
+    // callback queue depth for apps that do not set one in their header
+    const CALLBACK_QUEUE_LEN: usize = 10;
+
     #[link_section = ".app_memory"]
     static mut APP_MEMORY: [u8; 16384] = [0; 16384];
 
@@ -354,7 +390,9 @@ unsafe fn load_processes() -> &'static mut [Option<kernel::process::Process<'sta
             kernel::process::Process::create(apps_in_flash_ptr,
                                              app_memory_ptr,
                                              app_memory_size,
-                                             FAULT_RESPONSE);
+                                             &mpu,
+                                             FAULT_RESPONSE,
+                                             CALLBACK_QUEUE_LEN);
 
         if process.is_none() {
             break;
@@ -39,7 +39,7 @@
 
 extern crate cortexm0;
 extern crate capsules;
-#[macro_use(static_init)]
+#[macro_use(static_init, platform_drivers)]
 extern crate kernel;
 extern crate nrf51;
 
@@ -47,6 +47,7 @@ use capsules::timer::TimerDriver;
 use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use kernel::{Chip, SysTick};
 use kernel::hil::uart::UART;
+use kernel::mpu::MPU;
 use nrf51::pinmux::Pinmux;
 use nrf51::rtc::{RTC, Rtc};
 
@@ -65,17 +66,22 @@ const BUTTON2_PIN: usize = 18;
 const BUTTON3_PIN: usize = 19;
 const BUTTON4_PIN: usize = 20;
 
-unsafe fn load_process() -> &'static mut [Option<kernel::process::Process<'static>>] {
+// Number of concurrent processes this platform supports.
+const NUM_PROCS: usize = 1;
+
+unsafe fn load_process(mpu: kernel::mpu::Capabilities)
+                       -> &'static mut [Option<kernel::process::Process<'static>>] {
     extern "C" {
         /// Beginning of the ROM region containing app images.
         static _sapps: u8;
     }
 
-    const NUM_PROCS: usize = 1;
-
     // how should the kernel respond when a process faults
     const FAULT_RESPONSE: kernel::process::FaultResponse = kernel::process::FaultResponse::Panic;
 
+    // callback queue depth for apps that do not set one in their header
+    const CALLBACK_QUEUE_LEN: usize = 10;
+
     #[link_section = ".app_memory"]
     static mut APP_MEMORY: [u8; 8192] = [0; 8192];
 
@@ -89,7 +95,9 @@ unsafe fn load_process() -> &'static mut [Option<kernel::process::Process<'stati
             kernel::process::Process::create(apps_in_flash_ptr,
                                              app_memory_ptr,
                                              app_memory_size,
-                                             FAULT_RESPONSE);
+                                             &mpu,
+                                             FAULT_RESPONSE,
+                                             CALLBACK_QUEUE_LEN);
 
         if process.is_none() {
             break;
@@ -110,22 +118,18 @@ pub struct Platform {
     console: &'static capsules::console::Console<'static, nrf51::uart::UART>,
     led: &'static capsules::led::LED<'static, nrf51::gpio::GPIOPin>,
     button: &'static capsules::button::Button<'static, nrf51::gpio::GPIOPin>,
+    discovery: kernel::discovery::Discovery,
 }
 
 
-impl kernel::Platform for Platform {
-    fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
-        where F: FnOnce(Option<&kernel::Driver>) -> R
-    {
-        match driver_num {
-            0 => f(Some(self.console)),
-            1 => f(Some(self.gpio)),
-            3 => f(Some(self.timer)),
-            8 => f(Some(self.led)),
-            9 => f(Some(self.button)),
-            _ => f(None),
-        }
-    }
+platform_drivers! {
+    Platform, DRIVERS, platform,
+    0 => ("console", 1, platform.console),
+    1 => ("gpio", 1, platform.gpio),
+    3 => ("timer", 1, platform.timer),
+    8 => ("led", 1, platform.led),
+    9 => ("button", 1, platform.button),
+    kernel::discovery::DRIVER_NUM => ("discovery", 1, &platform.discovery),
 }
 
 #[no_mangle]
@@ -236,6 +240,7 @@ pub unsafe fn reset_handler() {
         console: console,
         led: led,
         button: button,
+        discovery: kernel::discovery::Discovery::new(DRIVERS),
     };
 
     alarm.start();
@@ -243,11 +248,15 @@ pub unsafe fn reset_handler() {
     let mut chip = nrf51::chip::NRF51::new();
     chip.systick().reset();
     chip.systick().enable(true);
+    let process = load_process(chip.mpu().capabilities());
 
+    let scheduler =
+        kernel::scheduler::RoundRobin::new(kernel::scheduler::DEFAULT_TIMESLICE_US);
     kernel::main(&platform,
                  &mut chip,
-                 load_process(),
-                 &kernel::ipc::IPC::new());
+                 &scheduler,
+                 process,
+                 &kernel::ipc::IPC::new(NUM_PROCS));
 
 }
 
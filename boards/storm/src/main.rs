@@ -4,7 +4,7 @@
 
 extern crate capsules;
 extern crate cortexm4;
-#[macro_use(static_init)]
+#[macro_use(static_init, platform_drivers)]
 extern crate kernel;
 extern crate sam4l;
 
@@ -14,7 +14,7 @@ use capsules::timer::TimerDriver;
 use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use capsules::virtual_i2c::{I2CDevice, MuxI2C};
 use capsules::virtual_spi::{VirtualSpiMasterDevice, MuxSpiMaster};
-use kernel::{Chip, Platform};
+use kernel::Chip;
 use kernel::hil;
 use kernel::hil::Controller;
 use kernel::hil::gpio::PinCtl;
@@ -42,17 +42,22 @@ pub mod io;
 static mut spi_read_buf: [u8; 64] = [0; 64];
 static mut spi_write_buf: [u8; 64] = [0; 64];
 
-unsafe fn load_processes() -> &'static mut [Option<kernel::process::Process<'static>>] {
+// Number of concurrent processes this platform supports.
+const NUM_PROCS: usize = 2;
+
+unsafe fn load_processes(mpu: kernel::mpu::Capabilities)
+                         -> &'static mut [Option<kernel::process::Process<'static>>] {
     extern "C" {
         /// Beginning of the ROM region containing app images.
         static _sapps: u8;
     }
 
-    const NUM_PROCS: usize = 2;
-
     // how should the kernel respond when a process faults
     const FAULT_RESPONSE: kernel::process::FaultResponse = kernel::process::FaultResponse::Panic;
 
+    // callback queue depth for apps that do not set one in their header
+    const CALLBACK_QUEUE_LEN: usize = 10;
+
     #[link_section = ".app_memory"]
     static mut APP_MEMORY: [u8; 16384] = [0; 16384];
 
@@ -66,7 +71,9 @@ unsafe fn load_processes() -> &'static mut [Option<kernel::process::Process<'sta
             kernel::process::Process::create(apps_in_flash_ptr,
                                              app_memory_ptr,
                                              app_memory_size,
-                                             FAULT_RESPONSE);
+                                             &mpu,
+                                             FAULT_RESPONSE,
+                                             CALLBACK_QUEUE_LEN);
 
         if process.is_none() {
             break;
@@ -94,30 +101,24 @@ struct Firestorm {
     adc: &'static capsules::adc::ADC<'static, sam4l::adc::Adc>,
     led: &'static capsules::led::LED<'static, sam4l::gpio::GPIOPin>,
     ipc: kernel::ipc::IPC,
+    discovery: kernel::discovery::Discovery,
     rng: &'static capsules::rng::SimpleRng<'static, sam4l::trng::Trng<'static>>,
 }
 
-impl Platform for Firestorm {
-    fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
-        where F: FnOnce(Option<&kernel::Driver>) -> R
-    {
-
-        match driver_num {
-            0 => f(Some(self.console)),
-            1 => f(Some(self.gpio)),
-            2 => f(Some(self.tmp006)),
-            3 => f(Some(self.timer)),
-            4 => f(Some(self.spi)),
-            5 => f(Some(self.nrf51822)),
-            6 => f(Some(self.isl29035)),
-            7 => f(Some(self.adc)),
-            8 => f(Some(self.led)),
-            14 => f(Some(self.rng)),
-
-            0xff => f(Some(&self.ipc)),
-            _ => f(None),
-        }
-    }
+platform_drivers! {
+    Firestorm, DRIVERS, firestorm,
+    0 => ("console", 1, firestorm.console),
+    1 => ("gpio", 1, firestorm.gpio),
+    2 => ("tmp006", 1, firestorm.tmp006),
+    3 => ("timer", 1, firestorm.timer),
+    4 => ("spi", 1, firestorm.spi),
+    5 => ("nrf51822", 1, firestorm.nrf51822),
+    6 => ("isl29035", 1, firestorm.isl29035),
+    7 => ("adc", 1, firestorm.adc),
+    8 => ("led", 1, firestorm.led),
+    14 => ("rng", 1, firestorm.rng),
+    kernel::discovery::DRIVER_NUM => ("discovery", 1, &firestorm.discovery),
+    0xff => ("ipc", 1, &firestorm.ipc),
 }
 
 
@@ -257,8 +258,11 @@ pub unsafe fn reset_handler() {
         16);
     ast.configure(mux_alarm);
 
-    let mux_i2c = static_init!(MuxI2C<'static>, MuxI2C::new(&sam4l::i2c::I2C2), 20);
+    let mux_i2c = static_init!(MuxI2C<'static>, MuxI2C::new(&sam4l::i2c::I2C2), 28);
     sam4l::i2c::I2C2.set_master_client(mux_i2c);
+    let i2c_call = kernel::deferred_call::DeferredCall::register(mux_i2c)
+        .expect("out of deferred calls");
+    mux_i2c.set_deferred_call(i2c_call);
 
     // Configure the TMP006. Device address 0x40
     let tmp006_i2c = static_init!(I2CDevice, I2CDevice::new(mux_i2c, 0x40), 32);
@@ -392,7 +396,8 @@ pub unsafe fn reset_handler() {
         nrf51822: nrf_serialization,
         adc: adc,
         led: led,
-        ipc: kernel::ipc::IPC::new(),
+        ipc: kernel::ipc::IPC::new(NUM_PROCS),
+        discovery: kernel::discovery::Discovery::new(DRIVERS),
         rng: rng_driver,
     };
 
@@ -432,5 +437,8 @@ pub unsafe fn reset_handler() {
     chip.mpu().enable_mpu();
 
 
-    kernel::main(&firestorm, &mut chip, load_processes(), &firestorm.ipc);
+    let processes = load_processes(chip.mpu().capabilities());
+    let scheduler =
+        kernel::scheduler::RoundRobin::new(kernel::scheduler::DEFAULT_TIMESLICE_US);
+    kernel::main(&firestorm, &mut chip, &scheduler, processes, &firestorm.ipc);
 }
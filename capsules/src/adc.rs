@@ -55,6 +55,21 @@ impl<'a, A: AdcSingle + 'a> Driver for ADC<'a, A> {
         }
     }
 
+    fn unsubscribe(&self, subscribe_num: usize, appid: AppId) -> Result<Option<Callback>, ReturnCode> {
+        match subscribe_num {
+            0 => {
+                let callback = self.callback.get();
+                if callback.map_or(false, |cb| cb.app_id() == appid) {
+                    self.callback.set(None);
+                    Ok(callback)
+                } else {
+                    Ok(None)
+                }
+            }
+            _ => Err(ReturnCode::ENOSUPPORT),
+        }
+    }
+
     fn command(&self, command_num: usize, data: usize, _: AppId) -> ReturnCode {
         match command_num {
             // TODO: This should return the number of valid ADC channels.
@@ -0,0 +1,164 @@
+//! Process preemption from an `Alarm` instead of the core SysTick.
+//!
+//! The SysTick keeps the core clock running while it counts, which keeps the
+//! chip out of its deeper sleep modes. `AlarmSysTick` implements the kernel's
+//! `SysTick` interface on an alarm, so the kernel can run tickless: the only
+//! timer running is the alarm, and it is armed only while a process runs
+//! with a timeslice.
+//!
+//! Built on a `VirtualMuxAlarm`, the underlying hardware alarm is armed for
+//! the earliest of the timeslice end and the deadlines of the other virtual
+//! alarms. Boards hand it to the chip in place of the SysTick:
+//!
+//! ```rust
+//! let systick_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!     VirtualMuxAlarm::new(mux_alarm),
+//!     24);
+//! let systick = static_init!(
+//!     AlarmSysTick<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
+//!     AlarmSysTick::new(systick_alarm),
+//!     16);
+//! systick_alarm.set_client(systick);
+//!
+//! let mut chip = sam4l::chip::Sam4l::new().with_systick(&*systick);
+//! ```
+//!
+//! Timeslices are measured in alarm ticks, so their resolution is that of the
+//! alarm, and intervals are clamped to a little over two minutes on a 32 kHz
+//! alarm. Like the virtual alarm mux, it assumes the alarm counter wraps at 32
+//! bits.
+//!
+//! The SysTick reloads each time its counter runs out. The alarm is only
+//! armed once per interval, so the value reloads once and then stays at zero
+//! until the timer is set again. If the alarm interrupt is delayed by more
+//! than an interval, the time beyond it is not counted.
+
+use core::cell::Cell;
+use kernel::SysTick;
+use kernel::hil::time::{self, Alarm, Frequency};
+
+pub struct AlarmSysTick<'a, A: Alarm + 'a> {
+    alarm: &'a A,
+    start: Cell<u32>,
+    interval: Cell<u32>,
+    armed: Cell<bool>,
+    /// Set once the interval ran out, until the timer is set again.
+    overflowed: Cell<bool>,
+}
+
+impl<'a, A: Alarm> AlarmSysTick<'a, A> {
+    pub const fn new(alarm: &'a A) -> AlarmSysTick<'a, A> {
+        AlarmSysTick {
+            alarm: alarm,
+            start: Cell::new(0),
+            interval: Cell::new(0),
+            armed: Cell::new(false),
+            overflowed: Cell::new(false),
+        }
+    }
+
+    fn deadline(&self) -> u32 {
+        self.start.get().wrapping_add(self.interval.get())
+    }
+
+    fn elapsed(&self) -> u32 {
+        self.alarm.now().wrapping_sub(self.start.get())
+    }
+}
+
+/// Converts microseconds to alarm ticks, in pieces to stay within 32 bits.
+fn us_to_tics(us: u32, frequency: u32) -> u32 {
+    (us / 1000000)
+        .saturating_mul(frequency)
+        .saturating_add((us % 1000000) / 1000 * frequency / 1000)
+        .saturating_add((us % 1000) * frequency / 1000000)
+}
+
+/// Converts alarm ticks to microseconds, in pieces to stay within 32 bits.
+fn tics_to_us(tics: u32, frequency: u32) -> u32 {
+    let rest_ms = tics % frequency * 1000;
+    (tics / frequency)
+        .saturating_mul(1000000)
+        .saturating_add(rest_ms / frequency * 1000)
+        .saturating_add(rest_ms % frequency * 1000 / frequency)
+}
+
+impl<'a, A: Alarm> SysTick for AlarmSysTick<'a, A> {
+    fn set_timer(&self, us: u32) {
+        // Keep the end of the interval within half the alarm's range so it
+        // is never mistaken for a time in the past
+        let tics = us_to_tics(us, <A::Frequency>::frequency());
+        self.interval.set(if tics > (!0 >> 1) { !0 >> 1 } else { tics });
+        self.start.set(self.alarm.now());
+        self.overflowed.set(false);
+        if self.armed.get() {
+            self.alarm.set_alarm(self.deadline());
+        }
+    }
+
+    /// Like the SysTick counter, the value reloads when it reaches zero, but
+    /// only once.
+    fn value(&self) -> u32 {
+        let interval = self.interval.get();
+        let elapsed = self.elapsed();
+        let left = if elapsed < interval {
+            interval - elapsed
+        } else if elapsed - interval < interval {
+            interval - (elapsed - interval)
+        } else {
+            0
+        };
+        tics_to_us(left, <A::Frequency>::frequency())
+    }
+
+    fn interval(&self) -> u32 {
+        tics_to_us(self.interval.get(), <A::Frequency>::frequency())
+    }
+
+    fn overflowed(&self) -> bool {
+        // Latch it so the elapsed time wrapping around cannot clear it
+        if self.elapsed() >= self.interval.get() {
+            self.overflowed.set(true);
+        }
+        self.overflowed.get()
+    }
+
+    fn reset(&self) {
+        if self.armed.get() {
+            self.armed.set(false);
+            self.alarm.disable();
+        }
+        self.interval.set(0);
+        self.start.set(self.alarm.now());
+        self.overflowed.set(false);
+    }
+
+    /// Arms the alarm for the end of the interval if `with_interrupt` is set.
+    ///
+    /// Unlike the SysTick, the alarm stays armed when the interrupt is later
+    /// disabled. The kernel does not get preempted by interrupts, and
+    /// re-arming the alarm around every context switch would cost more than
+    /// the occasional early wakeup.
+    fn enable(&self, with_interrupt: bool) {
+        if with_interrupt && !self.armed.get() {
+            self.armed.set(true);
+            self.alarm.set_alarm(self.deadline());
+        }
+    }
+
+    /// The alarm interrupt is serviced by the kernel like any other, so no
+    /// interrupt handler records the overflow.
+    fn overflow_fired() -> bool {
+        false
+    }
+}
+
+impl<'a, A: Alarm> time::Client for AlarmSysTick<'a, A> {
+    fn fired(&self) {
+        // The interrupt already returned control to the kernel, which sees
+        // the timeslice ran out through `overflowed`
+        self.armed.set(false);
+        self.overflowed.set(true);
+    }
+}
@@ -48,6 +48,7 @@ impl<'a, G: hil::gpio::Pin + hil::gpio::PinCtl> Driver for Button<'a, G> {
                             Error::OutOfMemory => ReturnCode::ENOMEM,
                             Error::AddressOutOfBounds => ReturnCode::EINVAL,
                             Error::NoSuchApp => ReturnCode::EINVAL,
+                            Error::InvalidState => ReturnCode::EINVAL,
                         }
                     })
             }
@@ -57,6 +58,19 @@ impl<'a, G: hil::gpio::Pin + hil::gpio::PinCtl> Driver for Button<'a, G> {
         }
     }
 
+    fn unsubscribe(&self, subscribe_num: usize, appid: AppId) -> Result<Option<Callback>, ReturnCode> {
+        match subscribe_num {
+            0 => {
+                Ok(self.callback
+                    .container(appid)
+                    .and_then(|cntr| cntr.enter(|cntr, _| cntr.0.take())))
+            }
+
+            // default
+            _ => Err(ReturnCode::ENOSUPPORT),
+        }
+    }
+
     fn command(&self, command_num: usize, data: usize, appid: AppId) -> ReturnCode {
         let pins = self.pins.as_ref();
         match command_num {
@@ -75,6 +89,7 @@ impl<'a, G: hil::gpio::Pin + hil::gpio::PinCtl> Driver for Button<'a, G> {
                                 Error::OutOfMemory => ReturnCode::ENOMEM,
                                 Error::AddressOutOfBounds => ReturnCode::EINVAL,
                                 Error::NoSuchApp => ReturnCode::EINVAL,
+                                Error::InvalidState => ReturnCode::EINVAL,
                             }
                         })
                 } else {
@@ -98,6 +113,7 @@ impl<'a, G: hil::gpio::Pin + hil::gpio::PinCtl> Driver for Button<'a, G> {
                                 Error::OutOfMemory => ReturnCode::ENOMEM,
                                 Error::AddressOutOfBounds => ReturnCode::EINVAL,
                                 Error::NoSuchApp => ReturnCode::EINVAL,
+                                Error::InvalidState => ReturnCode::EINVAL,
                             }
                         })
                 }
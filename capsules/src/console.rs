@@ -83,6 +83,7 @@ impl<'a, U: UART> Driver for Console<'a, U> {
                             Error::OutOfMemory => ReturnCode::ENOMEM,
                             Error::AddressOutOfBounds => ReturnCode::EINVAL,
                             Error::NoSuchApp => ReturnCode::EINVAL,
+                            Error::InvalidState => ReturnCode::EINVAL,
                         }
                     })
             }
@@ -97,6 +98,7 @@ impl<'a, U: UART> Driver for Console<'a, U> {
                             Error::OutOfMemory => ReturnCode::ENOMEM,
                             Error::AddressOutOfBounds => ReturnCode::EINVAL,
                             Error::NoSuchApp => ReturnCode::EINVAL,
+                            Error::InvalidState => ReturnCode::EINVAL,
                         }
                     })
             }
@@ -152,6 +154,7 @@ impl<'a, U: UART> Driver for Console<'a, U> {
                         Error::OutOfMemory => ReturnCode::ENOMEM,
                         Error::AddressOutOfBounds => ReturnCode::EINVAL,
                         Error::NoSuchApp => ReturnCode::EINVAL,
+                        Error::InvalidState => ReturnCode::EINVAL,
                     }
                 })
             },
@@ -159,6 +162,46 @@ impl<'a, U: UART> Driver for Console<'a, U> {
         }
     }
 
+    fn unsubscribe(&self, subscribe_num: usize, appid: AppId) -> Result<Option<Callback>, ReturnCode> {
+        match subscribe_num {
+            1 /* putstr/write_done */ => {
+                Ok(self.apps
+                    .container(appid)
+                    .and_then(|cntr| cntr.enter(|app, _| app.write_callback.take())))
+            }
+            _ => Err(ReturnCode::ENOSUPPORT),
+        }
+    }
+
+    fn unallow(&self,
+               appid: AppId,
+               allow_num: usize)
+               -> Result<Option<AppSlice<Shared, u8>>, ReturnCode> {
+        let mut previous = None;
+        match allow_num {
+            0 => {
+                self.apps.container(appid).map(|cntr| {
+                    cntr.enter(|app, _| {
+                        previous = app.read_buffer.take();
+                        app.read_idx = 0;
+                    })
+                });
+            }
+            1 => {
+                // An unfinished write is abandoned
+                self.apps.container(appid).map(|cntr| {
+                    cntr.enter(|app, _| {
+                        previous = app.write_buffer.take();
+                        app.write_remaining = 0;
+                        app.pending_write = false;
+                    })
+                });
+            }
+            _ => return Err(ReturnCode::ENOSUPPORT),
+        }
+        Ok(previous)
+    }
+
     fn command(&self, cmd_num: usize, arg1: usize, _: AppId) -> ReturnCode {
         match cmd_num {
             0 /* check if present */ => ReturnCode::SUCCESS,
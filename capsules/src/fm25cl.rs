@@ -371,6 +371,40 @@ impl<'a, S: hil::spi::SpiMasterDevice + 'a> Driver for FM25CLDriver<'a, S> {
         }
     }
 
+    fn unsubscribe(&self, subscribe_num: usize, appid: AppId) -> Result<Option<Callback>, ReturnCode> {
+        match subscribe_num {
+            0 => {
+                Ok(self.app_state.map_or(None, |appst| {
+                    let callback = appst.callback.get();
+                    if callback.map_or(false, |cb| cb.app_id() == appid) {
+                        appst.callback.set(None);
+                        callback
+                    } else {
+                        None
+                    }
+                }))
+            }
+
+            // default
+            _ => Err(ReturnCode::ENOSUPPORT),
+        }
+    }
+
+    fn unallow(&self,
+               appid: AppId,
+               allow_num: usize)
+               -> Result<Option<AppSlice<Shared, u8>>, ReturnCode> {
+        let take_if_owned = |buffer: &TakeCell<AppSlice<Shared, u8>>| {
+            let owned = buffer.map_or(false, |slice| slice.appid() == appid);
+            if owned { buffer.take() } else { None }
+        };
+        match allow_num {
+            0 => Ok(self.app_state.map_or(None, |appst| take_if_owned(&appst.read_buffer))),
+            1 => Ok(self.app_state.map_or(None, |appst| take_if_owned(&appst.write_buffer))),
+            _ => Err(ReturnCode::ENOSUPPORT),
+        }
+    }
+
     fn command(&self, command_num: usize, data: usize, _: AppId) -> ReturnCode {
         match command_num {
             0 /* check if present */ => ReturnCode::SUCCESS,
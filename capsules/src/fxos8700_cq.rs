@@ -260,6 +260,21 @@ impl<'a> Driver for Fxos8700cq<'a> {
         }
     }
 
+    fn unsubscribe(&self, subscribe_num: usize, appid: AppId) -> Result<Option<Callback>, ReturnCode> {
+        match subscribe_num {
+            0 => {
+                let callback = self.callback.get();
+                if callback.map_or(false, |cb| cb.app_id() == appid) {
+                    self.callback.set(None);
+                    Ok(callback)
+                } else {
+                    Ok(None)
+                }
+            }
+            _ => Err(ReturnCode::ENOSUPPORT),
+        }
+    }
+
     fn command(&self, command_num: usize, _arg1: usize, _: AppId) -> ReturnCode {
         match command_num {
             0 /* check if present */ => ReturnCode::SUCCESS,
@@ -96,6 +96,21 @@ impl<'a, G: Pin + PinCtl> Driver for GPIO<'a, G> {
         }
     }
 
+    fn unsubscribe(&self, subscribe_num: usize, appid: AppId) -> Result<Option<Callback>, ReturnCode> {
+        match subscribe_num {
+            0 => {
+                let callback = self.callback.get();
+                if callback.map_or(false, |cb| cb.app_id() == appid) {
+                    self.callback.set(None);
+                    Ok(callback)
+                } else {
+                    Ok(None)
+                }
+            }
+            _ => Err(ReturnCode::ENOSUPPORT),
+        }
+    }
+
     fn command(&self, command_num: usize, data: usize, _: AppId) -> ReturnCode {
         let pins = self.pins.as_ref();
         match command_num {
@@ -249,6 +249,42 @@ impl<'a> Driver for I2CMasterSlaveDriver<'a> {
         }
     }
 
+    fn unsubscribe(&self, subscribe_num: usize, appid: AppId) -> Result<Option<Callback>, ReturnCode> {
+        match subscribe_num {
+            0 => {
+                Ok(self.app_state.map_or(None, |app_state| {
+                    let owned = app_state.callback.map_or(false, |cb| cb.app_id() == appid);
+                    if owned {
+                        app_state.callback.take()
+                    } else {
+                        None
+                    }
+                }))
+            }
+
+            // default
+            _ => Err(ReturnCode::ENOSUPPORT),
+        }
+    }
+
+    fn unallow(&self,
+               appid: AppId,
+               allow_num: usize)
+               -> Result<Option<AppSlice<Shared, u8>>, ReturnCode> {
+        let take_if_owned = |buffer: &TakeCell<AppSlice<Shared, u8>>| {
+            let owned = buffer.map_or(false, |slice| slice.appid() == appid);
+            if owned { buffer.take() } else { None }
+        };
+        self.app_state
+            .map_or(Ok(None), |app_state| match allow_num {
+                0 => Ok(take_if_owned(&app_state.master_tx_buffer)),
+                1 => Ok(take_if_owned(&app_state.master_rx_buffer)),
+                2 => Ok(take_if_owned(&app_state.slave_tx_buffer)),
+                3 => Ok(take_if_owned(&app_state.slave_rx_buffer)),
+                _ => Err(ReturnCode::ENOSUPPORT),
+            })
+    }
+
     fn command(&self, command_num: usize, data: usize, _: AppId) -> ReturnCode {
         match command_num {
             0 /* check if present */ => ReturnCode::SUCCESS,
@@ -3,6 +3,7 @@
 
 extern crate kernel;
 
+pub mod alarm_systick;
 pub mod button;
 pub mod console;
 pub mod fm25cl;
@@ -23,3 +24,4 @@ pub mod lps25hb;
 pub mod tsl2561;
 pub mod fxos8700_cq;
 pub mod rng;
+pub mod shell;
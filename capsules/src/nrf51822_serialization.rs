@@ -143,6 +143,49 @@ impl<'a, U: UARTAdvanced> Driver for Nrf51822Serialization<'a, U> {
         }
     }
 
+    fn unsubscribe(&self, subscribe_type: usize, appid: AppId) -> Result<Option<Callback>, ReturnCode> {
+        match subscribe_type {
+            0 => {
+                Ok(self.app.map_or(None, |app| {
+                    if app.callback.map_or(false, |cb| cb.app_id() == appid) {
+                        app.callback.take()
+                    } else {
+                        None
+                    }
+                }))
+            }
+            _ => Err(ReturnCode::ENOSUPPORT),
+        }
+    }
+
+    fn unallow(&self,
+               appid: AppId,
+               allow_type: usize)
+               -> Result<Option<AppSlice<Shared, u8>>, ReturnCode> {
+        let owned_by_app = |slice: &Option<AppSlice<Shared, u8>>| {
+            slice.as_ref().map_or(false, |slice| slice.appid() == appid)
+        };
+        match allow_type {
+            0 => {
+                Ok(self.app.map_or(None, |app| if owned_by_app(&app.rx_buffer) {
+                    app.rx_recv_so_far = 0;
+                    app.rx_recv_total = 0;
+                    app.rx_buffer.take()
+                } else {
+                    None
+                }))
+            }
+            1 => {
+                Ok(self.app.map_or(None, |app| if owned_by_app(&app.tx_buffer) {
+                    app.tx_buffer.take()
+                } else {
+                    None
+                }))
+            }
+            _ => Err(ReturnCode::ENOSUPPORT),
+        }
+    }
+
     /// Issue a command to the Nrf51822Serialization driver.
     fn command(&self, command_type: usize, _: usize, _: AppId) -> ReturnCode {
 
@@ -137,6 +137,7 @@ impl<'a, RNG: rng::RNG> Driver for SimpleRng<'a, RNG> {
                             Error::OutOfMemory => ReturnCode::ENOMEM,
                             Error::AddressOutOfBounds => ReturnCode::EINVAL,
                             Error::NoSuchApp => ReturnCode::EINVAL,
+                            Error::InvalidState => ReturnCode::EINVAL,
                         }
                     })
             }
@@ -157,6 +158,7 @@ impl<'a, RNG: rng::RNG> Driver for SimpleRng<'a, RNG> {
                             Error::OutOfMemory => ReturnCode::ENOMEM,
                             Error::AddressOutOfBounds => ReturnCode::EINVAL,
                             Error::NoSuchApp => ReturnCode::EINVAL,
+                            Error::InvalidState => ReturnCode::EINVAL,
                         }
                     })
             }
@@ -166,6 +168,38 @@ impl<'a, RNG: rng::RNG> Driver for SimpleRng<'a, RNG> {
         }
     }
 
+    fn unsubscribe(&self, subscribe_num: usize, appid: AppId) -> Result<Option<Callback>, ReturnCode> {
+        match subscribe_num {
+            0 => {
+                Ok(self.apps
+                    .container(appid)
+                    .and_then(|cntr| cntr.enter(|app, _| app.callback.take())))
+            }
+
+            // default
+            _ => Err(ReturnCode::ENOSUPPORT),
+        }
+    }
+
+    fn unallow(&self,
+               appid: AppId,
+               allow_num: usize)
+               -> Result<Option<AppSlice<Shared, u8>>, ReturnCode> {
+        match allow_num {
+            0 => {
+                let mut previous = None;
+                self.apps.container(appid).map(|cntr| {
+                    cntr.enter(|app, _| {
+                        previous = app.buffer.take();
+                        app.remaining = 0;
+                    })
+                });
+                Ok(previous)
+            }
+            _ => Err(ReturnCode::ENOSUPPORT),
+        }
+    }
+
     fn command(&self, command_num: usize, data: usize, appid: AppId) -> ReturnCode {
         match command_num {
             0 => /* Check if exists */ ReturnCode::SUCCESS,
@@ -193,6 +227,7 @@ impl<'a, RNG: rng::RNG> Driver for SimpleRng<'a, RNG> {
                             Error::OutOfMemory => ReturnCode::ENOMEM,
                             Error::AddressOutOfBounds => ReturnCode::EINVAL,
                             Error::NoSuchApp => ReturnCode::EINVAL,
+                            Error::InvalidState => ReturnCode::EINVAL,
                         }
                     })
             }
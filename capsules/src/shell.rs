@@ -0,0 +1,346 @@
+//! A command shell for inspecting and controlling a running board over a
+//! UART.
+//!
+//! The shell reads a line at a time, echoing what is typed, and prints the
+//! output of the command followed by a prompt. It owns the UART it is given,
+//! so boards attach it to a UART the console capsule does not use:
+//!
+//! ```rust
+//! let shell = static_init!(
+//!     capsules::shell::Shell<'static, usart::USART, Imix>,
+//!     capsules::shell::Shell::new(&usart::USART0,
+//!                                 115200,
+//!                                 &mut capsules::shell::RX_BUF,
+//!                                 &mut capsules::shell::LINE_BUF,
+//!                                 &mut capsules::shell::TX_BUF),
+//!     44);
+//! usart::USART0.set_client(shell);
+//! // the platform must be static too
+//! shell.set_platform(imix, &imix.ipc);
+//! shell.initialize();
+//! ```
+//!
+//! Commands that take a slot refer to a process by its index in the process
+//! array, as printed by `list`. Output that does not fit in the transmit
+//! buffer is cut off.
+
+use core::cell::Cell;
+use core::cmp;
+use core::fmt::{self, Write};
+use core::str;
+use kernel::{AppId, container, process, Platform};
+use kernel::common::take_cell::TakeCell;
+use kernel::ipc::IPC;
+use kernel::hil::uart::{self, UART, Client};
+
+pub static mut RX_BUF: [u8; 1] = [0; 1];
+pub static mut LINE_BUF: [u8; 64] = [0; 64];
+pub static mut TX_BUF: [u8; 3072] = [0; 3072];
+
+const PROMPT: &'static str = "tock$ ";
+
+/// Driver numbers probed by `drivers` on platforms that do not list their
+/// drivers.
+const MAX_DRIVER_NUM: usize = 0xff;
+
+const HELP: &'static str = "\
+help           list commands\r\n\
+list           list processes\r\n\
+stats <slot>   memory map, registers and statistics of a process\r\n\
+fault          decoded fault status registers of the last fault\r\n\
+stop <slot>    stop a process\r\n\
+start <slot>   resume a stopped process\r\n\
+restart <slot> reload a process from flash and run it again\r\n\
+drivers        list the drivers the platform provides\r\n\
+grants         grant memory used by each process\r\n";
+
+pub struct Shell<'a, U: UART + 'a, P: Platform + 'a> {
+    uart: &'a U,
+    baud_rate: u32,
+    platform: Cell<Option<&'a P>>,
+    ipc: Cell<Option<&'a IPC>>,
+    rx_buffer: TakeCell<&'static mut [u8]>,
+    line: TakeCell<&'static mut [u8]>,
+    line_len: Cell<usize>,
+    tx_buffer: TakeCell<&'static mut [u8]>,
+}
+
+impl<'a, U: UART, P: Platform> Shell<'a, U, P> {
+    pub fn new(uart: &'a U,
+               baud_rate: u32,
+               rx_buffer: &'static mut [u8],
+               line: &'static mut [u8],
+               tx_buffer: &'static mut [u8])
+               -> Shell<'a, U, P> {
+        Shell {
+            uart: uart,
+            baud_rate: baud_rate,
+            platform: Cell::new(None),
+            ipc: Cell::new(None),
+            rx_buffer: TakeCell::new(rx_buffer),
+            line: TakeCell::new(line),
+            line_len: Cell::new(0),
+            tx_buffer: TakeCell::new(tx_buffer),
+        }
+    }
+
+    /// Sets the platform whose drivers `drivers` lists, and its IPC driver,
+    /// which `stop` and `restart` tell about the process they stop.
+    pub fn set_platform(&self, platform: &'a P, ipc: &'a IPC) {
+        self.platform.set(Some(platform));
+        self.ipc.set(Some(ipc));
+    }
+
+    /// Configures the UART and prints the first prompt.
+    pub fn initialize(&self) {
+        self.uart.init(uart::UARTParams {
+            baud_rate: self.baud_rate,
+            stop_bits: uart::StopBits::One,
+            parity: uart::Parity::None,
+            hw_flow_control: false,
+        });
+        self.transmit(|out| {
+            let _ = out.write_str("\r\n");
+            let _ = out.write_str(PROMPT);
+        });
+    }
+
+    /// Fills the transmit buffer with `f` and sends it. Receiving resumes once
+    /// the transmission completes.
+    fn transmit<F: FnOnce(&mut BufWriter)>(&self, f: F) {
+        self.tx_buffer.take().map(|buffer| {
+            let mut out = BufWriter {
+                buf: buffer,
+                len: 0,
+            };
+            f(&mut out);
+            self.uart.transmit(out.buf, out.len);
+        });
+    }
+
+    fn receive(&self) {
+        self.rx_buffer.take().map(|buffer| self.uart.receive(buffer, 1));
+    }
+
+    fn execute(&self, line: &str, out: &mut BufWriter) {
+        let mut words = line.split(' ').filter(|word| !word.is_empty());
+        let command = match words.next() {
+            Some(command) => command,
+            None => return,
+        };
+        let slot = words.next().and_then(|arg| arg.parse::<usize>().ok());
+
+        match command {
+            "help" => {
+                let _ = out.write_str(HELP);
+            }
+            "list" => self.list(out),
+            "stats" => {
+                let found = slot.and_then(|slot| {
+                    process::with_slot(slot, |process| process.statistics_str(out))
+                });
+                if found.is_none() {
+                    let _ = out.write_str("No such process.\r\n");
+                }
+            }
+            "fault" => process::fault_status_str(out),
+            "stop" => self.control(slot, out, |appid, ipc| process::stop(appid, ipc)),
+            "start" => self.control(slot, out, |appid, _| process::resume(appid)),
+            "restart" => self.control(slot, out, |appid, ipc| process::restart(appid, ipc)),
+            "drivers" => self.drivers(out),
+            "grants" => self.grants(out),
+            _ => {
+                let _ = out.write_fmt(format_args!("Unknown command: {}. Try help.\r\n", command));
+            }
+        }
+    }
+
+    fn list(&self, out: &mut BufWriter) {
+        let _ = out.write_str(" Slot  Name              State           Syscalls  Restarts  \
+                               Stack Used   CPU Time\r\n");
+        process::each(|slot, process| {
+            let state = StateName(process.current_state());
+            let _ = out.write_fmt(format_args!(" {:4}  {:16}  {:14}  {:8}  {:8}  {:5}/{:<5}  \
+                                                {}us\r\n",
+                                               slot,
+                                               process.package_name,
+                                               state,
+                                               process.syscall_count(),
+                                               process.restart_count(),
+                                               process.stack_high_water_mark(),
+                                               process.stack_size(),
+                                               process.cpu_time_us()));
+        });
+    }
+
+    fn control<F>(&self, slot: Option<usize>, out: &mut BufWriter, action: F)
+        where F: FnOnce(AppId, &IPC) -> Result<(), process::Error>
+    {
+        let ipc = match self.ipc.get() {
+            Some(ipc) => ipc,
+            None => {
+                let _ = out.write_str("No platform set.\r\n");
+                return;
+            }
+        };
+        let appid = slot.and_then(process::appid_for);
+        match appid.map_or(Err(process::Error::NoSuchApp), |appid| action(appid, ipc)) {
+            Ok(()) => {
+                let _ = out.write_str("Done.\r\n");
+            }
+            Err(process::Error::InvalidState) => {
+                let state = slot.and_then(|slot| {
+                    process::with_slot(slot, |process| process.current_state())
+                });
+                if let Some(state) = state {
+                    let _ = out.write_fmt(format_args!("Not possible, the process is {}.\r\n",
+                                                       StateName(state)));
+                }
+            }
+            Err(_) => {
+                let _ = out.write_str("No such process.\r\n");
+            }
+        }
+    }
+
+    fn drivers(&self, out: &mut BufWriter) {
+        match self.platform.get() {
+            Some(platform) if !platform.drivers().is_empty() => {
+                for info in platform.drivers() {
+                    let _ = out.write_fmt(format_args!("{:#4x}  {:<12} version {}\r\n",
+                                                       info.driver_num,
+                                                       info.name,
+                                                       info.version));
+                }
+            }
+            Some(platform) => {
+                let _ = out.write_str("Driver numbers:");
+                for driver_num in 0..MAX_DRIVER_NUM + 1 {
+                    if platform.with_driver(driver_num, |driver| driver.is_some()) {
+                        let _ = out.write_fmt(format_args!(" {:#x}", driver_num));
+                    }
+                }
+                let _ = out.write_str("\r\n");
+            }
+            None => {
+                let _ = out.write_str("No platform set.\r\n");
+            }
+        }
+    }
+
+    fn grants(&self, out: &mut BufWriter) {
+        process::each(|slot, process| {
+            let region = process.mem_end() as usize - process.grant_start() as usize;
+            let _ = out.write_fmt(format_args!("{} {}: grant region {} bytes\r\n",
+                                               slot,
+                                               process.package_name,
+                                               region));
+            for container_num in 0..container::count() {
+                let used = process.grant_bytes(container_num);
+                if used > 0 {
+                    let _ = out.write_fmt(format_args!("  Grant {:2}: {:6} bytes\r\n",
+                                                       container_num,
+                                                       used));
+                }
+            }
+        });
+    }
+}
+
+impl<'a, U: UART, P: Platform> Client for Shell<'a, U, P> {
+    fn transmit_complete(&self, buffer: &'static mut [u8], _error: uart::Error) {
+        self.tx_buffer.replace(buffer);
+        self.receive();
+    }
+
+    fn receive_complete(&self, buffer: &'static mut [u8], rx_len: usize, error: uart::Error) {
+        let c = buffer[0];
+        self.rx_buffer.replace(buffer);
+        if rx_len == 0 || error != uart::Error::CommandComplete {
+            self.receive();
+            return;
+        }
+
+        match c {
+            b'\r' | b'\n' => {
+                let len = self.line_len.get();
+                self.line_len.set(0);
+                self.line.map(|line| {
+                    self.transmit(|out| {
+                        let _ = out.write_str("\r\n");
+                        match str::from_utf8(&line[..len]) {
+                            Ok(line) => self.execute(line, out),
+                            Err(_) => {
+                                let _ = out.write_str("Invalid input.\r\n");
+                            }
+                        }
+                        let _ = out.write_str(PROMPT);
+                    });
+                });
+            }
+            // backspace and delete
+            0x08 | 0x7f => {
+                let len = self.line_len.get();
+                if len > 0 {
+                    self.line_len.set(len - 1);
+                    self.transmit(|out| {
+                        let _ = out.write_str("\x08 \x08");
+                    });
+                } else {
+                    self.receive();
+                }
+            }
+            0x20...0x7e => {
+                let len = self.line_len.get();
+                let stored = self.line.map_or(false, |line| if len < line.len() {
+                    line[len] = c;
+                    true
+                } else {
+                    false
+                });
+                if stored {
+                    self.line_len.set(len + 1);
+                    self.transmit(|out| {
+                        let _ = out.write_char(c as char);
+                    });
+                } else {
+                    self.receive();
+                }
+            }
+            _ => self.receive(),
+        }
+    }
+}
+
+/// Formats into a byte buffer, dropping what does not fit.
+struct BufWriter {
+    buf: &'static mut [u8],
+    len: usize,
+}
+
+impl Write for BufWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let count = cmp::min(s.len(), self.buf.len() - self.len);
+        self.buf[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
+
+/// Formats a process state with padding, which the `Debug` output of `State`
+/// does not support.
+struct StateName(process::State);
+
+impl fmt::Display for StateName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.0 {
+            process::State::Running => "Running",
+            process::State::Yielded => "Yielded",
+            process::State::StoppedRunning => "StoppedRunning",
+            process::State::StoppedYielded => "StoppedYielded",
+            process::State::Fault => "Fault",
+            process::State::Terminated => "Terminated",
+        };
+        f.pad(name)
+    }
+}
@@ -144,6 +144,52 @@ impl<'a, S: SpiMasterDevice> Driver for Spi<'a, S> {
             _ => ReturnCode::ENOSUPPORT
         }
     }
+
+    fn unsubscribe(&self, subscribe_num: usize, appid: AppId) -> Result<Option<Callback>, ReturnCode> {
+        match subscribe_num {
+            0 /* read_write */ => {
+                Ok(self.app.map_or(None, |app| {
+                    if app.callback.map_or(false, |cb| cb.app_id() == appid) {
+                        app.callback.take()
+                    } else {
+                        None
+                    }
+                }))
+            }
+            _ => Err(ReturnCode::ENOSUPPORT),
+        }
+    }
+
+    fn unallow(&self,
+               appid: AppId,
+               allow_num: usize)
+               -> Result<Option<AppSlice<Shared, u8>>, ReturnCode> {
+        if self.busy.get() {
+            // The buffers are in use by an ongoing transfer
+            return Err(ReturnCode::EBUSY);
+        }
+        let owned_by_app = |slice: &Option<AppSlice<Shared, u8>>| {
+            slice.as_ref().map_or(false, |slice| slice.appid() == appid)
+        };
+        match allow_num {
+            0 => {
+                Ok(self.app.map_or(None, |app| if owned_by_app(&app.app_read) {
+                    app.app_read.take()
+                } else {
+                    None
+                }))
+            }
+            1 => {
+                Ok(self.app.map_or(None, |app| if owned_by_app(&app.app_write) {
+                    app.app_write.take()
+                } else {
+                    None
+                }))
+            }
+            _ => Err(ReturnCode::ENOSUPPORT),
+        }
+    }
+
     // 0: read/write a single byte (blocking)
     // 1: read/write buffers
     //   - requires write buffer registered with allow
@@ -76,10 +76,17 @@ impl<'a, A: Alarm> Driver for TimerDriver<'a, A> {
                     Error::OutOfMemory => ReturnCode::ENOMEM,
                     Error::AddressOutOfBounds => ReturnCode::EINVAL,
                     Error::NoSuchApp => ReturnCode::EINVAL,
+                    Error::InvalidState => ReturnCode::EINVAL,
                 }
             })
     }
 
+    fn unsubscribe(&self, _: usize, appid: AppId) -> Result<Option<Callback>, ReturnCode> {
+        Ok(self.app_timer
+            .container(appid)
+            .and_then(|timer| timer.enter(|td, _| td.callback.take())))
+    }
+
     fn command(&self, cmd_type: usize, interval: usize, caller_id: AppId) -> ReturnCode {
         // First, convert from milliseconds to native clock frequency
         let interval = (interval as u32) * <A::Frequency>::frequency() / 1000;
@@ -147,6 +154,7 @@ impl<'a, A: Alarm> Driver for TimerDriver<'a, A> {
                     Error::OutOfMemory => ReturnCode::ENOMEM,
                     Error::AddressOutOfBounds => ReturnCode::EINVAL,
                     Error::NoSuchApp => ReturnCode::EINVAL,
+                    Error::InvalidState => ReturnCode::EINVAL,
                 };
                 (e, false)
             });
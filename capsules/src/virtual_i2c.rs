@@ -3,10 +3,22 @@
 //! `MuxI2C` provides shared access to a single I2C Master Bus
 //! for multiple users.
 //! `I2CDevice` provides access to a specific I2C address.
+//!
+//! Once a command completes, the mux starts the next queued command from a
+//! deferred call, so the bus driver's completion callback does not nest into
+//! the next command. Boards register it after creating the mux:
+//!
+//! ```rust,ignore
+//! let call = DeferredCall::register(mux_i2c).expect("out of deferred calls");
+//! mux_i2c.set_deferred_call(call);
+//! ```
+//!
+//! Without a deferred call the next command starts from the callback.
 
 use core::cell::Cell;
 use kernel::common::{List, ListLink, ListNode};
 use kernel::common::take_cell::TakeCell;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::hil::i2c::{self, I2CClient, I2CHwMasterClient, Error};
 
 pub struct MuxI2C<'a> {
@@ -14,6 +26,7 @@ pub struct MuxI2C<'a> {
     devices: List<'a, I2CDevice<'a>>,
     enabled: Cell<usize>,
     inflight: TakeCell<&'a I2CDevice<'a>>,
+    deferred_call: Cell<Option<DeferredCall>>,
 }
 
 impl<'a> I2CHwMasterClient for MuxI2C<'a> {
@@ -21,6 +34,15 @@ impl<'a> I2CHwMasterClient for MuxI2C<'a> {
         self.inflight.take().map(move |device| {
             device.command_complete(buffer, error);
         });
+        match self.deferred_call.get() {
+            Some(call) => call.set(),
+            None => self.do_next_op(),
+        }
+    }
+}
+
+impl<'a> DeferredCallClient for MuxI2C<'a> {
+    fn handle_deferred_call(&self) {
         self.do_next_op();
     }
 }
@@ -32,9 +54,16 @@ impl<'a> MuxI2C<'a> {
             devices: List::new(),
             enabled: Cell::new(0),
             inflight: TakeCell::empty(),
+            deferred_call: Cell::new(None),
         }
     }
 
+    /// Sets the deferred call that starts the next command once one
+    /// completes.
+    pub fn set_deferred_call(&self, call: DeferredCall) {
+        self.deferred_call.set(Some(call));
+    }
+
     fn enable(&self) {
         let enabled = self.enabled.get();
         self.enabled.set(enabled + 1);
@@ -11,26 +11,35 @@ const IQ_SIZE: usize = 100;
 static mut IQ_BUF: [NvicIdx; IQ_SIZE] = [NvicIdx::POWER_CLOCK; IQ_SIZE];
 pub static mut INTERRUPT_QUEUE: Option<RingBuffer<'static, NvicIdx>> = None;
 
-pub struct NRF51(());
+/// The nRF51, with `S` preempting processes. It has no SysTick, so by
+/// default processes are not preempted; `with_systick` adds an
+/// `AlarmSysTick` on RTC1 to preempt them.
+pub struct NRF51<S: kernel::SysTick = ()>((), S);
 
 impl NRF51 {
     pub unsafe fn new() -> NRF51 {
         INTERRUPT_QUEUE = Some(RingBuffer::new(&mut IQ_BUF));
-        NRF51(())
+        NRF51((), ())
     }
 }
 
+impl<S: kernel::SysTick> NRF51<S> {
+    /// Preempts processes with `systick`.
+    pub fn with_systick<T: kernel::SysTick>(self, systick: T) -> NRF51<T> {
+        NRF51(self.0, systick)
+    }
+}
 
-impl kernel::Chip for NRF51 {
+impl<S: kernel::SysTick> kernel::Chip for NRF51<S> {
     type MPU = ();
-    type SysTick = ();
+    type SysTick = S;
 
     fn mpu(&self) -> &Self::MPU {
         &self.0
     }
 
     fn systick(&self) -> &Self::SysTick {
-        &self.0
+        &self.1
     }
 
     fn service_pending_interrupts(&mut self) {
@@ -31,6 +31,7 @@
 // Date: August 5, 2015
 //
 
+use bpm::SleepMode;
 use core::cell::Cell;
 use core::mem;
 use kernel::common::volatile_cell::VolatileCell;
@@ -40,6 +41,7 @@ use kernel::returncode::ReturnCode;
 use nvic;
 use pm::{self, Clock, PBAClock};
 use scif;
+use sleep;
 
 #[repr(C, packed)]
 pub struct AdcRegisters {
@@ -100,6 +102,7 @@ impl Adc {
             regs.scr.set(0x0000001);
             // Disable SEOC interrupt
             regs.idr.set(0x00000001);
+            sleep::release(SleepMode::Sleep1);
             // Read the value from the LCV register.
             // The sample is 16 bits wide
             val = (regs.lcv.get() & 0xffff) as u16;
@@ -178,7 +181,11 @@ impl adc::AdcSingle for Adc {
             cfg |= 0x00000000; // BIPOLAR  =   0 (not bipolar)
             cfg |= 0x00000000; // HWLA     =   0 (no left justify value)
             regs.seqcfg.set(cfg);
-            // Enable end of conversion interrupt
+            // Enable end of conversion interrupt. The conversion needs the
+            // generic clock, which stops in deeper sleep modes.
+            if regs.imr.get() & 1 == 0 {
+                sleep::require(SleepMode::Sleep1);
+            }
             regs.ier.set(1);
             // Initiate conversion
             regs.cr.set(8);
@@ -22,6 +22,30 @@ const BPM_UNLOCK_KEY: u32 = 0xAA000000;
 
 static mut bpm: *mut BpmRegisters = BPM_BASE as *mut BpmRegisters;
 
+/// Sleep modes, from lightest to deepest. `Wait` and `Retention` stop every
+/// clock except the 32 kHz oscillators and need SCR.SLEEPDEEP set as well.
+/// Backup mode is not listed because waking from it is a reset.
+#[derive(Copy, Clone, PartialEq, PartialOrd)]
+pub enum SleepMode {
+    /// CPU clock stopped.
+    Sleep0 = 0,
+    /// CPU and AHB clocks stopped.
+    Sleep1 = 1,
+    /// CPU, AHB, peripheral bus and generic clocks stopped.
+    Sleep2 = 2,
+    /// As Sleep2, and clock sources other than the 32 kHz oscillators stopped.
+    Sleep3 = 3,
+    /// All clocks stopped except the 32 kHz oscillators.
+    Wait = 4,
+    /// As Wait, with the core and SRAM in a lower power retention state.
+    Retention = 5,
+}
+
+// PMCON fields
+const PMCON_BKUP: u32 = 1 << 8;
+const PMCON_RET: u32 = 1 << 9;
+const PMCON_SLEEP_MASK: u32 = 0x3 << 12;
+
 pub enum CK32Source {
     OSC32K = 0,
     RC32K = 1,
@@ -34,6 +58,19 @@ pub unsafe fn set_ck32source(source: CK32Source) {
     (*bpm).control.set(control | (source as u32) << 16);
 }
 
+/// Selects which mode the next `wfi` enters. SCR.SLEEPDEEP must be set for
+/// `Wait` and `Retention` and cleared otherwise.
+pub unsafe fn set_sleep_mode(mode: SleepMode) {
+    let control = (*bpm).control.get() & !(PMCON_SLEEP_MASK | PMCON_RET | PMCON_BKUP);
+    let control = match mode {
+        SleepMode::Wait => control,
+        SleepMode::Retention => control | PMCON_RET,
+        sleep => control | (sleep as u32) << 12,
+    };
+    unlock_register(0x1c); // Control
+    (*bpm).control.set(control);
+}
+
 unsafe fn unlock_register(register_offset: u32) {
     (*bpm).unlock.set(BPM_UNLOCK_KEY | register_offset);
 }
@@ -1,20 +1,27 @@
 use adc;
 use ast;
+use bpm::{self, SleepMode};
 use cortexm4;
 use dma;
 use flashcalw;
 use gpio;
 use i2c;
-use kernel::Chip;
+use kernel::{Chip, SysTick};
 use kernel::common::{RingBuffer, Queue};
+use kernel::support;
 use nvic;
+use pm;
+use sleep;
 use spi;
 use trng;
 use usart;
 
-pub struct Sam4l {
+/// The SAM4L, with `S` preempting processes. By default that is the core
+/// SysTick; `with_systick` swaps in another source, such as an
+/// `AlarmSysTick` on the AST for running tickless.
+pub struct Sam4l<S: SysTick = cortexm4::systick::SysTick> {
     pub mpu: cortexm4::mpu::MPU,
-    pub systick: &'static cortexm4::systick::SysTick,
+    pub systick: S,
 }
 
 const IQ_SIZE: usize = 100;
@@ -57,14 +64,24 @@ impl Sam4l {
 
         Sam4l {
             mpu: cortexm4::mpu::MPU::new(),
-            systick: cortexm4::systick::SysTick::new(),
+            systick: cortexm4::systick::SysTick::new_with_calibration(pm::get_system_frequency()),
         }
     }
 }
 
-impl Chip for Sam4l {
+impl<S: SysTick> Sam4l<S> {
+    /// Preempts processes with `systick` instead.
+    pub fn with_systick<T: SysTick>(self, systick: T) -> Sam4l<T> {
+        Sam4l {
+            mpu: self.mpu,
+            systick: systick,
+        }
+    }
+}
+
+impl<S: SysTick> Chip for Sam4l<S> {
     type MPU = cortexm4::mpu::MPU;
-    type SysTick = cortexm4::systick::SysTick;
+    type SysTick = S;
 
     fn service_pending_interrupts(&mut self) {
         use nvic::NvicIdx::*;
@@ -137,7 +154,20 @@ impl Chip for Sam4l {
         &self.mpu
     }
 
-    fn systick(&self) -> &cortexm4::systick::SysTick {
-        self.systick
+    fn systick(&self) -> &S {
+        &self.systick
+    }
+
+    fn sleep(&self) {
+        let mode = sleep::deepest_mode();
+        let deep = mode >= SleepMode::Wait;
+        unsafe {
+            bpm::set_sleep_mode(mode);
+            cortexm4::scb::set_sleepdeep(deep);
+            support::wfi();
+            if deep {
+                pm::restore_system_clock();
+            }
+        }
     }
 }
@@ -4,8 +4,10 @@ use core::mem;
 
 use kernel::common::take_cell::TakeCell;
 use kernel::common::volatile_cell::VolatileCell;
+use bpm::SleepMode;
 use nvic;
 use pm;
+use sleep;
 
 /// Memory registers for a DMA channel. Section 16.6.1 of the datasheet
 #[repr(C, packed)]
@@ -156,6 +158,8 @@ impl DMAChannel {
             pm::enable_clock(pm::Clock::PBB(pm::PBBClock::PDCA));
         }
         if !self.enabled.get() {
+            // The PDCA runs from the AHB clock
+            sleep::require(SleepMode::Sleep0);
             unsafe {
                 let num_enabled = intrinsics::atomic_xadd(&mut NUM_ENABLED, 1);
                 if num_enabled == 1 {
@@ -174,6 +178,7 @@ impl DMAChannel {
 
     pub fn disable(&self) {
         if self.enabled.get() {
+            sleep::release(SleepMode::Sleep0);
             unsafe {
                 let num_enabled = intrinsics::atomic_xsub(&mut NUM_ENABLED, 1);
                 if num_enabled == 1 {
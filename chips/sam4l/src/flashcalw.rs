@@ -27,8 +27,10 @@ use core::cell::Cell;
 use core::mem;
 use kernel::common::VolatileCell;
 use kernel::common::take_cell::TakeCell;
+use bpm::SleepMode;
 use nvic;
 use pm;
+use sleep;
 
 //  These are the registers of the PicoCache -- a cache dedicated to the flash.
 #[allow(dead_code)]
@@ -240,6 +242,21 @@ impl FLASHCALW {
         }
     }
 
+    // Changes the state, keeping the AHB clock the controller runs from
+    // during sleep while a command is in progress.
+    fn set_state(&self, state: FlashState) {
+        let busy = |state: FlashState| {
+            state != FlashState::Ready && state != FlashState::Unconfigured
+        };
+        let was_busy = busy(self.current_state.get());
+        if !was_busy && busy(state) {
+            sleep::require(SleepMode::Sleep0);
+        } else if was_busy && !busy(state) {
+            sleep::release(SleepMode::Sleep0);
+        }
+        self.current_state.set(state);
+    }
+
     pub fn pico_enabled(&self) -> bool {
         let regs: &PicocacheRegisters = unsafe { mem::transmute(self.cache) };
         regs.status.get() & 0x1 != 0
@@ -278,7 +295,7 @@ impl FLASHCALW {
         if error_status != 0 {
             // reset commands / ready
             self.current_command.set(Command::None);
-            self.current_state.set(FlashState::Ready);
+            self.set_state(FlashState::Ready);
 
             // call command complete with error
             match error_status {
@@ -308,7 +325,7 @@ impl FLASHCALW {
             Command::Write { page } => {
                 match self.current_state.get() {
                     FlashState::Unlocking => {
-                        self.current_state.set(FlashState::Erasing);
+                        self.set_state(FlashState::Erasing);
                         self.flashcalw_erase_page(page, true);
                     }
                     FlashState::Erasing => {
@@ -319,17 +336,17 @@ impl FLASHCALW {
                         self.clear_page_buffer();
                         self.write_to_page_buffer(page as usize * PAGE_SIZE as usize);
 
-                        self.current_state.set(FlashState::Writing);
+                        self.set_state(FlashState::Writing);
                         self.flashcalw_write_page(page);
                     }
                     FlashState::Writing => {
                         // Flush the cache
                         self.invalidate_cache();
-                        self.current_state.set(FlashState::Locking);
+                        self.set_state(FlashState::Locking);
                         self.lock_page_region(page, true);
                     }
                     FlashState::Locking => {
-                        self.current_state.set(FlashState::Ready);
+                        self.set_state(FlashState::Ready);
                         self.current_command.set(Command::None);
                     }
                     _ => {
@@ -341,15 +358,15 @@ impl FLASHCALW {
             Command::Erase { page } => {
                 match self.current_state.get() {
                     FlashState::Unlocking => {
-                        self.current_state.set(FlashState::Erasing);
+                        self.set_state(FlashState::Erasing);
                         self.flashcalw_erase_page(page, true);
                     }
                     FlashState::Erasing => {
-                        self.current_state.set(FlashState::Locking);
+                        self.set_state(FlashState::Locking);
                         self.lock_page_region(page, true);
                     }
                     FlashState::Locking => {
-                        self.current_state.set(FlashState::Ready);
+                        self.set_state(FlashState::Ready);
                         self.current_command.set(Command::None);
                     }
                     _ => {
@@ -358,7 +375,7 @@ impl FLASHCALW {
                 }
             }
             Command::None => {
-                self.current_state.set(FlashState::Ready);
+                self.set_state(FlashState::Ready);
             }
 
         }
@@ -793,7 +810,7 @@ impl FLASHCALW {
         //  So if the bootloader changes, nothing breaks.
         self.enable_picocache(true);
 
-        self.current_state.set(FlashState::Ready);
+        self.set_state(FlashState::Ready);
     }
 
     pub fn get_page_size(&self) -> u32 {
@@ -853,7 +870,7 @@ impl FLASHCALW {
             value.clone_from_slice(&data);
         });
 
-        self.current_state.set(FlashState::Unlocking);
+        self.set_state(FlashState::Unlocking);
         self.current_command.set(Command::Write { page: page_num });
         self.lock_page_region(page_num, false);
         0
@@ -868,7 +885,7 @@ impl FLASHCALW {
             return -1;
         }
 
-        self.current_state.set(FlashState::Unlocking);
+        self.set_state(FlashState::Unlocking);
         self.current_command.set(Command::Erase { page: page_num });
         self.lock_page_region(page_num, false);
         0
@@ -3,9 +3,11 @@ use core::mem;
 use core::ops::{Index, IndexMut};
 use kernel::common::take_cell::TakeCell;
 use kernel::common::volatile_cell::VolatileCell;
+use bpm::SleepMode;
 use kernel::hil;
 use nvic;
 use nvic::NvicIdx::*;
+use sleep;
 
 use self::Pin::*;
 
@@ -281,6 +283,9 @@ pub struct GPIOPin {
     pin_mask: u32,
     client_data: Cell<usize>,
     client: TakeCell<&'static hil::gpio::Client>,
+    /// Whether the enabled interrupt keeps the chip out of modes that stop
+    /// the peripheral bus clock.
+    requires_bus_clock: Cell<bool>,
 }
 
 impl GPIOPin {
@@ -291,6 +296,7 @@ impl GPIOPin {
             pin_mask: 1 << ((pin as u32) % 32),
             client_data: Cell::new(0),
             client: TakeCell::empty(),
+            requires_bus_clock: Cell::new(false),
         }
     }
 
@@ -394,6 +400,14 @@ impl GPIOPin {
     pub fn enable_interrupt(&self) {
         unsafe {
             let port: &mut Registers = mem::transmute(self.port);
+            // Without the glitch filter, edges are detected asynchronously
+            // and wake the chip from every sleep mode. Filtering samples the
+            // pin with the peripheral bus clock.
+            let filtered = port.gfer.val.get() & self.pin_mask != 0;
+            if filtered && !self.requires_bus_clock.get() {
+                sleep::require(SleepMode::Sleep1);
+                self.requires_bus_clock.set(true);
+            }
             nvic::enable(self.nvic);
             port.ier.set.set(self.pin_mask);
         }
@@ -401,6 +415,10 @@ impl GPIOPin {
 
     pub fn disable_interrupt(&self) {
         let port: &mut Registers = unsafe { mem::transmute(self.port) };
+        if self.requires_bus_clock.get() {
+            sleep::release(SleepMode::Sleep1);
+            self.requires_bus_clock.set(false);
+        }
         port.ier.clear.set(self.pin_mask);
         if port.ier.val.get() == 0 {
             unsafe {
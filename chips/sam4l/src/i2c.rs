@@ -267,6 +267,8 @@ impl I2CHw {
                         let buf = match self.dma.take() {
                             Some(dma) => {
                                 let b = dma.abort_xfer();
+                                // Stop holding the chip out of deep sleep
+                                dma.disable();
                                 self.dma.replace(dma);
                                 b
                             }
@@ -744,6 +746,7 @@ impl hil::i2c::I2CMaster for I2CHw {
     /// This disables the entire I2C peripheral
     fn disable(&self) {
         let regs: &mut Registers = unsafe { mem::transmute(self.registers) };
+        self.dma.map(|dma| dma.disable());
         write_volatile(&mut regs.control, 0x1 << 1);
         unsafe {
             pm::disable_clock(self.master_clock);
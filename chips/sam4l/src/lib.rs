@@ -21,6 +21,7 @@ pub mod pm;
 pub mod gpio;
 pub mod usart;
 pub mod scif;
+pub mod sleep;
 pub mod adc;
 pub mod flashcalw;
 pub mod wdt;
@@ -1,4 +1,5 @@
 use kernel::common::volatile_cell::VolatileCell;
+use sleep;
 
 #[repr(C, packed)]
 struct PmRegisters {
@@ -240,6 +241,7 @@ pub enum PBDClock {
 }
 
 /// Which source the system clock should be generated from.
+#[derive(Copy,Clone)]
 pub enum SystemClockSource {
     /// Use the internal digital frequency locked loop (DFLL) sourced from
     /// the internal RC32K clock. Note this typically requires calibration
@@ -271,6 +273,7 @@ static mut SCIF: *mut ScifRegisters = SCIF_BASE as *mut ScifRegisters;
 static mut FLASHCALW: *mut FlashcalwRegisters = FLASHCALW_BASE as *mut FlashcalwRegisters;
 
 static mut SYSTEM_FREQUENCY: VolatileCell<u32> = VolatileCell::new(0);
+static mut SYSTEM_CLOCK_SOURCE: Option<SystemClockSource> = None;
 
 unsafe fn unlock(register_offset: u32) {
     (*PM).unlock.set(0xAA000000 | register_offset);
@@ -459,6 +462,7 @@ unsafe fn configure_external_oscillator_pll() {
 
 pub unsafe fn setup_system_clock(clock_source: SystemClockSource, frequency: u32) {
     SYSTEM_FREQUENCY.set(frequency);
+    SYSTEM_CLOCK_SOURCE = Some(clock_source);
 
     match clock_source {
         SystemClockSource::DfllRc32k => {
@@ -475,10 +479,42 @@ pub unsafe fn setup_system_clock(clock_source: SystemClockSource, frequency: u32
     }
 }
 
+/// Waits for the system clock source to start again after a deep sleep mode,
+/// which stops every clock except the 32 kHz oscillators, and selects it as the
+/// main clock.
+pub unsafe fn restore_system_clock() {
+    match SYSTEM_CLOCK_SOURCE {
+        Some(SystemClockSource::DfllRc32k) => {
+            // Wait for DFLL0LOCKF
+            while (*SCIF).pclksr.get() & (1 << 2) == 0 {}
+            select_main_clock(MainClock::DFLL);
+        }
+
+        Some(SystemClockSource::ExternalOscillator) => {
+            // Wait for OSC0RDY
+            while (*SCIF).pclksr.get() & (1 << 0) == 0 {}
+            select_main_clock(MainClock::OSC0);
+        }
+
+        Some(SystemClockSource::ExternalOscillatorPll) => {
+            // Wait for PLL0LOCK
+            while (*SCIF).pclksr.get() & (1 << 6) == 0 {}
+            select_main_clock(MainClock::PLL);
+        }
+
+        None => {}
+    }
+}
+
 pub unsafe fn get_system_frequency() -> u32 {
     SYSTEM_FREQUENCY.get()
 }
 
+/// Whether the last reset was caused by the watchdog (WDT bit of RCAUSE).
+pub fn watchdog_caused_reset() -> bool {
+    unsafe { (*PM).rcause.get() & (1 << 3) != 0 }
+}
+
 macro_rules! mask_clock {
     ($module:ident: $field:ident | $mask:expr) => ({
         unlock(concat_idents!($module, _MASK_OFFSET));
@@ -490,7 +526,10 @@ macro_rules! mask_clock {
 pub unsafe fn enable_clock(clock: Clock) {
     match clock {
         Clock::HSB(v) => mask_clock!(HSB: hsbmask | 1 << (v as u32)),
-        Clock::PBA(v) => mask_clock!(PBA: pbamask | 1 << (v as u32)),
+        Clock::PBA(v) => {
+            mask_clock!(PBA: pbamask | 1 << (v as u32));
+            sleep::clock_enabled(v);
+        }
         Clock::PBB(v) => mask_clock!(PBB: pbbmask | 1 << (v as u32)),
         Clock::PBD(v) => mask_clock!(PBD: pbdmask | 1 << (v as u32)),
     }
@@ -499,7 +538,10 @@ pub unsafe fn enable_clock(clock: Clock) {
 pub unsafe fn disable_clock(clock: Clock) {
     match clock {
         Clock::HSB(v) => mask_clock!(HSB: hsbmask | !(1 << (v as u32))),
-        Clock::PBA(v) => mask_clock!(PBA: pbamask | !(1 << (v as u32))),
+        Clock::PBA(v) => {
+            mask_clock!(PBA: pbamask | !(1 << (v as u32)));
+            sleep::clock_disabled(v);
+        }
         Clock::PBB(v) => mask_clock!(PBB: pbbmask | !(1 << (v as u32))),
         Clock::PBD(v) => mask_clock!(PBD: pbdmask | !(1 << (v as u32))),
     }
@@ -0,0 +1,90 @@
+//! Selection of the deepest sleep mode the running peripherals allow.
+//!
+//! A peripheral that needs clocks to keep running while the kernel sleeps
+//! calls `require` with the deepest mode it still works in when it starts an
+//! operation, and `release` with the same mode when the operation ends. For
+//! example, a USART that is receiving needs its peripheral bus clock, so it
+//! requires `Sleep1` until the reception completes.
+//!
+//! Peripherals on peripheral bus A that do not register their operations
+//! this way could be in the middle of one whenever their clock is enabled.
+//! While any of their clocks is, the chip sleeps no deeper than `Sleep1`,
+//! which keeps the bus clocked.
+//!
+//! The AST does not need to register: it runs from the 32 kHz oscillator and
+//! its alarm is enabled as a wake source, so it keeps time and wakes the chip
+//! in every mode used here.
+
+use bpm::SleepMode;
+use core::intrinsics;
+use pm::PBAClock;
+
+const NUM_MODES: usize = 6;
+
+/// How many operations need each mode, indexed by `SleepMode`.
+static mut REQUIREMENTS: [usize; NUM_MODES] = [0; NUM_MODES];
+
+const MODES: [SleepMode; NUM_MODES] = [SleepMode::Sleep0,
+                                       SleepMode::Sleep1,
+                                       SleepMode::Sleep2,
+                                       SleepMode::Sleep3,
+                                       SleepMode::Wait,
+                                       SleepMode::Retention];
+
+/// Peripheral bus A clocks of the peripherals that `require` the modes their
+/// operations need.
+const REGISTERED_CLOCKS: u32 = 1 << (PBAClock::SPI as u32) | 1 << (PBAClock::USART0 as u32) |
+                               1 << (PBAClock::USART1 as u32) |
+                               1 << (PBAClock::USART2 as u32) |
+                               1 << (PBAClock::USART3 as u32) |
+                               1 << (PBAClock::ADCIFE as u32) |
+                               1 << (PBAClock::TRNG as u32);
+
+/// Enabled peripheral bus A clocks of peripherals that do not register
+/// their operations.
+static mut UNREGISTERED_CLOCKS: u32 = 0;
+
+/// Keeps the chip from sleeping deeper than `mode` until a matching
+/// `release`.
+pub fn require(mode: SleepMode) {
+    unsafe {
+        intrinsics::atomic_xadd(&mut REQUIREMENTS[mode as usize], 1);
+    }
+}
+
+/// Withdraws an earlier `require` of `mode`.
+pub fn release(mode: SleepMode) {
+    unsafe {
+        intrinsics::atomic_xsub(&mut REQUIREMENTS[mode as usize], 1);
+    }
+}
+
+/// Records that `clock` was enabled. Called by `pm::enable_clock`.
+pub fn clock_enabled(clock: PBAClock) {
+    let mask = 1 << (clock as u32);
+    if mask & REGISTERED_CLOCKS == 0 {
+        unsafe {
+            intrinsics::atomic_or(&mut UNREGISTERED_CLOCKS, mask);
+        }
+    }
+}
+
+/// Records that `clock` was disabled. Called by `pm::disable_clock`.
+pub fn clock_disabled(clock: PBAClock) {
+    unsafe {
+        intrinsics::atomic_and(&mut UNREGISTERED_CLOCKS, !(1 << (clock as u32)));
+    }
+}
+
+/// The deepest mode no running operation rules out.
+pub fn deepest_mode() -> SleepMode {
+    unsafe {
+        let required = REQUIREMENTS.iter().position(|&count| count > 0);
+        let deepest = required.map_or(SleepMode::Retention, |index| MODES[index]);
+        if UNREGISTERED_CLOCKS != 0 && deepest > SleepMode::Sleep1 {
+            SleepMode::Sleep1
+        } else {
+            deepest
+        }
+    }
+}
@@ -13,7 +13,9 @@ use kernel::hil::spi;
 use kernel::hil::spi::ClockPhase;
 use kernel::hil::spi::ClockPolarity;
 use kernel::hil::spi::SpiMasterClient;
+use bpm::SleepMode;
 use pm;
+use sleep;
 
 
 /// Implementation of DMA-based SPI master communication for
@@ -304,6 +306,8 @@ impl spi::SpiMaster for Spi {
 
         // We will have at least a write transfer in progress
         self.transfers_in_progress.set(1);
+        // The transfer is clocked from the peripheral bus
+        sleep::require(SleepMode::Sleep1);
 
         let read_len = match read_buffer {
             Some(ref buf) => buf.len(),
@@ -419,6 +423,8 @@ impl DMAClient for Spi {
         self.transfers_in_progress.set(self.transfers_in_progress.get() - 1);
 
         if self.transfers_in_progress.get() == 0 {
+            sleep::release(SleepMode::Sleep1);
+
             let txbuf = self.dma_write.map_or(None, |dma| {
                 let buf = dma.abort_xfer();
                 dma.disable();
@@ -1,10 +1,12 @@
 //! TRNG driver for the SAM4L
 
+use bpm::SleepMode;
 use core::cell::Cell;
 use kernel::common::volatile_cell::VolatileCell;
 use kernel::hil::rng::{self, Continue};
 use nvic;
 use pm;
+use sleep;
 
 #[repr(C)]
 struct Registers {
@@ -23,6 +25,7 @@ const BASE_ADDRESS: *const Registers = 0x40068000 as *const Registers;
 pub struct Trng<'a> {
     regs: *const Registers,
     client: Cell<Option<&'a rng::Client>>,
+    running: Cell<bool>,
 }
 
 pub static mut TRNG: Trng<'static> = Trng::new();
@@ -33,6 +36,7 @@ impl<'a> Trng<'a> {
         Trng {
             regs: BASE_ADDRESS,
             client: Cell::new(None),
+            running: Cell::new(false),
         }
     }
 
@@ -49,6 +53,8 @@ impl<'a> Trng<'a> {
             if let Continue::Done = result {
                 // disable controller
                 regs.control.set(KEY | 0);
+                self.running.set(false);
+                sleep::release(SleepMode::Sleep1);
                 unsafe {
                     pm::disable_clock(pm::Clock::PBA(pm::PBAClock::TRNG));
                 }
@@ -81,6 +87,10 @@ impl<'a, 'b> Iterator for TrngIter<'a, 'b> {
 impl<'a> rng::RNG for Trng<'a> {
     fn get(&self) {
         let regs = unsafe { &*self.regs };
+        if !self.running.get() {
+            self.running.set(true);
+            sleep::require(SleepMode::Sleep1);
+        }
         unsafe {
             pm::enable_clock(pm::Clock::PBA(pm::PBAClock::TRNG));
             nvic::enable(nvic::NvicIdx::TRNG);
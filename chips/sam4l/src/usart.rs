@@ -7,8 +7,10 @@ use kernel::common::volatile_cell::VolatileCell;
 // other modules
 use kernel::hil;
 // local modules
+use bpm::SleepMode;
 use nvic;
 use pm;
+use sleep;
 
 // Register map for SAM4L USART
 #[repr(C, packed)]
@@ -154,6 +156,32 @@ impl USART {
         self.tx_dma.replace(tx_dma);
     }
 
+    /// Changes the RX state, keeping the peripheral bus clock running during
+    /// sleep while a reception is in progress.
+    fn set_rx_state(&self, state: USARTStateRX) {
+        let was_idle = self.usart_rx_state.get() == USARTStateRX::Idle;
+        let is_idle = state == USARTStateRX::Idle;
+        if was_idle && !is_idle {
+            sleep::require(SleepMode::Sleep1);
+        } else if !was_idle && is_idle {
+            sleep::release(SleepMode::Sleep1);
+        }
+        self.usart_rx_state.set(state);
+    }
+
+    /// Changes the TX state, keeping the peripheral bus clock running during
+    /// sleep until the last byte is sent.
+    fn set_tx_state(&self, state: USARTStateTX) {
+        let was_idle = self.usart_tx_state.get() == USARTStateTX::Idle;
+        let is_idle = state == USARTStateTX::Idle;
+        if was_idle && !is_idle {
+            sleep::require(SleepMode::Sleep1);
+        } else if !was_idle && is_idle {
+            sleep::release(SleepMode::Sleep1);
+        }
+        self.usart_tx_state.set(state);
+    }
+
     pub fn enable_rx(&self) {
         let regs: &mut USARTRegisters = unsafe { mem::transmute(self.registers) };
         let cr_val = 0x00000000 | (1 << 4); // RXEN
@@ -171,7 +199,7 @@ impl USART {
         let cr_val = 0x00000000 | (1 << 5); // RXDIS
         regs.cr.set(cr_val);
 
-        self.usart_rx_state.set(USARTStateRX::Idle);
+        self.set_rx_state(USARTStateRX::Idle);
     }
 
     pub fn disable_tx(&self) {
@@ -179,14 +207,14 @@ impl USART {
         let cr_val = 0x00000000 | (1 << 7); // TXDIS
         regs.cr.set(cr_val);
 
-        self.usart_tx_state.set(USARTStateTX::Idle);
+        self.set_tx_state(USARTStateTX::Idle);
     }
 
     pub fn abort_rx(&self, error: hil::uart::Error) {
         if self.usart_rx_state.get() == USARTStateRX::DMA_Receiving {
             self.disable_rx();
             self.disable_rx_interrupts();
-            self.usart_rx_state.set(USARTStateRX::Idle);
+            self.set_rx_state(USARTStateRX::Idle);
 
             // get buffer
             let mut length = 0;
@@ -216,7 +244,7 @@ impl USART {
         if self.usart_tx_state.get() == USARTStateTX::DMA_Transmitting {
             self.disable_tx();
             self.disable_tx_interrupts();
-            self.usart_tx_state.set(USARTStateTX::Idle);
+            self.set_tx_state(USARTStateTX::Idle);
 
             // get buffer
             let mut length = 0;
@@ -435,7 +463,7 @@ impl dma::DMAClient for USART {
                     // disable RX and RX interrupts
                     self.disable_rx();
                     self.disable_rx_interrupts();
-                    self.usart_rx_state.set(USARTStateRX::Idle);
+                    self.set_rx_state(USARTStateRX::Idle);
 
                     // get buffer
                     let buffer = self.rx_dma.map_or(None, |rx_dma| {
@@ -464,7 +492,7 @@ impl dma::DMAClient for USART {
                     // TX transfer was completed
 
                     // note that the DMA has finished but TX cannot be disabled yet
-                    self.usart_tx_state.set(USARTStateTX::Transfer_Completing);
+                    self.set_tx_state(USARTStateTX::Transfer_Completing);
 
                     // get buffer
                     let buffer = self.tx_dma.map_or(None, |tx_dma| {
@@ -501,7 +529,7 @@ impl dma::DMAClient for USART {
                     });
 
                     // note that the DMA has finished but TX cannot be disabled yet
-                    self.usart_tx_state.set(USARTStateTX::Transfer_Completing);
+                    self.set_tx_state(USARTStateTX::Transfer_Completing);
 
                     // get buffer
                     let txbuf = self.tx_dma.map_or(None, |dma| {
@@ -593,7 +621,7 @@ impl hil::uart::UART for USART {
 
         // enable TX
         self.enable_tx();
-        self.usart_tx_state.set(USARTStateTX::DMA_Transmitting);
+        self.set_tx_state(USARTStateTX::DMA_Transmitting);
 
         // set up dma transfer and start transmission
         self.tx_dma.map(move |dma| {
@@ -616,7 +644,7 @@ impl hil::uart::UART for USART {
         // enable RX
         self.enable_rx();
         self.enable_rx_error_interrupts();
-        self.usart_rx_state.set(USARTStateRX::DMA_Receiving);
+        self.set_rx_state(USARTStateRX::DMA_Receiving);
 
         // set up dma transfer and start reception
         self.rx_dma.map(move |dma| {
@@ -638,7 +666,7 @@ impl hil::uart::UARTAdvanced for USART {
         // enable RX
         self.enable_rx();
         self.enable_rx_error_interrupts();
-        self.usart_rx_state.set(USARTStateRX::DMA_Receiving);
+        self.set_rx_state(USARTStateRX::DMA_Receiving);
 
         // set up dma transfer and start reception
         self.rx_dma.map(move |dma| {
@@ -659,7 +687,7 @@ impl hil::uart::UARTAdvanced for USART {
         // enable RX
         self.enable_rx();
         self.enable_rx_error_interrupts();
-        self.usart_rx_state.set(USARTStateRX::DMA_Receiving);
+        self.set_rx_state(USARTStateRX::DMA_Receiving);
 
         // set up dma transfer and start reception
         self.rx_dma.map(move |dma| {
@@ -121,4 +121,8 @@ impl hil::watchdog::Watchdog for Wdt {
     fn tickle(&self) {
         self.tickle();
     }
+
+    fn caused_reset(&self) -> bool {
+        pm::watchdog_caused_reset()
+    }
 }
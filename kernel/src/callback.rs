@@ -1,19 +1,39 @@
 use core::nonzero::NonZero;
 use process;
 
-#[derive(Clone,Copy)]
+/// Identifies a process by its slot in the process array and the generation
+/// of the process in that slot.
+///
+/// A slot is reused when its process is unloaded, and a process that restarts
+/// starts over in the same slot. Either way it gets a new generation, so an
+/// `AppId` kept from before, for example in a `Callback` or an `AppSlice`, no
+/// longer matches and the kernel refuses to act on it.
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub struct AppId {
     idx: usize,
+    generation: usize,
 }
 
 impl AppId {
-    pub fn new(idx: usize) -> AppId {
-        AppId { idx: idx }
+    pub fn new(idx: usize, generation: usize) -> AppId {
+        AppId {
+            idx: idx,
+            generation: generation,
+        }
     }
 
     pub fn idx(&self) -> usize {
         self.idx
     }
+
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Whether the process this id was handed out for is still in its slot.
+    pub fn is_live(&self) -> bool {
+        process::is_live(*self)
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -32,6 +52,8 @@ impl Callback {
         }
     }
 
+    /// Queues the callback for the app. Returns false if the app's queue is
+    /// full or the app this callback was subscribed by is gone.
     pub fn schedule(&mut self, r0: usize, r1: usize, r2: usize) -> bool {
         process::schedule(process::FunctionCall {
                               r0: r0,
@@ -46,4 +68,9 @@ impl Callback {
     pub fn app_id(&self) -> AppId {
         self.app_id
     }
+
+    /// The address of the function in the app this callback calls.
+    pub fn fn_ptr(&self) -> usize {
+        *self.fn_ptr as usize
+    }
 }
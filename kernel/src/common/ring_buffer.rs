@@ -15,6 +15,15 @@ impl<'a, T: Copy> RingBuffer<'a, T> {
             ring: ring,
         }
     }
+
+    /// Returns the element `index` places from the head without removing it.
+    pub fn get(&self, index: usize) -> Option<T> {
+        if index < queue::Queue::len(self) {
+            Some(self.ring[(self.head + index) % self.ring.len()])
+        } else {
+            None
+        }
+    }
 }
 
 impl<'a, T: Copy> queue::Queue<T> for RingBuffer<'a, T> {
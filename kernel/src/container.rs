@@ -2,18 +2,25 @@ use callback::AppId;
 use core::marker::PhantomData;
 use core::mem::size_of;
 use core::ops::{Deref, DerefMut};
-use core::ptr::{read_volatile, write_volatile, Unique};
+use core::ptr::{self, read_volatile, write_volatile, Unique};
+use core::slice;
 use process::{self, Error};
 
 pub static mut CONTAINER_COUNTER: usize = 0;
 
+/// The number of containers created so far. Containers are numbered from 0.
+pub fn count() -> usize {
+    unsafe { read_volatile(&CONTAINER_COUNTER) }
+}
+
 pub struct Container<T: Default> {
     container_num: usize,
     ptr: PhantomData<T>,
 }
 
 pub struct AppliedContainer<T> {
-    appid: usize,
+    appid: AppId,
+    container_num: usize,
     container: *mut T,
     _phantom: PhantomData<T>,
 }
@@ -24,8 +31,9 @@ impl<T> AppliedContainer<T> {
               R: Copy
     {
         let mut allocator = Allocator {
-            app: unsafe { process::PROCS[self.appid].as_mut().unwrap() },
+            app: unsafe { process::PROCS[self.appid.idx()].as_mut().unwrap() },
             app_id: self.appid,
+            container_num: self.container_num,
         };
         let mut root = unsafe { Owned::new(self.container, self.appid) };
         fun(&mut root, &mut allocator)
@@ -34,16 +42,17 @@ impl<T> AppliedContainer<T> {
 
 pub struct Allocator<'a> {
     app: &'a mut process::Process<'a>,
-    app_id: usize,
+    app_id: AppId,
+    container_num: usize,
 }
 
 pub struct Owned<T: ?Sized> {
     data: Unique<T>,
-    app_id: usize,
+    app_id: AppId,
 }
 
 impl<T: ?Sized> Owned<T> {
-    pub unsafe fn new(data: *mut T, app_id: usize) -> Owned<T> {
+    pub unsafe fn new(data: *mut T, app_id: AppId) -> Owned<T> {
         Owned {
             data: Unique::new(data),
             app_id: app_id,
@@ -51,16 +60,19 @@ impl<T: ?Sized> Owned<T> {
     }
 
     pub fn appid(&self) -> AppId {
-        AppId::new(self.app_id)
+        self.app_id
     }
 }
 
 impl<T: ?Sized> Drop for Owned<T> {
     fn drop(&mut self) {
+        // The memory of a process that is gone was reclaimed with it
+        if !self.app_id.is_live() {
+            return;
+        }
         unsafe {
-            let app_id = self.app_id;
             let data = self.data.get_mut() as *mut T as *mut u8;
-            match process::PROCS[app_id] {
+            match process::PROCS[self.app_id.idx()] {
                 None => {}
                 Some(ref mut app) => {
                     app.free(data);
@@ -87,13 +99,31 @@ impl<'a> Allocator<'a> {
     pub fn alloc<T>(&mut self, data: T) -> Result<Owned<T>, Error> {
         unsafe {
             let app_id = self.app_id;
-            self.app.alloc(size_of::<T>()).map_or(Err(Error::OutOfMemory), |arr| {
+            self.app.alloc(size_of::<T>(), self.container_num).map_or(Err(Error::OutOfMemory), |arr| {
                 let mut owned = Owned::new(arr.as_mut_ptr() as *mut T, app_id);
                 *owned = data;
                 Ok(owned)
             })
         }
     }
+
+    /// Allocates a slice of `len` elements, each set to the result of
+    /// calling `init`.
+    pub fn alloc_slice<T, F>(&mut self, len: usize, init: F) -> Result<Owned<[T]>, Error>
+        where F: Fn() -> T
+    {
+        unsafe {
+            let app_id = self.app_id;
+            let size = size_of::<T>() * len;
+            self.app.alloc(size, self.container_num).map_or(Err(Error::OutOfMemory), |arr| {
+                let data = arr.as_mut_ptr() as *mut T;
+                for i in 0..len {
+                    ptr::write(data.offset(i as isize), init());
+                }
+                Ok(Owned::new(slice::from_raw_parts_mut(data, len) as *mut [T], app_id))
+            })
+        }
+    }
 }
 
 impl<T: Default> Container<T> {
@@ -107,16 +137,19 @@ impl<T: Default> Container<T> {
     }
 
     pub fn container(&self, appid: AppId) -> Option<AppliedContainer<T>> {
+        if !appid.is_live() {
+            return None;
+        }
         unsafe {
-            let app_id = appid.idx();
-            match process::PROCS[app_id] {
+            match process::PROCS[appid.idx()] {
                 Some(ref mut app) => {
                     let cntr = app.container_for::<T>(self.container_num);
                     if cntr.is_null() {
                         None
                     } else {
                         Some(AppliedContainer {
-                            appid: app_id,
+                            appid: appid,
+                            container_num: self.container_num,
                             container: cntr,
                             _phantom: PhantomData,
                         })
@@ -127,20 +160,26 @@ impl<T: Default> Container<T> {
         }
     }
 
+    /// Runs `fun` on `appid`'s state in this container, allocating it first if
+    /// needed. Fails with `NoSuchApp` if the process is gone or `appid` is
+    /// stale.
     pub fn enter<F, R>(&self, appid: AppId, fun: F) -> Result<R, Error>
         where F: FnOnce(&mut Owned<T>, &mut Allocator) -> R,
               R: Copy
     {
+        if !appid.is_live() {
+            return Err(Error::NoSuchApp);
+        }
         unsafe {
-            let app_id = appid.idx();
-            match process::PROCS[app_id] {
+            match process::PROCS[appid.idx()] {
                 Some(ref mut app) => {
                     app.container_for_or_alloc::<T>(self.container_num)
                         .map_or(Err(Error::OutOfMemory), move |root_ptr| {
-                            let mut root = Owned::new(root_ptr, app_id);
+                            let mut root = Owned::new(root_ptr, appid);
                             let mut allocator = Allocator {
                                 app: app,
-                                app_id: app_id,
+                                app_id: appid,
+                                container_num: self.container_num,
                             };
                             let res = fun(&mut root, &mut allocator);
                             Ok(res)
@@ -155,17 +194,34 @@ impl<T: Default> Container<T> {
         where F: Fn(&mut Owned<T>)
     {
         unsafe {
-            let itr = process::PROCS.iter_mut().filter_map(|p| p.as_mut());
-            for (app_id, app) in itr.enumerate() {
+            let itr = process::PROCS
+                .iter_mut()
+                .enumerate()
+                .filter_map(|(idx, p)| p.as_mut().map(|app| (idx, app)));
+            for (idx, app) in itr {
                 let root_ptr = app.container_for::<T>(self.container_num);
                 if !root_ptr.is_null() {
-                    let mut root = Owned::new(root_ptr, app_id);
+                    let mut root = Owned::new(root_ptr, AppId::new(idx, app.generation()));
                     fun(&mut root);
                 }
             }
         }
     }
 
+    /// The number of bytes of `appid`'s grant region used by this container,
+    /// including its root and everything allocated through it.
+    pub fn grant_bytes(&self, appid: AppId) -> usize {
+        if !appid.is_live() {
+            return 0;
+        }
+        unsafe {
+            match process::PROCS.get(appid.idx()) {
+                Some(&Some(ref app)) => app.grant_bytes(self.container_num),
+                _ => 0,
+            }
+        }
+    }
+
     pub fn iter(&self) -> Iter<T> {
         unsafe {
             Iter {
@@ -190,7 +246,7 @@ impl<'a, T: Default> Iterator for Iter<'a, T> {
         while self.index < self.len {
             let idx = self.index;
             self.index += 1;
-            let res = self.container.container(AppId::new(idx));
+            let res = process::appid_for(idx).and_then(|appid| self.container.container(appid));
             if res.is_some() {
                 return res;
             }
@@ -0,0 +1,91 @@
+//! Deferred calls let capsules run code soon, but outside the current call
+//! stack.
+//!
+//! A capsule that starts its next operation from a hardware completion
+//! callback re-enters the driver that called it, and the calls can nest
+//! arbitrarily deep. With a deferred call the capsule marks a handler pending
+//! and returns. The kernel main loop runs pending handlers after servicing
+//! interrupts and before it schedules processes.
+//!
+//! ```rust,ignore
+//! impl<'a> DeferredCallClient for MuxI2C<'a> {
+//!     fn handle_deferred_call(&self) {
+//!         self.do_next_op();
+//!     }
+//! }
+//!
+//! // in the board's reset handler
+//! let call = DeferredCall::register(mux).expect("out of deferred calls");
+//! mux.set_deferred_call(call);
+//!
+//! // in the capsule, from any context including interrupt handlers
+//! self.deferred_call.get().map(|call| call.set());
+//! ```
+
+use common::VolatileCell;
+use support;
+
+/// The number of handlers that can be registered.
+pub const MAX_DEFERRED_CALLS: usize = 32;
+
+pub trait DeferredCallClient {
+    fn handle_deferred_call(&self);
+}
+
+/// Bit `i` is set while the handler in slot `i` is pending.
+static mut PENDING: VolatileCell<u32> = VolatileCell::new(0);
+
+static mut HANDLERS: [Option<&'static DeferredCallClient>; MAX_DEFERRED_CALLS] =
+    [None; MAX_DEFERRED_CALLS];
+
+static mut REGISTERED: usize = 0;
+
+/// A registered handler.
+#[derive(Copy, Clone)]
+pub struct DeferredCall {
+    idx: usize,
+}
+
+impl DeferredCall {
+    /// Registers `client` to be called whenever the returned `DeferredCall`
+    /// is set. Returns `None` if `MAX_DEFERRED_CALLS` handlers are already
+    /// registered.
+    pub unsafe fn register(client: &'static DeferredCallClient) -> Option<DeferredCall> {
+        if REGISTERED == MAX_DEFERRED_CALLS {
+            return None;
+        }
+        let idx = REGISTERED;
+        HANDLERS[idx] = Some(client);
+        REGISTERED += 1;
+        Some(DeferredCall { idx: idx })
+    }
+
+    /// Marks the handler pending. Setting it again before it runs has no
+    /// further effect: the handler runs once.
+    pub fn set(&self) {
+        unsafe {
+            support::atomic(|| PENDING.set(PENDING.get() | (1 << self.idx)));
+        }
+    }
+}
+
+/// Whether a handler is pending.
+pub fn has_tasks() -> bool {
+    unsafe { PENDING.get() != 0 }
+}
+
+/// Runs the pending handlers, in the order they were registered. Handlers set
+/// while this runs, including by the handlers themselves, run on the next
+/// call.
+pub unsafe fn service_deferred_calls() {
+    let pending = support::atomic(|| {
+        let pending = PENDING.get();
+        PENDING.set(0);
+        pending
+    });
+    for (idx, handler) in HANDLERS.iter().enumerate() {
+        if pending & (1 << idx) != 0 {
+            handler.map(|handler| handler.handle_deferred_call());
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Lets apps discover the drivers a platform serves.
+//!
+//! Driver numbers differ between boards, so an app that runs on several
+//! boards looks drivers up by name. Boards serve `Discovery` as driver
+//! `DRIVER_NUM`, built from the list `platform_drivers!` defines:
+//!
+//! ```rust,ignore
+//! platform_drivers! {
+//!     Hail, DRIVERS, hail,
+//!     0 => ("console", 1, hail.console),
+//!     kernel::discovery::DRIVER_NUM => ("discovery", 1, &hail.discovery),
+//! }
+//!
+//! let discovery = unsafe { Discovery::new(DRIVERS) };
+//! ```
+//!
+//! The system call interface is:
+//!
+//!   * command 0 returns the number of drivers.
+//!   * command 1 returns the driver number of the driver at the index passed
+//!   as argument, in the order the platform lists them.
+//!   * command 2 returns the version of the driver whose number is passed as
+//!   argument.
+//!   * command 3 copies the name of the driver whose number is passed as
+//!   argument into the buffer allowed with minor number 0, truncating it to
+//!   the buffer, and returns the length of the whole name.
+//!
+//! Drivers the header of the app does not permit are left out, as if the
+//! platform did not serve them.
+
+use ::{AppId, AppSlice, Container, Driver, Shared};
+use core::cmp;
+use platform::DriverInfo;
+use process;
+use returncode::ReturnCode;
+
+/// The driver number boards serve `Discovery` as.
+pub const DRIVER_NUM: usize = 0xfe;
+
+pub struct Discovery {
+    drivers: &'static [DriverInfo],
+    buffers: Container<Option<AppSlice<Shared, u8>>>,
+}
+
+impl Discovery {
+    pub unsafe fn new(drivers: &'static [DriverInfo]) -> Discovery {
+        Discovery {
+            drivers: drivers,
+            buffers: Container::create(),
+        }
+    }
+
+    /// The driver numbered `driver_num`, if `appid` may use it.
+    fn find(&self, appid: AppId, driver_num: usize) -> Option<&'static DriverInfo> {
+        if !process::may_use_driver(appid, driver_num) {
+            return None;
+        }
+        self.drivers.iter().find(|info| info.driver_num == driver_num)
+    }
+}
+
+impl Driver for Discovery {
+    fn command(&self, minor_num: usize, arg: usize, appid: AppId) -> ReturnCode {
+        let visible = |info: &&DriverInfo| process::may_use_driver(appid, info.driver_num);
+        match minor_num {
+            0 /* Number of drivers */ => {
+                ReturnCode::SuccessWithValue { value: self.drivers.iter().filter(&visible).count() }
+            }
+            1 /* Driver number at an index */ => {
+                self.drivers
+                    .iter()
+                    .filter(&visible)
+                    .nth(arg)
+                    .map_or(ReturnCode::EINVAL,
+                            |info| ReturnCode::SuccessWithValue { value: info.driver_num })
+            }
+            2 /* Version */ => {
+                self.find(appid, arg).map_or(ReturnCode::ENODEVICE,
+                                             |info| ReturnCode::SuccessWithValue {
+                                                 value: info.version,
+                                             })
+            }
+            3 /* Name */ => {
+                let name = match self.find(appid, arg) {
+                    Some(info) => info.name.as_bytes(),
+                    None => return ReturnCode::ENODEVICE,
+                };
+                self.buffers
+                    .enter(appid, |buffer, _| match **buffer {
+                        Some(ref mut slice) => {
+                            let len = cmp::min(slice.len(), name.len());
+                            slice.as_mut()[..len].copy_from_slice(&name[..len]);
+                            ReturnCode::SuccessWithValue { value: name.len() }
+                        }
+                        None => ReturnCode::ERESERVE,
+                    })
+                    .unwrap_or(ReturnCode::ENOMEM)
+            }
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+
+    fn allow(&self, appid: AppId, minor_num: usize, slice: AppSlice<Shared, u8>) -> ReturnCode {
+        match minor_num {
+            0 => {
+                self.buffers
+                    .enter(appid, |buffer, _| {
+                        **buffer = Some(slice);
+                        ReturnCode::SUCCESS
+                    })
+                    .unwrap_or(ReturnCode::ENOMEM)
+            }
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+
+    fn unallow(&self,
+               appid: AppId,
+               minor_num: usize)
+               -> Result<Option<AppSlice<Shared, u8>>, ReturnCode> {
+        match minor_num {
+            0 => {
+                let mut previous = None;
+                let _ = self.buffers.enter(appid, |buffer, _| {
+                    previous = buffer.take();
+                });
+                Ok(previous)
+            }
+            _ => Err(ReturnCode::ENOSUPPORT),
+        }
+    }
+}
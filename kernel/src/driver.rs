@@ -33,6 +33,14 @@
 //! command can also return more information, like the number of supported
 //! devices (useful for things like the number of LEDs).
 //!
+//! ## Revoking callbacks and buffers
+//!
+//! An application revokes a callback by passing a null callback to
+//! `subscribe`, and revokes a buffer by passing a null or zero-length buffer to
+//! `allow`. The scheduler turns these into calls to `unsubscribe` and `unallow`
+//! on the driver, which hands back the callback or buffer it held so the
+//! kernel can report it to the application.
+//!
 //! # The `yield` System-call
 //!
 //! While drivers do not handle the `yield` system call, it is important to
@@ -103,4 +111,28 @@ pub trait Driver {
     fn allow(&self, app: ::AppId, minor_num: usize, slice: ::AppSlice<::Shared, u8>) -> ReturnCode {
         ReturnCode::ENOSUPPORT
     }
+
+    /// `unsubscribe` is called when an application passes a null callback to
+    /// `subscribe`.
+    ///
+    /// The driver must stop calling the application's callback for
+    /// `minor_num` and hand it back, or return `Ok(None)` if the application
+    /// had not subscribed.
+    #[allow(unused_variables)]
+    fn unsubscribe(&self, minor_num: usize, app: ::AppId) -> Result<Option<::Callback>, ReturnCode> {
+        Err(ReturnCode::ENOSUPPORT)
+    }
+
+    /// `unallow` is called when an application passes a null or zero-length
+    /// buffer to `allow`.
+    ///
+    /// The driver must stop using the application's buffer for `minor_num` and
+    /// hand it back, or return `Ok(None)` if it held no buffer.
+    #[allow(unused_variables)]
+    fn unallow(&self,
+               app: ::AppId,
+               minor_num: usize)
+               -> Result<Option<::AppSlice<::Shared, u8>>, ReturnCode> {
+        Err(ReturnCode::ENOSUPPORT)
+    }
 }
@@ -9,4 +9,8 @@ pub trait Watchdog {
     /// Service the watchdog to let the hardware know the application
     /// is still executing.
     fn tickle(&self);
+
+    /// Whether the last reset of the chip was caused by the watchdog timing
+    /// out.
+    fn caused_reset(&self) -> bool;
 }
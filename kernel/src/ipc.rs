@@ -1,71 +1,421 @@
-//! Provide capsule driver for controlling buttons on a board.  This allows for much more cross
-//! platform controlling of buttons without having to know which of the GPIO pins exposed across
-//! the syscall interface are buttons.
+//! Inter-process communication between apps.
+//!
+//! An app offers a service by subscribing a service callback, and may register
+//! the service under a name. Clients find services by name, notify them, and
+//! share buffers with them. Processes are identified by their slot plus one,
+//! so process id 0 is never used.
+//!
+//! Apps can also exchange small messages without sharing memory. The kernel
+//! copies a message from the sender's send buffer into the receiver's receive
+//! buffer, so neither buffer needs to be aligned or use up an MPU region. A
+//! service answers a request with a reply, which goes back to the client that
+//! sent it. A receive buffer holds one message at a time: it fills up when a
+//! message arrives and is ready again once the app allows a receive buffer.
+//!
+//! Each app keeps a table of its peers, allocated in its grant region the
+//! first time it is needed. Boards set how many peers the table holds, which
+//! is usually the number of process slots:
+//!
+//! ```rust
+//! ipc: kernel::ipc::IPC::new(NUM_PROCS),
+//! ```
+//!
+//! The system call interface is:
+//!
+//!   * subscribe 0 sets the service callback. Subscribe `id` sets the callback
+//!   for notifications from service `id`.
+//!   * command `id` notifies process `id`, as a service if the argument is 0
+//!   and as a client otherwise.
+//!   * allow 0 looks up the service named by the buffer and returns its id.
+//!   Registered names are searched first, then package names. Apps that
+//!   faulted or exited are not found.
+//!   * allow `id` shares the buffer with process `id`.
+//!   * allow `REGISTER` registers the app's service under the name in the
+//!   buffer, replacing any name it registered before. Fails with `EBUSY` if
+//!   another app registered the name or has it as its package name.
+//!   * command `UNREGISTER` removes the app's service name.
+//!   * command `SERVICE_COUNT` returns the number of registered services.
+//!   * command `SERVICE_AT` returns the id of the registered service at the
+//!   index passed as argument, in process id order.
+//!   * command `SERVICE_NAME` copies the name of service id passed as argument
+//!   into the buffer allowed with `NAME_BUFFER`, truncating it to the buffer,
+//!   and returns the length of the whole name.
+//!   * subscribe `MESSAGE` sets the callback for messages. It gets the id of
+//!   the sender, the length of the message and whether it is a request, a
+//!   reply or a notice that the service the app waits on stopped.
+//!   * allow `RECEIVE_BUFFER` and `SEND_BUFFER` set the buffers messages are
+//!   received into and sent from. The whole send buffer is the message.
+//!   * command `SEND` sends a request to process id passed as argument.
+//!   Sending again replaces the request the app waits on a reply for.
+//!   * command `REPLY` replies to the client id passed as argument, which must
+//!   be waiting on a reply from the app.
+//!
+//! `SEND` and `REPLY` fail with `EOFF` if the receiver faulted or exited,
+//! `EBUSY` if its receive buffer is full, `ERESERVE` if it has no receive
+//! buffer or message callback and `ESIZE` if the message does not fit.
 
 use ::{AppId, AppSlice, Container, Callback, Driver, Shared};
 use ::process;
+use core::cell::Cell;
+use core::{cmp, slice};
+use container::Owned;
 use returncode::ReturnCode;
 
+/// Allow and command numbers of the service registry. They are far above
+/// any process id.
+const REGISTRY_BASE: usize = 0xff00;
+const REGISTER: usize = REGISTRY_BASE;
+const NAME_BUFFER: usize = REGISTRY_BASE + 1;
+const UNREGISTER: usize = REGISTRY_BASE;
+const SERVICE_COUNT: usize = REGISTRY_BASE + 1;
+const SERVICE_AT: usize = REGISTRY_BASE + 2;
+const SERVICE_NAME: usize = REGISTRY_BASE + 3;
+
+/// Subscribe, allow and command numbers of message passing.
+const MESSAGE_BASE: usize = REGISTRY_BASE + 0x10;
+const MESSAGE: usize = MESSAGE_BASE;
+const RECEIVE_BUFFER: usize = MESSAGE_BASE;
+const SEND_BUFFER: usize = MESSAGE_BASE + 1;
+const SEND: usize = MESSAGE_BASE;
+const REPLY: usize = MESSAGE_BASE + 1;
+
+/// What the message callback is told it received.
+const MESSAGE_REQUEST: usize = 0;
+const MESSAGE_REPLY: usize = 1;
+const MESSAGE_STOPPED: usize = 2;
+
+/// The longest name a service can register under.
+pub const MAX_NAME_LEN: usize = 32;
+
+/// The longest message the kernel copies.
+pub const MAX_MESSAGE_LEN: usize = 256;
+
+/// What an app keeps for one of its peers.
+struct Peer {
+    /// Memory the app shares with the peer.
+    shared_memory: Option<AppSlice<Shared, u8>>,
+    /// Called when the peer notifies the app as a service.
+    client_callback: Option<Callback>,
+}
+
+impl Default for Peer {
+    fn default() -> Peer {
+        Peer {
+            shared_memory: None,
+            client_callback: None,
+        }
+    }
+}
+
 struct IPCData {
-    shared_memory: [Option<AppSlice<Shared, u8>>; 8],
-    client_callbacks: [Option<Callback>; 8],
+    /// Indexed by the peer's slot. Allocated the first time it is needed.
+    peers: Option<Owned<[Peer]>>,
     callback: Option<Callback>,
+    /// The name the service is registered under, if `name_len` is not 0.
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+    /// Where `SERVICE_NAME` copies names to.
+    name_buffer: Option<AppSlice<Shared, u8>>,
+    message_callback: Option<Callback>,
+    receive_buffer: Option<AppSlice<Shared, u8>>,
+    /// Set while the receive buffer holds a message the app has not taken.
+    receive_full: bool,
+    send_buffer: Option<AppSlice<Shared, u8>>,
+    /// The service the app waits on a reply from.
+    awaiting: Option<AppId>,
 }
 
 impl Default for IPCData {
     fn default() -> IPCData {
         IPCData {
-            shared_memory: [None, None, None, None, None, None, None, None],
-            client_callbacks: [None, None, None, None, None, None, None, None],
+            peers: None,
             callback: None,
+            name: [0; MAX_NAME_LEN],
+            name_len: 0,
+            name_buffer: None,
+            message_callback: None,
+            receive_buffer: None,
+            receive_full: false,
+            send_buffer: None,
+            awaiting: None,
         }
     }
 }
 
+impl IPCData {
+    fn name(&self) -> &[u8] {
+        &self.name[..self.name_len]
+    }
+}
+
 pub struct IPC {
     data: Container<IPCData>,
+    num_peers: usize,
 }
 
 impl IPC {
-    pub unsafe fn new() -> IPC {
-        IPC { data: Container::create() }
+    /// Creates the IPC driver. Apps can communicate with processes in the
+    /// first `num_peers` slots.
+    pub unsafe fn new(num_peers: usize) -> IPC {
+        assert!(num_peers < REGISTRY_BASE);
+        IPC {
+            data: Container::create(),
+            num_peers: num_peers,
+        }
+    }
+
+    /// Runs `f` on what `appid` keeps for the peer in slot `peer_idx`.
+    fn with_peer<F, R>(&self, appid: AppId, peer_idx: usize, f: F) -> Result<R, ReturnCode>
+        where F: FnOnce(&mut Peer) -> R,
+              R: Copy
+    {
+        if peer_idx >= self.num_peers {
+            return Err(ReturnCode::EINVAL);
+        }
+        let num_peers = self.num_peers;
+        self.data
+            .enter(appid, |data, allocator| {
+                if data.peers.is_none() {
+                    data.peers = allocator.alloc_slice(num_peers, Peer::default).ok();
+                }
+                match data.peers {
+                    Some(ref mut peers) => Ok(f(&mut peers[peer_idx])),
+                    None => Err(ReturnCode::ENOMEM),
+                }
+            })
+            .unwrap_or(Err(ReturnCode::ENOMEM))
+    }
+
+    /// Like `with_peer`, but returns `None` instead of allocating the peer
+    /// table if `appid` does not have one yet.
+    fn with_existing_peer<F, R>(&self, appid: AppId, peer_idx: usize, f: F) -> Option<R>
+        where F: FnOnce(&mut Peer) -> R,
+              R: Copy
+    {
+        self.data.container(appid).and_then(|container| {
+            container.enter(|data, _| match data.peers {
+                Some(ref mut peers) if peer_idx < peers.len() => Some(f(&mut peers[peer_idx])),
+                _ => None,
+            })
+        })
+    }
+
+    /// The slot of the app whose service is registered under `name`.
+    /// Services of apps that faulted or exited are skipped.
+    fn find_service(&self, name: &[u8]) -> Option<usize> {
+        let found = Cell::new(None);
+        self.data.each(|data| {
+            if found.get().is_none() && data.name_len > 0 && !is_gone(data.appid().idx()) &&
+               data.name() == name {
+                found.set(Some(data.appid().idx()));
+            }
+        });
+        found.get()
+    }
+
+    /// The slots of the apps with a registered service, in order. Services
+    /// of apps that faulted or exited are skipped.
+    fn each_service<F: Fn(usize)>(&self, f: F) {
+        self.data.each(|data| {
+            if data.name_len > 0 && !is_gone(data.appid().idx()) {
+                f(data.appid().idx());
+            }
+        });
+    }
+
+    /// Registers `appid`'s service under the name in `slice`. The name must
+    /// not be taken by another app's registration or package name, so apps
+    /// cannot intercept clients looking for another app.
+    fn register(&self, appid: AppId, slice: &AppSlice<Shared, u8>) -> ReturnCode {
+        let name = slice.as_ref();
+        if name.len() == 0 || name.len() > MAX_NAME_LEN {
+            return ReturnCode::EINVAL;
+        }
+        let taken = [self.find_service(name), find_package(name)];
+        if taken.iter().any(|owner| owner.map_or(false, |idx| idx != appid.idx())) {
+            return ReturnCode::EBUSY;
+        }
+        self.data
+            .enter(appid, |data, _| {
+                data.name[..name.len()].copy_from_slice(name);
+                data.name_len = name.len();
+                ReturnCode::SUCCESS
+            })
+            .unwrap_or(ReturnCode::ENOMEM)
+    }
+
+    /// Copies the name of the service in slot `idx` into `appid`'s name
+    /// buffer.
+    fn copy_service_name(&self, appid: AppId, idx: usize) -> ReturnCode {
+        let service = match process::appid_for(idx).and_then(|id| self.data.container(id)) {
+            Some(service) => service,
+            None => return ReturnCode::EINVAL,
+        };
+        let (name, name_len) = service.enter(|data, _| (data.name, data.name_len));
+        if name_len == 0 {
+            return ReturnCode::EINVAL;
+        }
+        self.data
+            .enter(appid, |data, _| match data.name_buffer {
+                Some(ref mut buffer) => {
+                    let len = cmp::min(buffer.len(), name_len);
+                    buffer.as_mut()[..len].copy_from_slice(&name[..len]);
+                    ReturnCode::SuccessWithValue { value: name_len }
+                }
+                None => ReturnCode::ERESERVE,
+            })
+            .unwrap_or(ReturnCode::ENOMEM)
+    }
+
+    /// The app with process id `id`, if it can receive messages.
+    fn message_target(&self, id: usize) -> Result<AppId, ReturnCode> {
+        let appid = match id.checked_sub(1).and_then(process::appid_for) {
+            Some(appid) => appid,
+            None => return Err(ReturnCode::EINVAL),
+        };
+        if is_gone(appid.idx()) {
+            Err(ReturnCode::EOFF)
+        } else {
+            Ok(appid)
+        }
+    }
+
+    /// Copies the message in `from`'s send buffer into `to`'s receive buffer
+    /// and queues `to`'s message callback.
+    fn deliver(&self, from: AppId, to: AppId, kind: usize) -> ReturnCode {
+        if from.idx() == to.idx() {
+            return ReturnCode::EINVAL;
+        }
+        let message = self.data
+            .enter(from, |data, _| {
+                data.send_buffer.as_ref().map(|buffer| (buffer.as_ref().as_ptr(), buffer.len()))
+            })
+            .unwrap_or(None);
+        let message = match message {
+            Some((_, len)) if len > MAX_MESSAGE_LEN => return ReturnCode::ESIZE,
+            // The sender is the calling process, so its buffer stays valid
+            Some((ptr, len)) => unsafe { slice::from_raw_parts(ptr, len) },
+            None => return ReturnCode::ERESERVE,
+        };
+        self.data
+            .enter(to, |data, _| {
+                let mut callback = match data.message_callback {
+                    Some(callback) => callback,
+                    None => return ReturnCode::ERESERVE,
+                };
+                if data.receive_full {
+                    return ReturnCode::EBUSY;
+                }
+                match data.receive_buffer {
+                    Some(ref mut buffer) if buffer.len() >= message.len() => {
+                        buffer.as_mut()[..message.len()].copy_from_slice(message);
+                    }
+                    Some(_) => return ReturnCode::ESIZE,
+                    None => return ReturnCode::ERESERVE,
+                }
+                if !callback.schedule(from.idx() + 1, message.len(), kind) {
+                    return ReturnCode::EBUSY;
+                }
+                data.receive_full = true;
+                ReturnCode::SUCCESS
+            })
+            .unwrap_or(ReturnCode::ENOMEM)
+    }
+
+    fn send(&self, appid: AppId, id: usize) -> ReturnCode {
+        let service = match self.message_target(id) {
+            Ok(service) => service,
+            Err(err) => return err,
+        };
+        let res = self.deliver(appid, service, MESSAGE_REQUEST);
+        if res == ReturnCode::SUCCESS {
+            let _ = self.data.enter(appid, |data, _| data.awaiting = Some(service));
+        }
+        res
+    }
+
+    fn reply(&self, appid: AppId, id: usize) -> ReturnCode {
+        let client = match self.message_target(id) {
+            Ok(client) => client,
+            Err(err) => return err,
+        };
+        let awaiting = self.data
+            .container(client)
+            .and_then(|container| container.enter(|data, _| data.awaiting));
+        if awaiting != Some(appid) {
+            return ReturnCode::EINVAL;
+        }
+        let res = self.deliver(appid, client, MESSAGE_REPLY);
+        if res == ReturnCode::SUCCESS {
+            let _ = self.data.enter(client, |data, _| data.awaiting = None);
+        }
+        res
+    }
+
+    /// Tells the apps waiting on a reply from `appid` that it will not come.
+    /// The kernel calls this when a process faults or exits, and when it is
+    /// stopped, terminated, restarted or unloaded.
+    pub fn process_stopped(&self, appid: AppId) {
+        self.data.each(|data| {
+            if data.awaiting == Some(appid) {
+                data.awaiting = None;
+                data.message_callback.map(|mut callback| {
+                    callback.schedule(appid.idx() + 1, 0, MESSAGE_STOPPED)
+                });
+            }
+        });
     }
 
     pub unsafe fn schedule_callback(&self,
                                     appid: AppId,
                                     otherapp: AppId,
                                     cb_type: process::IPCType) {
-        self.data
-            .enter(appid, |mydata, _| {
-                let callback = match cb_type {
-                    process::IPCType::Service => mydata.callback,
-                    process::IPCType::Client => {
-                        *mydata.client_callbacks.get(otherapp.idx()).unwrap_or(&None)
+        let callback = match cb_type {
+            process::IPCType::Service => {
+                self.data.enter(appid, |mydata, _| mydata.callback).unwrap_or(None)
+            }
+            process::IPCType::Client => {
+                self.with_existing_peer(appid, otherapp.idx(), |peer| peer.client_callback)
+                    .unwrap_or(None)
+            }
+        };
+        callback.map(|mut callback| {
+            let shared = self.with_existing_peer(otherapp, appid.idx(), |peer| {
+                match peer.shared_memory {
+                    Some(ref slice) => {
+                        slice.expose_to(appid);
+                        callback.schedule(otherapp.idx() + 1, slice.len(), slice.ptr() as usize);
+                        true
                     }
-                };
-                callback.map(|mut callback| {
-                        self.data
-                            .enter(otherapp, |otherdata, _| {
-                                if appid.idx() >= otherdata.shared_memory.len() {
-                                    return;
-                                }
-                                match otherdata.shared_memory[appid.idx()] {
-                                    Some(ref slice) => {
-                                        slice.expose_to(appid);
-                                        callback.schedule(otherapp.idx() + 1,
-                                                          slice.len(),
-                                                          slice.ptr() as usize);
-                                    }
-                                    None => {
-                                        callback.schedule(appid.idx() + 1, 0, 0);
-                                    }
-                                }
-                            })
-                            .unwrap_or(());
-                    })
-                    .unwrap_or(());
-            })
-            .unwrap_or(());
+                    None => false,
+                }
+            });
+            if shared != Some(true) {
+                callback.schedule(appid.idx() + 1, 0, 0);
+            }
+        });
+    }
+}
+
+/// The slot of the app with package name `name`. Apps that faulted or
+/// exited are skipped.
+fn find_package(name: &[u8]) -> Option<usize> {
+    let procs = unsafe { &process::PROCS };
+    procs.iter()
+        .enumerate()
+        .position(|(idx, process)| match process {
+            &Some(ref p) => p.package_name.as_bytes() == name && !is_gone(idx),
+            &None => false,
+        })
+}
+
+/// Whether the process in slot `idx` faulted or exited.
+fn is_gone(idx: usize) -> bool {
+    let procs = unsafe { &process::PROCS };
+    match procs[idx].as_ref().map(|p| p.current_state()) {
+        Some(process::State::Fault) |
+        Some(process::State::Terminated) => true,
+        _ => false,
     }
 }
 
@@ -78,22 +428,62 @@ impl Driver for IPC {
                     ReturnCode::SUCCESS
                 }).unwrap_or(ReturnCode::EBUSY)
             }
+            MESSAGE => {
+                self.data.enter(callback.app_id(), |data, _| {
+                    data.message_callback = Some(callback);
+                    ReturnCode::SUCCESS
+                }).unwrap_or(ReturnCode::ENOMEM)
+            }
             svc_id /* Client callback */ => {
-                if svc_id - 1 >= 8 {
-                    ReturnCode::EINVAL /* Maximum of 8 IPC's exceeded */
-                } else {
-                    self.data.enter(callback.app_id(), |data, _| {
-                        data.client_callbacks[svc_id - 1] = Some(callback);
-                        ReturnCode::SUCCESS
-                    }).unwrap_or(ReturnCode::EBUSY)
-                }
+                self.with_peer(callback.app_id(), svc_id - 1, |peer| {
+                    peer.client_callback = Some(callback);
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err)
             }
         }
     }
 
     fn command(&self, target_id: usize, client_or_svc: usize, appid: AppId) -> ReturnCode {
+        match target_id {
+            UNREGISTER => {
+                return self.data
+                    .enter(appid, |data, _| {
+                        data.name_len = 0;
+                        ReturnCode::SUCCESS
+                    })
+                    .unwrap_or(ReturnCode::ENOMEM);
+            }
+            SERVICE_COUNT => {
+                let count = Cell::new(0);
+                self.each_service(|_| count.set(count.get() + 1));
+                return ReturnCode::SuccessWithValue { value: count.get() };
+            }
+            SERVICE_AT => {
+                let index = Cell::new(0);
+                let found = Cell::new(None);
+                self.each_service(|idx| {
+                    if index.get() == client_or_svc {
+                        found.set(Some(idx));
+                    }
+                    index.set(index.get() + 1);
+                });
+                return found.get()
+                    .map_or(ReturnCode::EINVAL,
+                            |idx| ReturnCode::SuccessWithValue { value: idx + 1 });
+            }
+            SERVICE_NAME => {
+                if client_or_svc == 0 {
+                    return ReturnCode::EINVAL;
+                }
+                return self.copy_service_name(appid, client_or_svc - 1);
+            }
+            SEND => return self.send(appid, client_or_svc),
+            REPLY => return self.reply(appid, client_or_svc),
+            _ => {}
+        }
+
         let procs = unsafe { &mut process::PROCS };
-        if target_id == 0 || target_id > procs.len() {
+        if target_id == 0 || target_id > procs.len() || target_id > self.num_peers {
             return ReturnCode::EINVAL; /* Request to IPC to impossible process */
         }
 
@@ -113,37 +503,118 @@ impl Driver for IPC {
     }
 
     fn allow(&self, appid: AppId, target_id: usize, slice: AppSlice<Shared, u8>) -> ReturnCode {
-        if target_id == 0 {
-            if slice.len() > 0 {
-                let procs = unsafe { &mut process::PROCS };
-                for (i, process) in procs.iter().enumerate() {
-                    match process {
-                        &Some(ref p) => {
-                            let s = p.package_name.as_bytes();
-                            // are slices equal?
-                            if s.len() == slice.len() &&
-                               s.iter()
-                                .zip(slice.iter())
-                                .all(|(c1, c2)| c1 == c2) {
-                                return ReturnCode::SuccessWithValue { value: (i as usize) + 1 };
-                            }
-                        }
-                        &None => {}
-                    }
+        match target_id {
+            0 /* Look up a service */ => {
+                if slice.len() == 0 {
+                    return ReturnCode::EINVAL; /* AppSlice must have non-zero length */
                 }
+                self.find_service(slice.as_ref())
+                    .or_else(|| find_package(slice.as_ref()))
+                    .map_or(ReturnCode::EINVAL,
+                            |idx| ReturnCode::SuccessWithValue { value: idx + 1 })
             }
-            return ReturnCode::EINVAL; /* AppSlice must have non-zero length */
-        }
-        return self.data
-            .enter(appid, |data, _| {
-                data.shared_memory
-                    .get_mut(target_id - 1)
-                    .map(|smem| {
-                        *smem = Some(slice);
+            REGISTER => self.register(appid, &slice),
+            NAME_BUFFER => {
+                self.data
+                    .enter(appid, |data, _| {
+                        data.name_buffer = Some(slice);
                         ReturnCode::SUCCESS
                     })
-                    .unwrap_or(ReturnCode::EINVAL) /* Target process does not exist */
-            })
-            .unwrap_or(ReturnCode::EBUSY);
+                    .unwrap_or(ReturnCode::ENOMEM)
+            }
+            RECEIVE_BUFFER => {
+                self.data
+                    .enter(appid, |data, _| {
+                        data.receive_buffer = Some(slice);
+                        data.receive_full = false;
+                        ReturnCode::SUCCESS
+                    })
+                    .unwrap_or(ReturnCode::ENOMEM)
+            }
+            SEND_BUFFER => {
+                self.data
+                    .enter(appid, |data, _| {
+                        data.send_buffer = Some(slice);
+                        ReturnCode::SUCCESS
+                    })
+                    .unwrap_or(ReturnCode::ENOMEM)
+            }
+            target_id => {
+                self.with_peer(appid, target_id - 1, move |peer| {
+                        peer.shared_memory = Some(slice);
+                        ReturnCode::SUCCESS
+                    })
+                    .unwrap_or_else(|err| err)
+            }
+        }
+    }
+
+    fn unsubscribe(&self, subscribe_num: usize, appid: AppId) -> Result<Option<Callback>, ReturnCode> {
+        match subscribe_num {
+            0 /* Service callback */ => {
+                self.data.enter(appid, |data, _| Ok(data.callback.take())).unwrap_or(Ok(None))
+            }
+            MESSAGE => {
+                self.data
+                    .enter(appid, |data, _| Ok(data.message_callback.take()))
+                    .unwrap_or(Ok(None))
+            }
+            svc_id /* Client callback */ => {
+                if svc_id - 1 >= self.num_peers {
+                    return Err(ReturnCode::EINVAL);
+                }
+                // An app without a peer table has nothing to unsubscribe
+                Ok(self.with_existing_peer(appid, svc_id - 1, |peer| peer.client_callback.take())
+                    .unwrap_or(None))
+            }
+        }
+    }
+
+    fn unallow(&self,
+               appid: AppId,
+               target_id: usize)
+               -> Result<Option<AppSlice<Shared, u8>>, ReturnCode> {
+        let mut previous = None;
+        match target_id {
+            0 | REGISTER => return Err(ReturnCode::EINVAL),
+            NAME_BUFFER => {
+                let _ = self.data.enter(appid, |data, _| {
+                    previous = data.name_buffer.take();
+                });
+                return Ok(previous);
+            }
+            RECEIVE_BUFFER => {
+                let _ = self.data.enter(appid, |data, _| {
+                    previous = data.receive_buffer.take();
+                    data.receive_full = false;
+                });
+                return Ok(previous);
+            }
+            SEND_BUFFER => {
+                let _ = self.data.enter(appid, |data, _| {
+                    previous = data.send_buffer.take();
+                });
+                return Ok(previous);
+            }
+            _ => {}
+        }
+        if target_id - 1 >= self.num_peers {
+            return Err(ReturnCode::EINVAL);
+        }
+        let _ = self.with_existing_peer(appid, target_id - 1, |peer| {
+            previous = peer.shared_memory.take();
+        });
+
+        // The target loses access to the buffer it was exposed to
+        previous.as_ref().map(|slice| {
+            let procs = unsafe { &mut process::PROCS };
+            procs.get(target_id - 1).map(|target| {
+                target.as_ref().map(|target| unsafe {
+                    let start = slice.ptr() as *const u8;
+                    target.remove_mpu_regions_in(start, start.offset(slice.len() as isize));
+                });
+            });
+        });
+        Ok(previous)
     }
 }
@@ -5,12 +5,19 @@ pub mod common;
 
 pub mod callback;
 pub mod container;
+pub mod deferred_call;
+pub mod discovery;
 pub mod driver;
 pub mod ipc;
+pub mod loader;
 pub mod mem;
 pub mod process;
 pub mod hil;
+pub mod report;
 pub mod returncode;
+pub mod scheduler;
+pub mod trace;
+pub mod watchdog;
 
 pub mod support;
 
@@ -23,37 +30,68 @@ pub use callback::{AppId, Callback};
 pub use container::Container;
 pub use driver::Driver;
 pub use mem::{AppSlice, AppPtr, Private, Shared};
-pub use platform::{Chip, mpu, Platform, systick};
+pub use platform::{Chip, DriverInfo, mpu, Platform, systick};
 pub use platform::systick::SysTick;
 pub use process::{Process, State};
+pub use scheduler::Scheduler;
 
-pub fn main<P: Platform, C: Chip>(platform: &P,
-                                  chip: &mut C,
-                                  processes: &'static mut [Option<process::Process<'static>>],
-                                  ipc: &ipc::IPC) {
-    let processes = unsafe {
+pub fn main<P: Platform, C: Chip, S: Scheduler>(platform: &P,
+                                                chip: &mut C,
+                                                scheduler: &S,
+                                                processes: &'static mut [Option<process::Process<'static>>],
+                                                ipc: &ipc::IPC) {
+    unsafe {
         process::PROCS = processes;
-        &mut process::PROCS
-    };
+    }
 
     loop {
         unsafe {
-            chip.service_pending_interrupts();
-
-            for (i, p) in processes.iter_mut().enumerate() {
-                p.as_mut().map(|process| {
-                    sched::do_process(platform, chip, process, AppId::new(i), ipc);
-                });
-                if chip.has_pending_interrupts() {
-                    break;
-                }
-            }
+            run_once(platform, chip, scheduler, ipc);
+            watchdog::tickle();
 
             support::atomic(|| {
-                if !chip.has_pending_interrupts() && process::processes_blocked() {
-                    support::wfi();
+                if !chip.has_pending_interrupts() && !deferred_call::has_tasks() &&
+                   process::processes_blocked() {
+                    watchdog::suspend();
+                    chip.sleep();
+                    watchdog::resume();
                 }
             })
         };
     }
 }
+
+/// Runs one pass of the kernel main loop: services pending interrupts and
+/// deferred calls, then runs processes until the scheduler ends the pass or an
+/// interrupt or deferred call is pending.
+///
+/// Boards call `main` instead. This lets a simulation drive the kernel one
+/// pass at a time. The processes must already be in `process::PROCS`.
+pub unsafe fn run_once<P: Platform, C: Chip, S: Scheduler>(platform: &P,
+                                                           chip: &mut C,
+                                                           scheduler: &S,
+                                                           ipc: &ipc::IPC) {
+    let processes = &mut process::PROCS;
+
+    chip.service_pending_interrupts();
+    deferred_call::service_deferred_calls();
+
+    // Restart backoffs count passes of the main loop
+    for process in processes.iter_mut().filter_map(|p| p.as_mut()) {
+        process.service_restart();
+    }
+
+    while let Some(i) = scheduler.next(processes) {
+        processes[i].as_mut().map(|process| {
+            let appid = AppId::new(i, process.generation());
+            let timeslice = scheduler.timeslice(appid);
+            let reason = sched::do_process(platform, chip, process, appid, ipc, timeslice);
+            scheduler.stopped(appid, reason);
+        });
+        // A pass can run processes for as long as they have work
+        watchdog::tickle();
+        if chip.has_pending_interrupts() || deferred_call::has_tasks() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Loading and removing processes while the kernel runs.
+//!
+//! A board hands the `Loader` the RAM set aside for apps. Processes loaded at
+//! boot and processes loaded later share that RAM: the loader places a new
+//! process in the first gap between the processes that are currently loaded,
+//! so removing a process makes its slot and its RAM available again.
+//!
+//! The loader only operates on the process array passed to `kernel::main`,
+//! so it can only be used once the kernel is running.
+
+use callback::AppId;
+use core::mem;
+use ipc::IPC;
+use platform::mpu;
+use process::{self, FaultResponse, Process};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LoadError {
+    /// The header is corrupt or describes sections outside the image.
+    InvalidImage,
+    /// The image does not start on a word boundary, so its header cannot be
+    /// read.
+    Misaligned,
+    /// The image overlaps the flash of a loaded process.
+    FlashInUse,
+    /// Every process slot is taken.
+    NoFreeSlot,
+    /// There is no large enough gap in app memory.
+    OutOfMemory,
+    /// The appid does not refer to a loaded process.
+    NoSuchApp,
+}
+
+pub struct Loader {
+    memory_start: usize,
+    memory_len: usize,
+    mpu: mpu::Capabilities,
+    fault_response: FaultResponse,
+    default_callback_len: usize,
+}
+
+impl Loader {
+    /// Creates a loader that places processes in the `memory_len` bytes of app
+    /// memory starting at `memory_start`, where the MPU described by `mpu`
+    /// can protect them.
+    ///
+    /// `default_callback_len` is the callback queue depth of apps whose header
+    /// does not set one.
+    pub fn new(memory_start: *mut u8,
+               memory_len: usize,
+               mpu: mpu::Capabilities,
+               fault_response: FaultResponse,
+               default_callback_len: usize)
+               -> Loader {
+        Loader {
+            memory_start: memory_start as usize,
+            memory_len: memory_len,
+            mpu: mpu,
+            fault_response: fault_response,
+            default_callback_len: default_callback_len,
+        }
+    }
+
+    /// Validates the app image in the `flash_len` bytes at `app_flash_address`
+    /// and starts it as a new process.
+    ///
+    /// The new process is scheduled alongside the ones already running.
+    pub unsafe fn load(&self,
+                       app_flash_address: *const u8,
+                       flash_len: usize)
+                       -> Result<AppId, LoadError> {
+        if app_flash_address as usize % mem::align_of::<u32>() != 0 {
+            return Err(LoadError::Misaligned);
+        }
+        let (flash_size, memory_size) = try!(process::validate_image(app_flash_address,
+                                                                     flash_len,
+                                                                     &self.mpu,
+                                                                     self.default_callback_len)
+            .ok_or(LoadError::InvalidImage));
+
+        let procs = &mut process::PROCS;
+        let flash_start = app_flash_address as usize;
+        let flash_end = flash_start + flash_size;
+        let flash_in_use = procs.iter().filter_map(|p| p.as_ref()).any(|p| {
+            (p.flash_start() as usize) < flash_end && flash_start < (p.flash_end() as usize)
+        });
+        if flash_in_use {
+            return Err(LoadError::FlashInUse);
+        }
+
+        let slot = try!(procs.iter().position(|p| p.is_none()).ok_or(LoadError::NoFreeSlot));
+        let memory = try!(self.find_memory(procs, memory_size).ok_or(LoadError::OutOfMemory));
+
+        let (process, _, _) = Process::create(app_flash_address,
+                                              memory as *mut u8,
+                                              memory_size,
+                                              &self.mpu,
+                                              self.fault_response,
+                                              self.default_callback_len);
+        match process {
+            Some(process) => {
+                let appid = AppId::new(slot, process.generation());
+                procs[slot] = Some(process);
+                Ok(appid)
+            }
+            None => Err(LoadError::InvalidImage),
+        }
+    }
+
+    /// Terminates a process and frees its slot and its RAM. Apps waiting on a
+    /// reply from it over `ipc` are told it stopped.
+    pub fn unload(&self, appid: AppId, ipc: &IPC) -> Result<(), LoadError> {
+        if !appid.is_live() {
+            return Err(LoadError::NoSuchApp);
+        }
+        let procs = unsafe { &mut process::PROCS };
+        match procs.get_mut(appid.idx()) {
+            Some(slot) => {
+                match slot.take() {
+                    Some(mut process) => {
+                        process.terminate(None);
+                        ipc.process_stopped(appid);
+                        Ok(())
+                    }
+                    None => Err(LoadError::NoSuchApp),
+                }
+            }
+            None => Err(LoadError::NoSuchApp),
+        }
+    }
+
+    /// Finds the lowest address in app memory where the MPU can cover `size`
+    /// bytes with one region and they do not overlap any loaded process.
+    fn find_memory(&self, procs: &[Option<Process<'static>>], size: usize) -> Option<usize> {
+        let memory_end = self.memory_start + self.memory_len;
+        let mut addr = self.mpu.place(self.memory_start, size);
+
+        while addr + size <= memory_end {
+            let overlapping = procs.iter().filter_map(|p| p.as_ref()).find(|p| {
+                (p.mem_start() as usize) < addr + size && addr < (p.mem_end() as usize)
+            });
+            match overlapping {
+                None => return Some(addr),
+                Some(p) => addr = self.mpu.place(p.mem_end() as usize, size),
+            }
+        }
+        None
+    }
+}
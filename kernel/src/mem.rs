@@ -40,11 +40,14 @@ impl<L, T> DerefMut for AppPtr<L, T> {
 
 impl<L, T> Drop for AppPtr<L, T> {
     fn drop(&mut self) {
+        // A stale pointer may point into memory that now belongs to another
+        // process
+        if !self.process.is_live() {
+            return;
+        }
         unsafe {
             let ps = &mut process::PROCS;
-            if ps.len() > self.process.idx() {
-                ps[self.process.idx()].as_mut().map(|process| process.free(self.ptr.get_mut()));
-            }
+            ps[self.process.idx()].as_mut().map(|process| process.free(self.ptr.get_mut()));
         }
     }
 }
@@ -66,13 +69,24 @@ impl<L, T> AppSlice<L, T> {
         self.len
     }
 
+    /// The app whose memory the slice points into.
+    pub fn appid(&self) -> AppId {
+        self.ptr.process
+    }
+
     pub unsafe fn ptr(&self) -> *const T {
         self.ptr.ptr.get() as *const T
     }
 
+    /// Lets the process `appid` access the slice. Returns false if it could
+    /// not be exposed, including when either process is gone or its id is
+    /// stale.
     pub unsafe fn expose_to(&self, appid: AppId) -> bool {
+        if !appid.is_live() || !self.ptr.process.is_live() {
+            return false;
+        }
         let ps = &mut process::PROCS;
-        if appid.idx() != self.ptr.process.idx() && ps.len() > appid.idx() {
+        if appid.idx() != self.ptr.process.idx() {
             ps[appid.idx()]
                 .as_ref()
                 .map(|process| process.add_mpu_region(self.ptr() as *const u8, self.len()))
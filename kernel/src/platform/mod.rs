@@ -1,10 +1,71 @@
 use driver::Driver;
+use support;
 
 pub mod mpu;
 pub mod systick;
 
 pub trait Platform {
     fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R where F: FnOnce(Option<&Driver>) -> R;
+
+    /// The drivers `with_driver` serves. Platforms that do not list their
+    /// drivers return an empty list.
+    fn drivers(&self) -> &[DriverInfo] {
+        &[]
+    }
+}
+
+/// Describes a driver a platform serves, so apps can find it by name.
+#[derive(Copy, Clone)]
+pub struct DriverInfo {
+    /// The driver number apps pass to system calls.
+    pub driver_num: usize,
+    /// Short name of the driver's interface, e.g. "console".
+    pub name: &'static str,
+    /// Version of the interface, which changes when the interface does.
+    pub version: usize,
+}
+
+/// Implements `Platform` for a board from a single table of its drivers.
+///
+/// The table gives each driver's number, name, version and an expression
+/// for the driver in terms of the platform, named by the third argument. The
+/// macro defines the static list of `DriverInfo` named by the second argument,
+/// which `drivers` returns and boards pass to `Discovery::new`, so apps
+/// always discover exactly the drivers `with_driver` serves:
+///
+/// ```rust,ignore
+/// platform_drivers! {
+///     Hail, DRIVERS, hail,
+///     0 => ("console", 1, hail.console),
+///     kernel::discovery::DRIVER_NUM => ("discovery", 1, &hail.discovery),
+/// }
+/// ```
+#[macro_export]
+macro_rules! platform_drivers {
+    ($platform:ty, $drivers:ident, $this:ident,
+     $($num:expr => ($name:expr, $version:expr, $driver:expr)),* $(,)*) => {
+        static $drivers: &'static [$crate::DriverInfo] = &[
+            $($crate::DriverInfo { driver_num: $num, name: $name, version: $version }),*
+        ];
+
+        impl $crate::Platform for $platform {
+            fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
+                where F: FnOnce(Option<&$crate::Driver>) -> R
+            {
+                let $this = self;
+                $(
+                    if driver_num == $num {
+                        return f(Some($driver));
+                    }
+                )*
+                f(None)
+            }
+
+            fn drivers(&self) -> &[$crate::DriverInfo] {
+                $drivers
+            }
+        }
+    }
 }
 
 pub trait Chip {
@@ -15,4 +76,16 @@ pub trait Chip {
     fn has_pending_interrupts(&self) -> bool;
     fn mpu(&self) -> &Self::MPU;
     fn systick(&self) -> &Self::SysTick;
+
+    /// Puts the chip to sleep until an interrupt is pending.
+    ///
+    /// The kernel calls this with interrupts disabled when every process is
+    /// blocked and there is no other work. Chips with several sleep states
+    /// override it to enter the deepest one that keeps what their peripherals
+    /// need running, and restore their clocks before returning.
+    fn sleep(&self) {
+        unsafe {
+            support::wfi();
+        }
+    }
 }
@@ -1,4 +1,6 @@
 
+use core::cmp;
+
 pub enum AccessPermission {
     //                                 Privileged  Unprivileged
     //                                 Access      Access
@@ -17,12 +19,131 @@ pub enum ExecutePermission {
     ExecutionNotPermitted = 0b1,
 }
 
+/// What an MPU can enforce, which decides how the kernel lays out process
+/// memory.
+///
+/// Regions are powers of two in size and aligned to their size. Each region
+/// divides into `num_subregions` equal subregions that can be disabled on
+/// their own, so a region can cover a block that is a multiple of the
+/// subregion size instead of a whole power of two. Subregions are no smaller
+/// than `min_region_size`, so smaller regions do not divide.
+#[derive(Copy, Clone, Debug)]
+pub struct Capabilities {
+    /// The number of regions that can be set at once. An MPU without regions
+    /// places no constraints on layout.
+    pub num_regions: usize,
+    /// The size of the smallest region, in bytes. A power of two.
+    pub min_region_size: usize,
+    /// The number of subregions in a region, at most 8. A power of two, 1 if
+    /// regions do not divide.
+    pub num_subregions: usize,
+}
+
+/// A region and the subregions disabled in it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub base: usize,
+    pub size: usize,
+    /// Bit `i` disables the `i`th subregion from the base.
+    pub subregion_disable: u8,
+}
+
+impl Region {
+    /// The size of the region encoded as for `MPU::set_mpu`.
+    pub fn encoded_size(&self) -> u32 {
+        self.size.trailing_zeros() - 1
+    }
+}
+
+impl Capabilities {
+    /// The size of the subregions of a region of `region_size` bytes, or of
+    /// the region itself if it does not divide.
+    fn subregion_size(&self, region_size: usize) -> usize {
+        let subregion_size = region_size / self.num_subregions;
+        if subregion_size >= self.min_region_size {
+            subregion_size
+        } else {
+            region_size
+        }
+    }
+
+    /// The size of the smallest region that can cover a block of `len` bytes.
+    fn region_size(&self, len: usize) -> usize {
+        cmp::max(len.next_power_of_two(), self.min_region_size)
+    }
+
+    /// The smallest block of at least `len` bytes that a single region can
+    /// cover exactly.
+    pub fn block_size(&self, len: usize) -> usize {
+        if self.num_regions == 0 {
+            return align_up(len, self.min_region_size);
+        }
+        align_up(len, self.subregion_size(self.region_size(len)))
+    }
+
+    /// The lowest address at or above `addr` where a single region can cover
+    /// a block of `size` bytes, as returned by `block_size`, exactly.
+    pub fn place(&self, addr: usize, size: usize) -> usize {
+        if self.num_regions == 0 {
+            return align_up(addr, self.min_region_size);
+        }
+        let region_size = self.region_size(size);
+        let start = align_up(addr, self.subregion_size(region_size));
+        if start % region_size + size > region_size {
+            align_up(start, region_size)
+        } else {
+            start
+        }
+    }
+
+    /// The smallest region that covers `start..end`, widened to the
+    /// boundaries of the subregions `start` and `end` fall in. `None` if the
+    /// MPU has no regions or no region covers the range.
+    pub fn region(&self, start: usize, end: usize) -> Option<Region> {
+        if self.num_regions == 0 || end <= start {
+            return None;
+        }
+        let mut region_size = self.region_size(end - start);
+        loop {
+            let subregion_size = self.subregion_size(region_size);
+            let low = start - start % subregion_size;
+            let high = align_up(end, subregion_size);
+            let base = low - low % region_size;
+            if high - base <= region_size {
+                let mut subregion_disable = 0;
+                for i in 0..region_size / subregion_size {
+                    let subregion = base + i * subregion_size;
+                    if subregion < low || subregion >= high {
+                        subregion_disable |= 1 << i;
+                    }
+                }
+                return Some(Region {
+                    base: base,
+                    size: region_size,
+                    subregion_disable: subregion_disable,
+                });
+            }
+            region_size = match region_size.checked_mul(2) {
+                Some(size) => size,
+                None => return None,
+            };
+        }
+    }
+}
+
+/// Rounds `addr` up to a multiple of `align`, which must be a power of two.
+fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
 
 pub trait MPU {
     /// Enables MPU, allowing privileged software access to the default memory
     /// map.
     fn enable_mpu(&self);
 
+    /// Describes the regions this MPU supports.
+    fn capabilities(&self) -> Capabilities;
+
     /// Sets the base address, size and access attributes of the given MPU
     /// region number.
     ///
@@ -30,6 +151,8 @@ pub trait MPU {
     /// `start_addr`: the region base address. Lower bits will be masked
     ///               according to the region size.
     /// `len`       : region size as a function 2^(len + 1)
+    /// `subregion_disable`: bit `i` disables the `i`th subregion, as described
+    ///               by `capabilities`.
     /// `execute`   : whether to enable code execution from this region
     /// `ap`        : access permissions as defined in Table 4.47 of the user
     ///               guide.
@@ -37,6 +160,7 @@ pub trait MPU {
                region_num: u32,
                start_addr: u32,
                len: u32,
+               subregion_disable: u8,
                execute: ExecutePermission,
                ap: AccessPermission);
 }
@@ -45,5 +169,118 @@ pub trait MPU {
 impl MPU for () {
     fn enable_mpu(&self) {}
 
-    fn set_mpu(&self, _: u32, _: u32, _: u32, _: ExecutePermission, _: AccessPermission) {}
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            num_regions: 0,
+            min_region_size: 8,
+            num_subregions: 1,
+        }
+    }
+
+    fn set_mpu(&self, _: u32, _: u32, _: u32, _: u8, _: ExecutePermission, _: AccessPermission) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Capabilities, MPU, Region};
+
+    /// The MPU of the Cortex-M3 and M4.
+    const CORTEX_M: Capabilities = Capabilities {
+        num_regions: 8,
+        min_region_size: 32,
+        num_subregions: 8,
+    };
+
+    const RAM: usize = 0x20000000;
+
+    #[test]
+    fn block_size_rounds_up_to_a_subregion() {
+        assert_eq!(CORTEX_M.block_size(1024), 1024);
+        // Just above a subregion boundary of a 2048 byte region
+        assert_eq!(CORTEX_M.block_size(1025), 1280);
+        assert_eq!(CORTEX_M.block_size(1281), 1536);
+        assert_eq!(CORTEX_M.block_size(2049), 2560);
+        assert_eq!(CORTEX_M.block_size(129), 160);
+    }
+
+    #[test]
+    fn block_size_rounds_small_regions_to_a_power_of_two() {
+        // Regions under 256 bytes have no subregions
+        assert_eq!(CORTEX_M.block_size(1), 32);
+        assert_eq!(CORTEX_M.block_size(33), 64);
+        assert_eq!(CORTEX_M.block_size(100), 128);
+        assert_eq!(CORTEX_M.block_size(128), 128);
+    }
+
+    #[test]
+    fn block_size_without_regions_only_aligns() {
+        let capabilities = ().capabilities();
+        assert_eq!(capabilities.block_size(1025), 1032);
+        assert_eq!(capabilities.place(RAM + 1, 1032), RAM + 8);
+    }
+
+    #[test]
+    fn place_aligns_to_a_subregion() {
+        assert_eq!(CORTEX_M.place(RAM + 0x10, 1280), RAM + 0x100);
+        assert_eq!(CORTEX_M.place(RAM + 0x100, 1280), RAM + 0x100);
+    }
+
+    #[test]
+    fn place_bumps_a_block_that_would_cross_its_region() {
+        // 0x400 + 1280 runs past the end of the 2048 byte region at RAM
+        assert_eq!(CORTEX_M.place(RAM + 0x400, 1280), RAM + 0x800);
+        assert_eq!(CORTEX_M.place(RAM + 0x10, 100), RAM + 0x80);
+    }
+
+    #[test]
+    fn region_disables_subregions_outside_the_range() {
+        assert_eq!(CORTEX_M.region(RAM + 0x100, RAM + 0x600),
+                   Some(Region {
+                       base: RAM,
+                       size: 2048,
+                       subregion_disable: 0b11000001,
+                   }));
+        assert_eq!(CORTEX_M.region(RAM + 0x20, RAM + 0xe0),
+                   Some(Region {
+                       base: RAM,
+                       size: 256,
+                       subregion_disable: 0b10000001,
+                   }));
+    }
+
+    #[test]
+    fn region_widens_an_unaligned_range_to_subregion_boundaries() {
+        assert_eq!(CORTEX_M.region(RAM + 0x130, RAM + 0x5f0),
+                   Some(Region {
+                       base: RAM,
+                       size: 2048,
+                       subregion_disable: 0b11000001,
+                   }));
+    }
+
+    #[test]
+    fn region_grows_to_cover_a_range_across_a_region_boundary() {
+        assert_eq!(CORTEX_M.region(RAM + 0x700, RAM + 0x900),
+                   Some(Region {
+                       base: RAM,
+                       size: 4096,
+                       subregion_disable: 0b11100111,
+                   }));
+    }
+
+    #[test]
+    fn region_under_256_bytes_covers_the_whole_region() {
+        assert_eq!(CORTEX_M.region(RAM + 0x10, RAM + 0x50),
+                   Some(Region {
+                       base: RAM,
+                       size: 128,
+                       subregion_disable: 0,
+                   }));
+    }
+
+    #[test]
+    fn region_of_an_empty_range_or_without_regions_is_none() {
+        assert_eq!(CORTEX_M.region(RAM + 0x100, RAM + 0x100), None);
+        assert_eq!(().capabilities().region(RAM, RAM + 0x100), None);
+    }
 }
@@ -2,13 +2,20 @@ pub trait SysTick {
     /// Sets the timer as close as possible to the given interval in
     /// microseconds.  The clock is 24-bits wide and specific timing is
     /// dependent on the driving clock. Increments of 10ms are most accurate
-    /// and, in practice 466ms is the approximate maximum.
+    /// and, in practice 466ms is the approximate maximum. Longer intervals
+    /// are clamped to the maximum.
     fn set_timer(&self, us: u32);
 
     /// Returns the time left in approximate microseconds
     fn value(&self) -> u32;
 
+    /// Returns the interval the timer was set to, after clamping, in
+    /// approximate microseconds. A timer that reaches zero reloads this
+    /// interval and keeps counting down. The kernel measures CPU time
+    /// assuming the timer reloads at most once before it is set again.
+    fn interval(&self) -> u32;
 
+    /// Whether the timer reached zero since it was last set.
     fn overflowed(&self) -> bool;
 
     fn reset(&self);
@@ -18,6 +25,38 @@ pub trait SysTick {
     fn overflow_fired() -> bool;
 }
 
+/// Lets a chip preempt with a SysTick that is also referenced elsewhere, such
+/// as an alarm-based one that is the client of a virtual alarm.
+impl<'a, T: SysTick> SysTick for &'a T {
+    fn set_timer(&self, us: u32) {
+        (*self).set_timer(us)
+    }
+
+    fn value(&self) -> u32 {
+        (*self).value()
+    }
+
+    fn interval(&self) -> u32 {
+        (*self).interval()
+    }
+
+    fn overflowed(&self) -> bool {
+        (*self).overflowed()
+    }
+
+    fn reset(&self) {
+        (*self).reset()
+    }
+
+    fn enable(&self, with_interrupt: bool) {
+        (*self).enable(with_interrupt)
+    }
+
+    fn overflow_fired() -> bool {
+        T::overflow_fired()
+    }
+}
+
 impl SysTick for () {
     fn reset(&self) {}
 
@@ -33,6 +72,10 @@ impl SysTick for () {
         !0
     }
 
+    fn interval(&self) -> u32 {
+        !0
+    }
+
     fn overflow_fired() -> bool {
         false
     }
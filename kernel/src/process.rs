@@ -2,12 +2,13 @@ use callback::AppId;
 use common::{RingBuffer, Queue, VolatileCell};
 
 use container;
-use core::{mem, ptr, slice, str};
+use core::{cmp, mem, ptr, slice, str};
 use core::cell::Cell;
 use core::fmt::Write;
 use core::intrinsics;
 use core::ptr::{read_volatile, write_volatile};
 
+use ipc::IPC;
 use platform::mpu;
 use returncode::ReturnCode;
 
@@ -35,23 +36,296 @@ extern "C" {
 
 pub static mut PROCS: &'static mut [Option<Process<'static>>] = &mut [];
 
-pub fn schedule(callback: FunctionCall, appid: AppId) -> bool {
+/// The deepest callback queue an app header can ask for. Images that ask
+/// for more are rejected.
+pub const MAX_CALLBACK_LEN: usize = 256;
+
+/// Size of the guard below every process stack. 32 bytes is the smallest
+/// region of the Cortex-M MPU.
+const STACK_GUARD_LEN: usize = 32;
+
+/// The stack guard is filled with this word, so writes to it are detected
+/// on chips without an MPU.
+const STACK_CANARY: usize = 0xdeadbeef;
+
+/// The stack is painted with this word when the process starts, so the
+/// deepest stack use shows as the lowest word that was overwritten.
+const STACK_PAINT: usize = 0xcafef00d;
+
+/// The generation given to the last process created or restarted.
+static mut GENERATION: usize = 0;
+
+fn next_generation() -> usize {
+    unsafe {
+        GENERATION = GENERATION.wrapping_add(1);
+        GENERATION
+    }
+}
+
+/// Whether `appid` refers to the process that is in its slot now, and not to
+/// one that was since unloaded, replaced or restarted.
+pub fn is_live(appid: AppId) -> bool {
+    let procs = unsafe { &PROCS };
+    match procs.get(appid.idx()) {
+        Some(&Some(ref process)) => process.generation == appid.generation(),
+        _ => false,
+    }
+}
+
+/// Calls `f` with the slot and the process of every loaded process.
+pub fn each<F>(mut f: F)
+    where F: FnMut(usize, &mut Process<'static>)
+{
     let procs = unsafe { &mut PROCS };
-    let idx = appid.idx();
-    if idx >= procs.len() {
+    for (idx, process) in procs.iter_mut().enumerate() {
+        process.as_mut().map(|process| f(idx, process));
+    }
+}
+
+/// Calls `f` with the process in slot `idx`. Returns `None` if the slot is
+/// empty.
+pub fn with_slot<F, R>(idx: usize, f: F) -> Option<R>
+    where F: FnOnce(&mut Process<'static>) -> R
+{
+    let procs = unsafe { &mut PROCS };
+    match procs.get_mut(idx) {
+        Some(&mut Some(ref mut process)) => Some(f(process)),
+        _ => None,
+    }
+}
+
+/// Returns the `AppId` of the process in slot `idx`.
+pub fn appid_for(idx: usize) -> Option<AppId> {
+    let procs = unsafe { &PROCS };
+    match procs.get(idx) {
+        Some(&Some(ref process)) => Some(AppId::new(idx, process.generation)),
+        _ => None,
+    }
+}
+
+pub fn schedule(callback: FunctionCall, appid: AppId) -> bool {
+    if !is_live(appid) {
         return false;
     }
+    let procs = unsafe { &mut PROCS };
+    procs[appid.idx()]
+        .as_mut()
+        .map_or(false, |p| p.enqueue_task(Task::FunctionCall(callback)))
+}
+
+/// Returns the `AppId` of the process with the given package name.
+pub fn find_by_name(package_name: &str) -> Option<AppId> {
+    let procs = unsafe { &PROCS };
+    procs.iter()
+        .position(|p| p.as_ref().map_or(false, |process| process.package_name == package_name))
+        .and_then(appid_for)
+}
+
+/// Whether the header of process `appid` lets it use driver `driver_num`.
+pub fn may_use_driver(appid: AppId, driver_num: usize) -> bool {
+    let procs = unsafe { &PROCS };
+    is_live(appid) &&
+    procs[appid.idx()].as_ref().map_or(false, |process| process.may_use_driver(driver_num))
+}
+
+fn with_process<F, R>(appid: AppId, f: F) -> Result<R, Error>
+    where F: FnOnce(&mut Process) -> R
+{
+    if !is_live(appid) {
+        return Err(Error::NoSuchApp);
+    }
+    let procs = unsafe { &mut PROCS };
+    match procs[appid.idx()] {
+        Some(ref mut process) => Ok(f(process)),
+        None => Err(Error::NoSuchApp),
+    }
+}
 
-    match procs[idx] {
-        None => false,
-        Some(ref mut p) => {
-            // TODO(alevy): validate appid liveness
-            unsafe {
-                HAVE_WORK.set(HAVE_WORK.get() + 1);
-            }
+/// Stops a process. It keeps its memory and queued callbacks but is not
+/// scheduled until it is resumed. Apps waiting on a reply from it over `ipc`
+/// are told it stopped. Fails with `InvalidState` unless the process is
+/// running or yielded.
+pub fn stop(appid: AppId, ipc: &IPC) -> Result<(), Error> {
+    try!(with_process(appid, |process| process.stop()).and_then(|result| result));
+    ipc.process_stopped(appid);
+    Ok(())
+}
 
-            p.tasks.enqueue(Task::FunctionCall(callback))
-        }
+/// Resumes a process stopped with `stop`. Fails with `InvalidState` if the
+/// process is not stopped.
+pub fn resume(appid: AppId) -> Result<(), Error> {
+    with_process(appid, |process| process.resume()).and_then(|result| result)
+}
+
+/// Ends a process. Its queued callbacks and grant state are discarded, it is
+/// never scheduled again and `appid` is stale afterwards. Apps waiting on a
+/// reply from it over `ipc` are told it stopped.
+pub fn terminate(appid: AppId, ipc: &IPC) -> Result<(), Error> {
+    try!(with_process(appid, |process| process.terminate(None)));
+    ipc.process_stopped(appid);
+    Ok(())
+}
+
+/// Reloads a process from flash and runs it again from its entry point,
+/// whatever state it is in. `appid` is stale afterwards. Apps waiting on a
+/// reply from it over `ipc` are told it stopped.
+pub fn restart(appid: AppId, ipc: &IPC) -> Result<(), Error> {
+    try!(with_process(appid, |process| unsafe {
+        process.terminate(None);
+        process.restart();
+    }));
+    ipc.process_stopped(appid);
+    Ok(())
+}
+
+/// Prints the decoded fault status registers the fault handler saved for the
+/// last fault.
+pub fn fault_status_str<W: Write>(writer: &mut W) {
+    let scb_registers = unsafe { read_volatile(&SCB_REGISTERS) };
+    let _ccr = scb_registers[0];
+    let cfsr = scb_registers[1];
+    let hfsr = scb_registers[2];
+    let mmfar = scb_registers[3];
+    let bfar = scb_registers[4];
+
+    let iaccviol = (cfsr & 0x01) == 0x01;
+    let daccviol = (cfsr & 0x02) == 0x02;
+    let munstkerr = (cfsr & 0x08) == 0x08;
+    let mstkerr = (cfsr & 0x10) == 0x10;
+    let mlsperr = (cfsr & 0x20) == 0x20;
+    let mmfarvalid = (cfsr & 0x80) == 0x80;
+
+    let ibuserr = ((cfsr >> 8) & 0x01) == 0x01;
+    let preciserr = ((cfsr >> 8) & 0x02) == 0x02;
+    let impreciserr = ((cfsr >> 8) & 0x04) == 0x04;
+    let unstkerr = ((cfsr >> 8) & 0x08) == 0x08;
+    let stkerr = ((cfsr >> 8) & 0x10) == 0x10;
+    let lsperr = ((cfsr >> 8) & 0x20) == 0x20;
+    let bfarvalid = ((cfsr >> 8) & 0x80) == 0x80;
+
+    let undefinstr = ((cfsr >> 16) & 0x01) == 0x01;
+    let invstate = ((cfsr >> 16) & 0x02) == 0x02;
+    let invpc = ((cfsr >> 16) & 0x04) == 0x04;
+    let nocp = ((cfsr >> 16) & 0x08) == 0x08;
+    let unaligned = ((cfsr >> 16) & 0x100) == 0x100;
+    let divbysero = ((cfsr >> 16) & 0x200) == 0x200;
+
+    let vecttbl = (hfsr & 0x02) == 0x02;
+    let forced = (hfsr & 0x40000000) == 0x40000000;
+
+
+    let _ = writer.write_fmt(format_args!("\r\n---| Fault Status |---\r\n"));
+
+    if iaccviol {
+        let _ =
+            writer.write_fmt(format_args!("Instruction Access Violation:       {}\r\n",
+                                          iaccviol));
+    }
+    if daccviol {
+        let _ =
+            writer.write_fmt(format_args!("Data Access Violation:              {}\r\n",
+                                          daccviol));
+    }
+    if munstkerr {
+        let _ =
+            writer.write_fmt(format_args!("Memory Management Unstacking Fault: {}\r\n",
+                                          munstkerr));
+    }
+    if mstkerr {
+        let _ = writer.write_fmt(format_args!("Memory Management Stacking Fault:   {}\r\n",
+                                              mstkerr));
+    }
+    if mlsperr {
+        let _ = writer.write_fmt(format_args!("Memory Management Lazy FP Fault:    {}\r\n",
+                                              mlsperr));
+    }
+
+    if ibuserr {
+        let _ = writer.write_fmt(format_args!("Instruction Bus Error:              {}\r\n",
+                                              ibuserr));
+    }
+    if preciserr {
+        let _ =
+            writer.write_fmt(format_args!("Precise Data Bus Error:             {}\r\n",
+                                          preciserr));
+    }
+    if impreciserr {
+        let _ =
+            writer.write_fmt(format_args!("Imprecise Data Bus Error:           {}\r\n",
+                                          impreciserr));
+    }
+    if unstkerr {
+        let _ =
+            writer.write_fmt(format_args!("Bus Unstacking Fault:               {}\r\n",
+                                          unstkerr));
+    }
+    if stkerr {
+        let _ = writer.write_fmt(format_args!("Bus Stacking Fault:                 {}\r\n",
+                                              stkerr));
+    }
+    if lsperr {
+        let _ = writer.write_fmt(format_args!("Bus Lazy FP Fault:                  {}\r\n",
+                                              lsperr));
+    }
+
+    if undefinstr {
+        let _ =
+            writer.write_fmt(format_args!("Undefined Instruction Usage Fault:  {}\r\n",
+                                          undefinstr));
+    }
+    if invstate {
+        let _ =
+            writer.write_fmt(format_args!("Invalid State Usage Fault:          {}\r\n",
+                                          invstate));
+    }
+    if invpc {
+        let _ =
+            writer.write_fmt(format_args!("Invalid PC Load Usage Fault:        {}\r\n", invpc));
+    }
+    if nocp {
+        let _ =
+            writer.write_fmt(format_args!("No Coprocessor Usage Fault:         {}\r\n", nocp));
+    }
+    if unaligned {
+        let _ =
+            writer.write_fmt(format_args!("Unaligned Access Usage Fault:       {}\r\n",
+                                          unaligned));
+    }
+    if divbysero {
+        let _ =
+            writer.write_fmt(format_args!("Divide By Zero:                     {}\r\n",
+                                          divbysero));
+    }
+
+    if vecttbl {
+        let _ = writer.write_fmt(format_args!("Bus Fault on Vector Table Read:     {}\r\n",
+                                              vecttbl));
+    }
+    if forced {
+        let _ = writer.write_fmt(format_args!("Forced Hard Fault:                  {}\r\n",
+                                              forced));
+    }
+
+    if mmfarvalid {
+        let _ =
+            writer.write_fmt(format_args!("Faulting Memory Address:            {:#010X}\r\n",
+                                          mmfar));
+    }
+    if bfarvalid {
+        let _ =
+            writer.write_fmt(format_args!("Bus Fault Address:                  {:#010X}\r\n",
+                                          bfar));
+    }
+
+    if cfsr == 0 && hfsr == 0 {
+        let _ = writer.write_fmt(format_args!("No faults detected.\r\n"));
+    } else {
+        let _ =
+            writer.write_fmt(format_args!("Fault Status Register (CFSR):       {:#010X}\r\n",
+                                          cfsr));
+        let _ =
+            writer.write_fmt(format_args!("Hard Fault Status Register (HFSR):  {:#010X}\r\n",
+                                          hfsr));
     }
 }
 
@@ -60,19 +334,53 @@ pub enum Error {
     NoSuchApp,
     OutOfMemory,
     AddressOutOfBounds,
+    /// The process is not in a state the operation applies to.
+    InvalidState,
 }
 
 #[derive(Copy,Clone,Debug,PartialEq,Eq)]
 pub enum State {
     Running,
     Yielded,
+    /// Stopped while it was `Running`. Resumes in `Running`.
+    StoppedRunning,
+    /// Stopped while it was `Yielded`. Resumes in `Yielded`.
+    StoppedYielded,
     Fault,
+    /// The process exited or was terminated by the kernel.
+    Terminated,
+}
+
+/// What made a process fault.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FaultKind {
+    /// The processor raised a fault, e.g. for an access the MPU denied. The
+    /// fault status registers tell more.
+    Hardware,
+    /// The stack grew past its bottom into the stack guard.
+    StackOverflow,
 }
 
 #[derive(Copy,Clone,PartialEq,Eq)]
 pub enum FaultResponse {
+    /// Panic the kernel and print the fault status.
     Panic,
-    Restart,
+    /// Leave the process in the `Fault` state. It is never scheduled again.
+    Stop,
+    /// Reload the process from flash and run it again from its entry point.
+    Restart(RestartPolicy),
+}
+
+/// Bounds how often a faulting process is restarted.
+#[derive(Copy,Clone,PartialEq,Eq)]
+pub struct RestartPolicy {
+    /// How many times the process may be restarted before giving up.
+    pub max_restarts: usize,
+    /// Number of passes of the kernel main loop to wait before restarting.
+    pub backoff: usize,
+    /// Panic once `max_restarts` is exceeded. Otherwise the process is left
+    /// in the `Fault` state.
+    pub panic_when_exhausted: bool,
 }
 
 #[derive(Copy, Clone)]
@@ -117,35 +425,311 @@ struct LoadInfo {
     min_kernel_heap_len: u32,
     pkg_name_offset: u32,
     pkg_name_size: u32,
+    /// Number of callbacks the kernel queues for the process before dropping
+    /// them. 0 selects the board default. Added in version 2.
+    callback_queue_len: u32,
+    /// Offset and length in bytes of the list of drivers the process may use,
+    /// made of `DriverPermission` entries. An empty list places no
+    /// restrictions. Added in version 3.
+    permissions_offset: u32,
+    permissions_size: u32,
     checksum: u32,
 }
 
+/// Version 2 of the header, which has no permissions.
+#[repr(C)]
+struct LoadInfoV2 {
+    version: u32,
+    total_size: u32,
+    entry_offset: u32,
+    rel_data_offset: u32,
+    rel_data_size: u32,
+    text_offset: u32,
+    text_size: u32,
+    got_offset: u32,
+    got_size: u32,
+    data_offset: u32,
+    data_size: u32,
+    bss_mem_offset: u32,
+    bss_size: u32,
+    min_stack_len: u32,
+    min_app_heap_len: u32,
+    min_kernel_heap_len: u32,
+    pkg_name_offset: u32,
+    pkg_name_size: u32,
+    callback_queue_len: u32,
+    checksum: u32,
+}
+
+/// Version 1 of the header, which has no `callback_queue_len`.
+#[repr(C)]
+struct LoadInfoV1 {
+    version: u32,
+    total_size: u32,
+    entry_offset: u32,
+    rel_data_offset: u32,
+    rel_data_size: u32,
+    text_offset: u32,
+    text_size: u32,
+    got_offset: u32,
+    got_size: u32,
+    data_offset: u32,
+    data_size: u32,
+    bss_mem_offset: u32,
+    bss_size: u32,
+    min_stack_len: u32,
+    min_app_heap_len: u32,
+    min_kernel_heap_len: u32,
+    pkg_name_offset: u32,
+    pkg_name_size: u32,
+    checksum: u32,
+}
+
+impl LoadInfo {
+    /// Size of the header in flash.
+    fn header_size(&self) -> usize {
+        match self.version {
+            1 => mem::size_of::<LoadInfoV1>(),
+            2 => mem::size_of::<LoadInfoV2>(),
+            _ => mem::size_of::<LoadInfo>(),
+        }
+    }
+
+    /// The depth of the process's callback queue, or `None` if the header
+    /// asks for more than `MAX_CALLBACK_LEN` or the queue would have no room
+    /// for the call to the process's entry point.
+    fn callback_len(&self, default_callback_len: usize) -> Option<usize> {
+        match self.callback_queue_len as usize {
+            0 if default_callback_len > 0 => Some(default_callback_len),
+            len if len > 0 && len <= MAX_CALLBACK_LEN => Some(len),
+            _ => None,
+        }
+    }
+}
+
 /// Converts a pointer to memory to a LoadInfo struct
 ///
 /// This function takes a pointer to arbitrary memory and Optionally returns a
 /// LoadInfo struct. This function will validate the header checksum, but does
-/// not perform sanity or security checking on the structure
-unsafe fn parse_and_validate_load_info(address: *const u8) -> Option<&'static LoadInfo> {
-    let load_info = &*(address as *const LoadInfo);
+/// not perform sanity or security checking on the structure. Version 1
+/// headers are converted to the current layout.
+unsafe fn parse_and_validate_load_info(address: *const u8) -> Option<LoadInfo> {
+    match read_volatile(address as *const u32) {
+        1 => {
+            let v1 = &*(address as *const LoadInfoV1);
+            let checksum = v1.version ^ v1.total_size ^ v1.entry_offset ^ v1.rel_data_offset ^
+                           v1.rel_data_size ^ v1.text_offset ^ v1.text_size ^
+                           v1.got_offset ^ v1.got_size ^ v1.data_offset ^
+                           v1.data_size ^ v1.bss_mem_offset ^ v1.bss_size ^
+                           v1.min_stack_len ^ v1.min_app_heap_len ^
+                           v1.min_kernel_heap_len ^ v1.pkg_name_offset ^ v1.pkg_name_size;
+
+            if checksum != v1.checksum {
+                return None;
+            }
 
-    if load_info.version != 1 {
-        return None;
+            Some(LoadInfo {
+                version: v1.version,
+                total_size: v1.total_size,
+                entry_offset: v1.entry_offset,
+                rel_data_offset: v1.rel_data_offset,
+                rel_data_size: v1.rel_data_size,
+                text_offset: v1.text_offset,
+                text_size: v1.text_size,
+                got_offset: v1.got_offset,
+                got_size: v1.got_size,
+                data_offset: v1.data_offset,
+                data_size: v1.data_size,
+                bss_mem_offset: v1.bss_mem_offset,
+                bss_size: v1.bss_size,
+                min_stack_len: v1.min_stack_len,
+                min_app_heap_len: v1.min_app_heap_len,
+                min_kernel_heap_len: v1.min_kernel_heap_len,
+                pkg_name_offset: v1.pkg_name_offset,
+                pkg_name_size: v1.pkg_name_size,
+                callback_queue_len: 0,
+                permissions_offset: 0,
+                permissions_size: 0,
+                checksum: v1.checksum,
+            })
+        }
+        2 => {
+            let v2 = &*(address as *const LoadInfoV2);
+            let checksum = v2.version ^ v2.total_size ^ v2.entry_offset ^ v2.rel_data_offset ^
+                           v2.rel_data_size ^ v2.text_offset ^ v2.text_size ^
+                           v2.got_offset ^ v2.got_size ^ v2.data_offset ^
+                           v2.data_size ^ v2.bss_mem_offset ^ v2.bss_size ^
+                           v2.min_stack_len ^ v2.min_app_heap_len ^
+                           v2.min_kernel_heap_len ^ v2.pkg_name_offset ^ v2.pkg_name_size ^
+                           v2.callback_queue_len;
+
+            if checksum != v2.checksum {
+                return None;
+            }
+
+            Some(LoadInfo {
+                version: v2.version,
+                total_size: v2.total_size,
+                entry_offset: v2.entry_offset,
+                rel_data_offset: v2.rel_data_offset,
+                rel_data_size: v2.rel_data_size,
+                text_offset: v2.text_offset,
+                text_size: v2.text_size,
+                got_offset: v2.got_offset,
+                got_size: v2.got_size,
+                data_offset: v2.data_offset,
+                data_size: v2.data_size,
+                bss_mem_offset: v2.bss_mem_offset,
+                bss_size: v2.bss_size,
+                min_stack_len: v2.min_stack_len,
+                min_app_heap_len: v2.min_app_heap_len,
+                min_kernel_heap_len: v2.min_kernel_heap_len,
+                pkg_name_offset: v2.pkg_name_offset,
+                pkg_name_size: v2.pkg_name_size,
+                callback_queue_len: v2.callback_queue_len,
+                permissions_offset: 0,
+                permissions_size: 0,
+                checksum: v2.checksum,
+            })
+        }
+        3 => {
+            let load_info = &*(address as *const LoadInfo);
+
+            let checksum =
+                load_info.version ^ load_info.total_size ^ load_info.entry_offset ^
+                load_info.rel_data_offset ^ load_info.rel_data_size ^ load_info.text_offset ^
+                load_info.text_size ^ load_info.got_offset ^
+                load_info.got_size ^ load_info.data_offset ^ load_info.data_size ^
+                load_info.bss_mem_offset ^ load_info.bss_size ^ load_info.min_stack_len ^
+                load_info.min_app_heap_len ^
+                load_info.min_kernel_heap_len ^ load_info.pkg_name_offset ^
+                load_info.pkg_name_size ^ load_info.callback_queue_len ^
+                load_info.permissions_offset ^ load_info.permissions_size;
+
+            if checksum != load_info.checksum {
+                return None;
+            }
+
+            Some(*load_info)
+        }
+        _ => None,
     }
+}
+
+/// Checks that every section described by `load_info` lies within the image,
+/// and that the permissions are a word-aligned array of entries.
+fn sections_in_bounds(load_info: &LoadInfo) -> bool {
+    let total_size = load_info.total_size as usize;
+    let sections = [(load_info.rel_data_offset, load_info.rel_data_size),
+                    (load_info.text_offset, load_info.text_size),
+                    (load_info.got_offset, load_info.got_size),
+                    (load_info.data_offset, load_info.data_size),
+                    (load_info.pkg_name_offset, load_info.pkg_name_size),
+                    (load_info.permissions_offset, load_info.permissions_size)];
+    let permissions_valid = load_info.permissions_offset % 4 == 0 &&
+                            load_info.permissions_size as usize %
+                            mem::size_of::<DriverPermission>() == 0;
+
+    total_size >= load_info.header_size() && (load_info.entry_offset as usize) < total_size &&
+    permissions_valid &&
+    sections.iter().all(|&(offset, size)| {
+        (offset as usize).checked_add(size as usize).map_or(false, |end| end <= total_size)
+    })
+}
+
+/// Checks that the GOT, data and BSS fit in the memory set aside for them in
+/// front of the stack, and that the entry point is a Thumb address. `load`
+/// panics on images that fail these checks.
+fn memory_layout_valid(app_flash_address: *const u8, load_info: &LoadInfo) -> bool {
+    let data_end = match load_info.bss_mem_offset.checked_add(load_info.bss_size) {
+        Some(data_end) => data_end,
+        None => return false,
+    };
+    let got_and_data_fit = load_info.got_size
+        .checked_add(load_info.data_size)
+        .map_or(false, |len| len <= data_end);
+    let init_fn = app_flash_address as usize + load_info.entry_offset as usize;
+
+    got_and_data_fit && init_fn & 0x1 == 1
+}
 
-    let checksum =
-        load_info.version ^ load_info.total_size ^ load_info.entry_offset ^
-        load_info.rel_data_offset ^ load_info.rel_data_size ^ load_info.text_offset ^
-        load_info.text_size ^ load_info.got_offset ^
-        load_info.got_size ^ load_info.data_offset ^ load_info.data_size ^
-        load_info.bss_mem_offset ^ load_info.bss_size ^ load_info.min_stack_len ^
-        load_info.min_app_heap_len ^
-        load_info.min_kernel_heap_len ^ load_info.pkg_name_offset ^ load_info.pkg_name_size;
+/// The amount of RAM a process needs, rounded up so it can be covered by a
+/// single MPU region.
+///
+/// The callback queue lives in the grant region on top of the kernel heap the
+/// app asks for.
+///
+/// Returns `None` if the amount does not fit in a `usize`.
+fn required_memory(load_info: &LoadInfo,
+                   mpu: &mpu::Capabilities,
+                   callback_len: usize)
+                   -> Option<usize> {
+    // The ring buffer of the queue has one spare entry, see `setup_grant_region`
+    let ring_size = callback_len.checked_add(1)
+        .and_then(|ring_len| ring_len.checked_mul(mem::size_of::<Task>()));
+    let callback_queue_len = match ring_size {
+        Some(len) => align8!(len),
+        None => return None,
+    };
+    let lens = [stack_bottom_offset(load_info),
+                align8!(load_info.min_stack_len as usize),
+                align8!(load_info.min_app_heap_len as usize),
+                align8!(load_info.min_kernel_heap_len as usize),
+                callback_queue_len];
+
+    lens.iter()
+        .fold(Some(0), |total: Option<usize>, &len| total.and_then(|t| t.checked_add(len)))
+        .map(|total| mpu.block_size(total))
+}
 
-    if checksum != load_info.checksum {
+/// The offset of the bottom of the stack from the start of process memory.
+///
+/// The data and BSS come first, followed by the stack guard, aligned to its
+/// size so an MPU region can cover it.
+fn stack_bottom_offset(load_info: &LoadInfo) -> usize {
+    let data_end = align8!((load_info.bss_mem_offset + load_info.bss_size) as usize);
+    let guard_start = (data_end + STACK_GUARD_LEN - 1) & !(STACK_GUARD_LEN - 1);
+    guard_start + STACK_GUARD_LEN
+}
+
+/// Validates an app image without loading it.
+///
+/// Checks the header checksum, that every section lies within the image, that
+/// the process's data fits in its memory, that the callback queue is no
+/// deeper than `MAX_CALLBACK_LEN` and that the image fits in the `flash_len`
+/// bytes at `app_flash_address`.
+/// Returns the size of the image in flash and the amount of RAM the process
+/// needs, which must be placed where `mpu` can cover it with one region.
+pub unsafe fn validate_image(app_flash_address: *const u8,
+                             flash_len: usize,
+                             mpu: &mpu::Capabilities,
+                             default_callback_len: usize)
+                             -> Option<(usize, usize)> {
+    if flash_len < mem::size_of::<LoadInfoV1>() {
         return None;
     }
+    parse_and_validate_load_info(app_flash_address).and_then(|load_info| {
+        if load_info.total_size as usize <= flash_len && sections_in_bounds(&load_info) &&
+           memory_layout_valid(app_flash_address, &load_info) {
+            load_info.callback_len(default_callback_len)
+                .and_then(|callback_len| required_memory(&load_info, mpu, callback_len))
+                .map(|memory_len| (load_info.total_size as usize, memory_len))
+        } else {
+            None
+        }
+    })
+}
 
-    Some(load_info)
+/// An entry of the permissions in a version 3 header: the process may use
+/// driver `driver_num`, and issue it commands `first_command` through
+/// `last_command`. A driver may have several entries to allow several ranges
+/// of commands.
+#[derive(Debug)]
+#[repr(C)]
+struct DriverPermission {
+    driver_num: u32,
+    first_command: u32,
+    last_command: u32,
 }
 
 #[derive(Default)]
@@ -177,6 +761,8 @@ pub struct Process<'a> {
     ///  |  ----   <- cur_stack
     ///  |
     ///  |  ----   <- app_mem_start
+    ///  | Guard
+    ///  |  ----
     ///  |  Data
     ///  |======== <- memory[0]
 
@@ -189,9 +775,28 @@ pub struct Process<'a> {
     cur_stack: *const u8,
     app_mem_start: *const u8,
 
+    /// The end of the part of the grant region that `alloc` hands out, just
+    /// below the task queue and the container pointers.
+    grant_blocks_end: *const u8,
+
+    /// Depth of the callback queue
+    callback_len: usize,
+
+    /// How many callbacks were dropped because the callback queue was full
+    dropped_callbacks: Cell<usize>,
+
     /// How many syscalls have occurred since the process started
     syscall_count: Cell<usize>,
 
+    /// Microseconds of CPU time the process has used
+    cpu_time_us: Cell<u64>,
+
+    /// How many times the scheduler has run the process
+    scheduled_count: Cell<usize>,
+
+    /// How many times the process was preempted at the end of its timeslice
+    timeslice_expirations: Cell<usize>,
+
     /// Process text segment
     text: &'static [u8],
 
@@ -205,6 +810,20 @@ pub struct Process<'a> {
     /// How to deal with Faults occuring in the process
     fault_response: FaultResponse,
 
+    /// How many times the process has been restarted after a fault
+    restart_count: usize,
+
+    /// Main loop passes left before a pending restart happens. `None` if no
+    /// restart is pending.
+    restart_backoff: Option<usize>,
+
+    /// The status the process passed to the exit syscall. `None` until the
+    /// process exits, or if the kernel terminated it.
+    exit_status: Option<usize>,
+
+    /// What made the process fault. `None` unless it is in the `Fault` state.
+    fault_kind: Option<FaultKind>,
+
     /// MPU regions are saved as a pointer-size pair.
     ///
     /// size is encoded as X where
@@ -217,22 +836,35 @@ pub struct Process<'a> {
     /// The pointer must be aligned to the size. E.g. if the size is 32 bytes, the pointer must be
     /// 32-byte aligned.
     ///
-    mpu_regions: [Cell<(*const u8, usize)>; 5],
+    mpu_regions: [Cell<(*const u8, usize)>; 4],
 
     tasks: RingBuffer<'a, Task>,
 
+    /// Distinguishes this process from earlier ones in the same slot. Changes
+    /// when the process restarts.
+    generation: usize,
+
+    /// The drivers the process may use, from its header. `None` places no
+    /// restrictions.
+    permissions: Option<&'static [DriverPermission]>,
+
     pub package_name: &'static str,
 }
 
-fn closest_power_of_two(mut num: u32) -> u32 {
-    num -= 1;
-    num |= num >> 1;
-    num |= num >> 2;
-    num |= num >> 4;
-    num |= num >> 8;
-    num |= num >> 16;
-    num += 1;
-    num
+/// Alignment of blocks allocated in the grant region.
+const GRANT_ALIGN: usize = 8;
+
+/// Owner of a block in the grant region that has been freed.
+const FREE_BLOCK: usize = !0;
+
+/// Header in front of every block allocated in the grant region. Blocks are
+/// laid out back to back from `kernel_memory_break` up, so the headers chain
+/// through the whole region.
+struct GrantBlock {
+    /// Size of the block, including the header.
+    size: usize,
+    /// The container the block was allocated for, or `FREE_BLOCK`.
+    owner: usize,
 }
 
 // Stores the current number of callbacks enqueued + processes in Running state
@@ -243,17 +875,138 @@ pub fn processes_blocked() -> bool {
 }
 
 impl<'a> Process<'a> {
-    pub fn schedule_ipc(&mut self, from: AppId, cb_type: IPCType) {
-        unsafe {
-            HAVE_WORK.set(HAVE_WORK.get() + 1);
+    pub fn schedule_ipc(&mut self, from: AppId, cb_type: IPCType) -> bool {
+        self.enqueue_task(Task::IPC((from, cb_type)))
+    }
+
+    /// Queues a task for the process.
+    ///
+    /// Tasks for a stopped process are kept until it is resumed, but do not
+    /// count as work for the kernel. Faulted and terminated processes accept
+    /// no tasks.
+    fn enqueue_task(&mut self, task: Task) -> bool {
+        let enqueued = match self.state {
+            State::Running | State::Yielded => {
+                let enqueued = self.tasks.enqueue(task);
+                if enqueued {
+                    unsafe {
+                        HAVE_WORK.set(HAVE_WORK.get() + 1);
+                    }
+                }
+                enqueued
+            }
+            State::StoppedRunning | State::StoppedYielded => self.tasks.enqueue(task),
+            State::Fault | State::Terminated => return false,
+        };
+        if !enqueued {
+            self.dropped_callbacks.set(self.dropped_callbacks.get() + 1);
         }
-        self.tasks.enqueue(Task::IPC((from, cb_type)));
+        enqueued
+    }
+
+    /// How many callbacks were dropped because the callback queue was full.
+    pub fn dropped_callbacks(&self) -> usize {
+        self.dropped_callbacks.get()
     }
 
     pub fn current_state(&self) -> State {
         self.state
     }
 
+    /// Whether the process has work for the scheduler: it is running or has
+    /// queued tasks.
+    ///
+    /// A process waiting to be restarted is not ready. The main loop restarts
+    /// it once its backoff has elapsed.
+    pub fn ready(&self) -> bool {
+        match self.state {
+            State::Running => true,
+            State::Yielded => self.tasks.has_elements(),
+            State::Fault | State::StoppedRunning | State::StoppedYielded |
+            State::Terminated => false,
+        }
+    }
+
+    /// Stops the process. Fails unless it is running or yielded.
+    pub fn stop(&mut self) -> Result<(), Error> {
+        let queued = self.tasks.len();
+        let (work, state) = match self.state {
+            State::Running => (queued + 1, State::StoppedRunning),
+            State::Yielded => (queued, State::StoppedYielded),
+            _ => return Err(Error::InvalidState),
+        };
+        unsafe {
+            HAVE_WORK.set(HAVE_WORK.get() - work);
+        }
+        self.state = state;
+        Ok(())
+    }
+
+    /// Resumes a stopped process in the state it was stopped in. Fails if
+    /// it is not stopped.
+    pub fn resume(&mut self) -> Result<(), Error> {
+        let queued = self.tasks.len();
+        let (work, state) = match self.state {
+            State::StoppedRunning => (queued + 1, State::Running),
+            State::StoppedYielded => (queued, State::Yielded),
+            _ => return Err(Error::InvalidState),
+        };
+        unsafe {
+            HAVE_WORK.set(HAVE_WORK.get() + work);
+        }
+        self.state = state;
+        Ok(())
+    }
+
+    /// Ends the process.
+    ///
+    /// Queued callbacks are dropped, the grant region is cleared so no
+    /// capsule sees the process anymore, and memory shared with other
+    /// processes over IPC is unmapped from them. `exit_status` is the value
+    /// the process passed to the exit syscall, if it exited on its own.
+    pub fn terminate(&mut self, exit_status: Option<usize>) {
+        let work = match self.state {
+            State::Running => self.tasks.len() + 1,
+            State::Yielded => self.tasks.len(),
+            State::Fault if self.restart_backoff.is_some() => 1,
+            State::Terminated => return,
+            _ => 0,
+        };
+        unsafe {
+            HAVE_WORK.set(HAVE_WORK.get() - work);
+        }
+        while self.tasks.dequeue().is_some() {}
+        self.restart_backoff = None;
+        self.exit_status = exit_status;
+        self.state = State::Terminated;
+        // Callbacks, buffers and grant state of the process must not outlive
+        // it
+        self.generation = next_generation();
+
+        unsafe {
+            let (kernel_memory_break, tasks) = setup_grant_region(self.memory, self.callback_len);
+            self.kernel_memory_break = kernel_memory_break;
+            self.grant_blocks_end = kernel_memory_break;
+            self.tasks = tasks;
+        }
+
+        for region in self.mpu_regions.iter() {
+            region.set((ptr::null(), 0));
+        }
+        let (start, end) = (self.mem_start(), self.mem_end());
+        let procs = unsafe { &PROCS };
+        for process in procs.iter().filter_map(|p| p.as_ref()) {
+            if process.mem_start() != start {
+                process.remove_mpu_regions_in(start, end);
+            }
+        }
+    }
+
+    /// The status the process passed to the exit syscall.
+    pub fn exit_status(&self) -> Option<usize> {
+        self.exit_status
+    }
+
     pub fn yield_state(&mut self) {
         if self.state == State::Running {
             self.state = State::Yielded;
@@ -263,27 +1016,155 @@ impl<'a> Process<'a> {
         }
     }
 
-    pub unsafe fn fault_state(&mut self) {
+    pub unsafe fn fault_state(&mut self, kind: FaultKind) {
         write_volatile(&mut APP_FAULT, 0);
+        self.fault_kind = Some(kind);
+
+        // The process no longer has work: it is not running and its queued
+        // tasks will never be delivered.
+        if self.state == State::Running {
+            HAVE_WORK.set(HAVE_WORK.get() - 1);
+        }
+        while self.dequeue_task().is_some() {}
         self.state = State::Fault;
 
         match self.fault_response {
             FaultResponse::Panic => {
                 // process faulted. Panic and print status
-                panic!("Process {} had a fault", self.package_name);
+                match kind {
+                    FaultKind::Hardware => panic!("Process {} had a fault", self.package_name),
+                    FaultKind::StackOverflow => {
+                        panic!("Process {} overflowed its stack", self.package_name)
+                    }
+                }
+            }
+            FaultResponse::Stop => {}
+            FaultResponse::Restart(policy) => {
+                if self.restart_count >= policy.max_restarts {
+                    if policy.panic_when_exhausted {
+                        panic!("Process {} had a fault ({:?}) and exceeded its restart limit ({})",
+                               self.package_name,
+                               kind,
+                               policy.max_restarts);
+                    }
+                } else if policy.backoff == 0 {
+                    self.restart_count += 1;
+                    self.restart();
+                } else {
+                    // Keep the kernel loop spinning until the restart happens
+                    HAVE_WORK.set(HAVE_WORK.get() + 1);
+                    self.restart_backoff = Some(policy.backoff);
+                }
             }
-            FaultResponse::Restart => {
-                //XXX: unimplemented
-                panic!("Process {} had a fault and could not be restarted", self.package_name);
-                /*
-                // HAVE_WORK is really screwed up in this case
-                // the tasks ring buffer needs to be cleared
-                // need to re-load() the app
-                */
+        }
+    }
+
+    /// Counts down a pending restart, restarting the process once its backoff
+    /// has elapsed. Called once per pass of the kernel main loop. Does nothing
+    /// unless a restart is pending.
+    pub unsafe fn service_restart(&mut self) {
+        match self.restart_backoff {
+            Some(0) => {
+                HAVE_WORK.set(HAVE_WORK.get() - 1);
+                self.restart_count += 1;
+                self.restart();
             }
+            Some(passes) => self.restart_backoff = Some(passes - 1),
+            None => {}
         }
     }
 
+    /// Reloads the process from flash and queues its entry point.
+    ///
+    /// The data, BSS and GOT are copied out of flash again, the stack and heap
+    /// are reset and the grant region is cleared. Any grant state capsules
+    /// held for the process is lost.
+    unsafe fn restart(&mut self) {
+        self.restart_backoff = None;
+        self.exit_status = None;
+        self.fault_kind = None;
+
+        let load_info = match parse_and_validate_load_info(self.text.as_ptr()) {
+            Some(load_info) => load_info,
+            None => return,
+        };
+        let load_result = match load(&load_info,
+                                     self.text.as_ptr(),
+                                     self.memory.as_mut_ptr(),
+                                     self.memory.len()) {
+            Some(load_result) => load_result,
+            None => return,
+        };
+
+        let stack_len = align8!(load_info.min_stack_len);
+        let stack_heap_boundary = self.memory
+            .as_ptr()
+            .offset((load_result.data_len + stack_len) as isize);
+        let (kernel_memory_break, tasks) = setup_grant_region(self.memory, self.callback_len);
+
+        self.kernel_memory_break = kernel_memory_break;
+        self.grant_blocks_end = kernel_memory_break;
+        self.app_memory_break = stack_heap_boundary;
+        self.stack_heap_boundary = stack_heap_boundary;
+        self.cur_stack = stack_heap_boundary;
+        self.app_mem_start = load_result.app_mem_start;
+        self.tasks = tasks;
+        self.paint_stack();
+
+        self.stored_regs = Default::default();
+        self.yield_pc = load_result.init_fn;
+        self.psr = 0x01000000;
+        for region in self.mpu_regions.iter() {
+            region.set((ptr::null(), 0));
+        }
+
+        // Callbacks and buffers the process handed out before belong to
+        // the old instance
+        self.generation = next_generation();
+        let init_fn = Task::FunctionCall(FunctionCall {
+            pc: load_result.init_fn,
+            r0: load_result.app_mem_start as usize,
+            r1: self.app_memory_break as usize,
+            r2: self.kernel_memory_break as usize,
+            r3: 0,
+        });
+        if !self.tasks.enqueue(init_fn) {
+            // Only a queue without room for a single callback is full here
+            self.state = State::Fault;
+            return;
+        }
+        self.state = State::Yielded;
+        HAVE_WORK.set(HAVE_WORK.get() + 1);
+    }
+
+    /// How many times the process has been restarted after a fault.
+    pub fn restart_count(&self) -> usize {
+        self.restart_count
+    }
+
+    /// The generation of the process, see `AppId`.
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Whether the header of the process lets it use driver `driver_num`.
+    pub fn may_use_driver(&self, driver_num: usize) -> bool {
+        self.permissions.map_or(true, |permissions| {
+            permissions.iter().any(|p| p.driver_num as usize == driver_num)
+        })
+    }
+
+    /// Whether the header of the process lets it issue command `minor` to
+    /// driver `driver_num`.
+    pub fn may_command(&self, driver_num: usize, minor: usize) -> bool {
+        self.permissions.map_or(true, |permissions| {
+            permissions.iter().any(|p| {
+                p.driver_num as usize == driver_num && p.first_command as usize <= minor &&
+                minor <= p.last_command as usize
+            })
+        })
+    }
+
     pub fn dequeue_task(&mut self) -> Option<Task> {
         self.tasks.dequeue().map(|cb| {
             unsafe {
@@ -293,6 +1174,14 @@ impl<'a> Process<'a> {
         })
     }
 
+    pub fn flash_start(&self) -> *const u8 {
+        self.text.as_ptr()
+    }
+
+    pub fn flash_end(&self) -> *const u8 {
+        unsafe { self.text.as_ptr().offset(self.text.len() as isize) }
+    }
+
     pub fn mem_start(&self) -> *const u8 {
         self.memory.as_ptr()
     }
@@ -301,50 +1190,169 @@ impl<'a> Process<'a> {
         unsafe { self.memory.as_ptr().offset(self.memory.len() as isize) }
     }
 
-    pub fn setup_mpu<MPU: mpu::MPU>(&self, mpu: &MPU) {
-        let data_start = self.memory.as_ptr() as usize;
-        let data_len = (32 - self.memory.len().leading_zeros()) as u32;
+    /// The start of the grant region. Memory from here to `mem_end` belongs
+    /// to the kernel.
+    pub fn grant_start(&self) -> *const u8 {
+        self.kernel_memory_break
+    }
 
-        let text_start = self.text.as_ptr() as usize;
-        let text_len = ((32 - self.text.len().leading_zeros()) - 2) as u32;
+    /// The bottom of the app's stack, above its data, bss and the stack
+    /// guard.
+    pub fn app_mem_start(&self) -> *const u8 {
+        self.app_mem_start
+    }
 
-        let mut grant_size = unsafe {
-            self.memory.as_ptr().offset(self.memory.len() as isize) as u32 -
-            (self.kernel_memory_break as u32)
-        };
-        grant_size = closest_power_of_two(grant_size);
-        let grant_base = unsafe {
-            self.memory
-                .as_ptr()
-                .offset(self.memory.len() as isize)
-                .offset(-(grant_size as isize))
-        };
-        let mgrant_size = grant_size.trailing_zeros() - 1;
+    /// Whether the stack grew past its bottom: either the stack pointer is
+    /// below it, or something wrote to the stack guard.
+    pub fn stack_overflowed(&self) -> bool {
+        if self.cur_stack < self.app_mem_start {
+            return true;
+        }
+        let guard = self.stack_guard_start() as *const usize;
+        (0..STACK_GUARD_LEN / mem::size_of::<usize>())
+            .any(|i| unsafe { read_volatile(guard.offset(i as isize)) } != STACK_CANARY)
+    }
 
-        // Data segment read/write/execute
-        mpu.set_mpu(0,
-                    data_start as u32,
-                    data_len,
-                    mpu::ExecutePermission::ExecutionPermitted,
-                    mpu::AccessPermission::ReadWrite);
+    /// Fills the unused part of the stack with `STACK_PAINT`.
+    unsafe fn paint_stack(&mut self) {
+        let bottom = self.app_mem_start as *mut usize;
+        let words = (self.cur_stack as usize - bottom as usize) / mem::size_of::<usize>();
+        for i in 0..words {
+            write_volatile(bottom.offset(i as isize), STACK_PAINT);
+        }
+    }
+
+    /// The deepest the stack has grown since the process started, in bytes.
+    ///
+    /// Found by looking for the lowest word of the stack that no longer holds
+    /// the paint, so it misses stack the process skipped without writing.
+    pub fn stack_high_water_mark(&self) -> usize {
+        let bottom = self.app_mem_start as *const usize;
+        let words = (self.stack_heap_boundary as usize - bottom as usize) /
+                    mem::size_of::<usize>();
+        let painted = (0..words)
+            .take_while(|&i| unsafe { read_volatile(bottom.offset(i as isize)) } == STACK_PAINT)
+            .count();
+        let deepest = self.stack_heap_boundary as usize - self.app_mem_start as usize -
+                      painted * mem::size_of::<usize>();
+        let current = self.stack_heap_boundary as usize - self.cur_stack as usize;
+        cmp::max(deepest, current)
+    }
+
+    fn stack_guard_start(&self) -> *const u8 {
+        unsafe { self.app_mem_start.offset(-(STACK_GUARD_LEN as isize)) }
+    }
+
+    /// What made the process fault, if it is in the `Fault` state.
+    pub fn fault_kind(&self) -> Option<FaultKind> {
+        self.fault_kind
+    }
+
+    pub fn stack_heap_boundary(&self) -> *const u8 {
+        self.stack_heap_boundary
+    }
+
+    /// The end of the app's heap.
+    pub fn app_memory_break(&self) -> *const u8 {
+        self.app_memory_break
+    }
+
+    pub fn stack_size(&self) -> usize {
+        self.stack_heap_boundary as usize - self.app_mem_start as usize
+    }
+
+    /// The number of bytes between the app's break and the grant region, which
+    /// either the heap or the grant region can still grow into.
+    pub fn free_memory(&self) -> usize {
+        self.kernel_memory_break as usize - self.app_memory_break as usize
+    }
+
+    /// Moves the boundary between the stack and the heap, e.g. for a runtime
+    /// that sizes its stack at startup.
+    ///
+    /// The boundary must be word aligned and must not reach into the grant
+    /// region. The stack grows down from the boundary, so it can only move
+    /// down while the stack is empty. If the heap ends below the new boundary,
+    /// the app's break is moved up to it.
+    pub fn set_stack_heap_boundary(&mut self, boundary: *const u8) -> Result<(), Error> {
+        let stack_empty = self.cur_stack == self.stack_heap_boundary;
+        if boundary as usize % 4 != 0 || boundary < self.app_mem_start ||
+           boundary > self.kernel_memory_break {
+            Err(Error::AddressOutOfBounds)
+        } else if boundary < self.stack_heap_boundary && !stack_empty {
+            // The frames below the old boundary would become heap
+            Err(Error::OutOfMemory)
+        } else {
+            if stack_empty {
+                self.cur_stack = boundary;
+            }
+            self.stack_heap_boundary = boundary;
+            if self.app_memory_break < boundary {
+                self.app_memory_break = boundary;
+            }
+            Ok(())
+        }
+    }
+
+    pub fn setup_mpu<MPU: mpu::MPU>(&self, mpu: &MPU) {
+        let capabilities = mpu.capabilities();
+
+        let text_start = self.text.as_ptr() as usize;
+        let text_len = (32 - (self.text.len() as u32).leading_zeros()) - 2;
+
+        // Data segment read/write/execute. The process was placed so that one
+        // region covers its memory exactly.
+        if let Some(data) = capabilities.region(self.mem_start() as usize,
+                                                self.mem_end() as usize) {
+            mpu.set_mpu(0,
+                        data.base as u32,
+                        data.encoded_size(),
+                        data.subregion_disable,
+                        mpu::ExecutePermission::ExecutionPermitted,
+                        mpu::AccessPermission::ReadWrite);
+        }
         // Text segment read/execute (no write)
         mpu.set_mpu(1,
                     text_start as u32,
                     text_len,
+                    0,
                     mpu::ExecutePermission::ExecutionPermitted,
                     mpu::AccessPermission::ReadOnly);
 
-        // Disallow access to grant region
-        mpu.set_mpu(2,
-                    grant_base as u32,
-                    mgrant_size,
-                    mpu::ExecutePermission::ExecutionNotPermitted,
-                    mpu::AccessPermission::PrivilegedOnly);
+        // Disallow access to grant region. The region may reach below the
+        // kernel memory break to the start of a subregion, which takes that
+        // memory from the app rather than exposing grants.
+        if let Some(grant) = capabilities.region(self.kernel_memory_break as usize,
+                                                 self.mem_end() as usize) {
+            mpu.set_mpu(2,
+                        grant.base as u32,
+                        grant.encoded_size(),
+                        grant.subregion_disable,
+                        mpu::ExecutePermission::ExecutionNotPermitted,
+                        mpu::AccessPermission::PrivilegedOnly);
+        }
+
+        // Stack guard, so a stack that overflows faults before it reaches the
+        // data. The canary in the guard catches overflows if the MPU cannot
+        // cover the guard alone.
+        if capabilities.num_regions > 3 && capabilities.min_region_size <= STACK_GUARD_LEN {
+            if let Some(guard) = capabilities.region(self.stack_guard_start() as usize,
+                                                     self.app_mem_start as usize) {
+                mpu.set_mpu(3,
+                            guard.base as u32,
+                            guard.encoded_size(),
+                            guard.subregion_disable,
+                            mpu::ExecutePermission::ExecutionNotPermitted,
+                            mpu::AccessPermission::PrivilegedOnly);
+            }
+        }
 
-        for (i, region) in self.mpu_regions.iter().enumerate() {
-            mpu.set_mpu((i + 3) as u32,
+        let num_shared = capabilities.num_regions.saturating_sub(4);
+        for (i, region) in self.mpu_regions.iter().enumerate().take(num_shared) {
+            mpu.set_mpu((i + 4) as u32,
                         region.get().0 as u32,
                         region.get().1 as u32,
+                        0,
                         mpu::ExecutePermission::ExecutionPermitted,
                         mpu::AccessPermission::ReadWrite);
         }
@@ -369,70 +1377,66 @@ impl<'a> Process<'a> {
         return false;
     }
 
+    /// Clears any MPU region that exposes memory between `start` and `end`.
+    pub fn remove_mpu_regions_in(&self, start: *const u8, end: *const u8) {
+        for region in self.mpu_regions.iter() {
+            let base = region.get().0;
+            if base >= start && base < end {
+                region.set((ptr::null(), 0));
+            }
+        }
+    }
+
+    /// Creates a process from the app image at `app_flash_address`, with its
+    /// memory at the first place in the remaining app memory where `mpu` can
+    /// cover it with one region.
+    ///
+    /// Returns the process, the size of the image in flash and the amount of
+    /// app memory used, including any padding skipped to place the process.
     pub unsafe fn create(app_flash_address: *const u8,
                          remaining_app_memory: *mut u8,
                          remaining_app_memory_size: usize,
-                         fault_response: FaultResponse)
+                         mpu: &mpu::Capabilities,
+                         fault_response: FaultResponse,
+                         default_callback_len: usize)
                          -> (Option<Process<'a>>, usize, usize) {
         if let Some(load_info) = parse_and_validate_load_info(app_flash_address) {
             let app_flash_size = load_info.total_size as usize;
 
+            let callback_len = match load_info.callback_len(default_callback_len) {
+                Some(callback_len) => callback_len,
+                None => return (None, 0, 0),
+            };
+            let app_slice_size = match required_memory(&load_info, mpu, callback_len) {
+                Some(app_slice_size) => app_slice_size,
+                None => return (None, 0, 0),
+            };
+            let padding = mpu.place(remaining_app_memory as usize, app_slice_size) -
+                          remaining_app_memory as usize;
+            let app_memory_start = remaining_app_memory.offset(padding as isize);
+
             // Load the process into memory
             if let Some(load_result) =
-                load(load_info,
+                load(&load_info,
                      app_flash_address,
-                     remaining_app_memory,
-                     remaining_app_memory_size) {
+                     app_memory_start,
+                     remaining_app_memory_size.saturating_sub(padding)) {
                 let stack_len = align8!(load_info.min_stack_len);
-                let app_heap_len = align8!(load_info.min_app_heap_len);
-                let kernel_heap_len = align8!(load_info.min_kernel_heap_len);
 
-                let app_slice_size =
-                    closest_power_of_two(load_result.data_len + stack_len + app_heap_len +
-                                         kernel_heap_len) as usize;
-                // TODO round app_slice_size up to a closer MPU unit.
-                // This is a very conservative approach that rounds up to power of
-                // two. We should be able to make this closer to what we actually need.
-
-                if app_slice_size > remaining_app_memory_size {
+                if padding + app_slice_size > remaining_app_memory_size {
                     panic!("{:?} failed to load. Insufficient memory. Requested {} have {}",
                            load_result.package_name,
-                           app_slice_size,
+                           padding + app_slice_size,
                            remaining_app_memory_size);
                 }
 
-                let app_memory = slice::from_raw_parts_mut(remaining_app_memory, app_slice_size);
+                let app_memory = slice::from_raw_parts_mut(app_memory_start, app_slice_size);
                 let stack_heap_boundary = app_memory.as_mut_ptr()
                     .offset((load_result.data_len + stack_len) as isize);
                 let app_memory_break = stack_heap_boundary;
 
                 // Set up initial grant region
-                let mut kernel_memory_break = app_memory.as_mut_ptr()
-                    .offset(app_memory.len() as isize);
-
-                // make room for container pointers
-                let pointer_size = mem::size_of::<*const usize>();
-                let num_ctrs = read_volatile(&container::CONTAINER_COUNTER);
-                let container_ptrs_size = num_ctrs * pointer_size;
-                kernel_memory_break = kernel_memory_break.offset(-(container_ptrs_size as isize));
-
-                // set all pointers to null
-                let opts = slice::from_raw_parts_mut(kernel_memory_break as *mut *const usize,
-                                                     num_ctrs);
-                for opt in opts.iter_mut() {
-                    *opt = ptr::null()
-                }
-
-                // Allocate memory for callback ring buffer
-                let callback_size = mem::size_of::<Task>();
-                let callback_len = 10;
-                let callback_offset = callback_len * callback_size;
-                kernel_memory_break = kernel_memory_break.offset(-(callback_offset as isize));
-
-                // Set up ring buffer
-                let callback_buf = slice::from_raw_parts_mut(kernel_memory_break as *mut Task,
-                                                             callback_len);
-                let tasks = RingBuffer::new(callback_buf);
+                let (kernel_memory_break, tasks) = setup_grant_region(app_memory, callback_len);
 
                 let mut process = Process {
                     memory: app_memory,
@@ -442,8 +1446,15 @@ impl<'a> Process<'a> {
                     stack_heap_boundary: stack_heap_boundary,
                     cur_stack: stack_heap_boundary,
                     app_mem_start: load_result.app_mem_start,
+                    grant_blocks_end: kernel_memory_break,
+
+                    callback_len: callback_len,
+                    dropped_callbacks: Cell::new(0),
 
                     syscall_count: Cell::new(0),
+                    cpu_time_us: Cell::new(0),
+                    scheduled_count: Cell::new(0),
+                    timeslice_expirations: Cell::new(0),
 
                     text: slice::from_raw_parts(app_flash_address, app_flash_size),
 
@@ -454,13 +1465,18 @@ impl<'a> Process<'a> {
 
                     state: State::Yielded,
                     fault_response: fault_response,
+                    restart_count: 0,
+                    restart_backoff: None,
+                    exit_status: None,
+                    fault_kind: None,
 
                     mpu_regions: [Cell::new((ptr::null(), 0)),
-                                  Cell::new((ptr::null(), 0)),
                                   Cell::new((ptr::null(), 0)),
                                   Cell::new((ptr::null(), 0)),
                                   Cell::new((ptr::null(), 0))],
                     tasks: tasks,
+                    generation: next_generation(),
+                    permissions: load_result.permissions,
                     package_name: load_result.package_name,
                 };
 
@@ -471,17 +1487,22 @@ impl<'a> Process<'a> {
                            load_result.init_fn);
                 }
 
-                process.tasks.enqueue(Task::FunctionCall(FunctionCall {
+                process.paint_stack();
+                let init_fn = Task::FunctionCall(FunctionCall {
                     pc: load_result.init_fn,
                     r0: load_result.app_mem_start as usize,
                     r1: process.app_memory_break as usize,
                     r2: process.kernel_memory_break as usize,
                     r3: 0,
-                }));
+                });
+                // The app could never start without room for its entry point
+                if !process.tasks.enqueue(init_fn) {
+                    return (None, 0, 0);
+                }
 
                 HAVE_WORK.set(HAVE_WORK.get() + 1);
 
-                return (Some(process), app_flash_size, app_slice_size);
+                return (Some(process), app_flash_size, padding + app_slice_size);
             }
         }
         (None, 0, 0)
@@ -511,17 +1532,111 @@ impl<'a> Process<'a> {
         buf_start_addr >= self.mem_start() && buf_end_addr <= self.mem_end()
     }
 
-    pub unsafe fn alloc(&mut self, size: usize) -> Option<&mut [u8]> {
-        let new_break = self.kernel_memory_break.offset(-(size as isize));
+    /// Allocates `size` bytes in the grant region on behalf of container
+    /// `container_num`.
+    ///
+    /// Blocks freed earlier are reused first fit. Otherwise the grant region
+    /// grows down towards the app's memory break.
+    pub unsafe fn alloc(&mut self, size: usize, container_num: usize) -> Option<&mut [u8]> {
+        let header_size = mem::size_of::<GrantBlock>();
+        let block_size = align8!(size + header_size);
+
+        let mut block = self.kernel_memory_break as *mut GrantBlock;
+        while (block as *const u8) < self.grant_blocks_end {
+            let free_size = (*block).size;
+            if (*block).owner == FREE_BLOCK && free_size >= block_size {
+                if free_size - block_size >= header_size + GRANT_ALIGN {
+                    let rest = (block as *mut u8).offset(block_size as isize) as *mut GrantBlock;
+                    (*rest).size = free_size - block_size;
+                    (*rest).owner = FREE_BLOCK;
+                    (*block).size = block_size;
+                }
+                (*block).owner = container_num;
+                let data = (block as *mut u8).offset(header_size as isize);
+                return Some(slice::from_raw_parts_mut(data, size));
+            }
+            block = (block as *mut u8).offset(free_size as isize) as *mut GrantBlock;
+        }
+
+        let new_break = self.kernel_memory_break.offset(-(block_size as isize));
         if new_break < self.app_memory_break {
             None
         } else {
             self.kernel_memory_break = new_break;
-            Some(slice::from_raw_parts_mut(new_break as *mut u8, size))
+            let block = new_break as *mut GrantBlock;
+            (*block).size = block_size;
+            (*block).owner = container_num;
+            let data = (block as *mut u8).offset(header_size as isize);
+            Some(slice::from_raw_parts_mut(data, size))
+        }
+    }
+
+    /// Returns memory allocated with `alloc` to the grant region.
+    ///
+    /// Pointers that were not returned by `alloc`, like app buffers shared
+    /// with ALLOW, and the roots of containers are ignored.
+    pub unsafe fn free<T>(&mut self, ptr: *mut T) {
+        let ptr = ptr as *const u8;
+        if ptr < self.kernel_memory_break || ptr >= self.grant_blocks_end {
+            return;
+        }
+        let num_ctrs = read_volatile(&container::CONTAINER_COUNTER);
+        for container_num in 0..num_ctrs {
+            if *self.container_ptr::<u8>(container_num) as *const u8 == ptr {
+                return;
+            }
+        }
+
+        let header_size = mem::size_of::<GrantBlock>();
+        let mut block = self.kernel_memory_break as *mut GrantBlock;
+        while (block as *const u8) < self.grant_blocks_end {
+            let data = (block as *const u8).offset(header_size as isize);
+            if data == ptr {
+                if (*block).owner != FREE_BLOCK {
+                    (*block).owner = FREE_BLOCK;
+                    self.coalesce_grant_blocks();
+                }
+                return;
+            }
+            block = (block as *mut u8).offset((*block).size as isize) as *mut GrantBlock;
+        }
+    }
+
+    /// Merges neighbouring free blocks and gives a free block at the bottom of
+    /// the grant region back to the app.
+    unsafe fn coalesce_grant_blocks(&mut self) {
+        let mut block = self.kernel_memory_break as *mut GrantBlock;
+        while (block as *const u8) < self.grant_blocks_end {
+            let next = (block as *mut u8).offset((*block).size as isize) as *mut GrantBlock;
+            if (*block).owner == FREE_BLOCK && (next as *const u8) < self.grant_blocks_end &&
+               (*next).owner == FREE_BLOCK {
+                (*block).size += (*next).size;
+            } else {
+                block = next;
+            }
+        }
+
+        let lowest = self.kernel_memory_break as *const GrantBlock;
+        if (lowest as *const u8) < self.grant_blocks_end && (*lowest).owner == FREE_BLOCK {
+            self.kernel_memory_break = self.kernel_memory_break.offset((*lowest).size as isize);
         }
     }
 
-    pub unsafe fn free<T>(&mut self, _: *mut T) {}
+    /// The number of grant region bytes allocated for container
+    /// `container_num`, including allocator overhead.
+    pub fn grant_bytes(&self, container_num: usize) -> usize {
+        let mut bytes = 0;
+        let mut block = self.kernel_memory_break as *const GrantBlock;
+        unsafe {
+            while (block as *const u8) < self.grant_blocks_end {
+                if (*block).owner == container_num {
+                    bytes += (*block).size;
+                }
+                block = (block as *const u8).offset((*block).size as isize) as *const GrantBlock;
+            }
+        }
+        bytes
+    }
 
     unsafe fn container_ptr<T>(&self, container_num: usize) -> *mut *mut T {
         let container_num = container_num as isize;
@@ -537,7 +1652,7 @@ impl<'a> Process<'a> {
                                                      -> Option<*mut T> {
         let ctr_ptr = self.container_ptr::<T>(container_num);
         if (*ctr_ptr).is_null() {
-            self.alloc(mem::size_of::<T>()).map(|root_arr| {
+            self.alloc(mem::size_of::<T>(), container_num).map(|root_arr| {
                 let root_ptr = root_arr.as_mut_ptr() as *mut T;
                 *root_ptr = Default::default();
                 write_volatile(ctr_ptr, root_ptr);
@@ -611,6 +1726,54 @@ impl<'a> Process<'a> {
         self.syscall_count.set(self.syscall_count.get() + 1);
     }
 
+    /// How many system calls the process has made.
+    pub fn syscall_count(&self) -> usize {
+        self.syscall_count.get()
+    }
+
+    /// The number of tasks waiting in the process's callback queue.
+    pub fn queued_task_count(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// The task `index` places from the front of the callback queue.
+    pub fn queued_task(&self, index: usize) -> Option<Task> {
+        self.tasks.get(index)
+    }
+
+    /// The address the process continues at when it is next scheduled after
+    /// yielding.
+    pub fn yield_pc(&self) -> usize {
+        self.yield_pc
+    }
+
+    pub fn incr_scheduled_count(&self) {
+        self.scheduled_count.set(self.scheduled_count.get() + 1);
+    }
+
+    pub fn incr_timeslice_expirations(&self) {
+        self.timeslice_expirations.set(self.timeslice_expirations.get() + 1);
+    }
+
+    pub fn add_cpu_time(&self, us: u64) {
+        self.cpu_time_us.set(self.cpu_time_us.get() + us);
+    }
+
+    /// Microseconds of CPU time the process has used since it was created.
+    pub fn cpu_time_us(&self) -> u64 {
+        self.cpu_time_us.get()
+    }
+
+    /// How many times the scheduler has run the process.
+    pub fn scheduled_count(&self) -> usize {
+        self.scheduled_count.get()
+    }
+
+    /// How many times the process was preempted at the end of its timeslice.
+    pub fn timeslice_expirations(&self) -> usize {
+        self.timeslice_expirations.get()
+    }
+
     pub fn sp(&self) -> usize {
         self.cur_stack as usize
     }
@@ -660,177 +1823,39 @@ impl<'a> Process<'a> {
         unsafe { read_volatile(pspr.offset(3)) }
     }
 
-    pub unsafe fn fault_str<W: Write>(&mut self, writer: &mut W) {
-        let _ccr = SCB_REGISTERS[0];
-        let cfsr = SCB_REGISTERS[1];
-        let hfsr = SCB_REGISTERS[2];
-        let mmfar = SCB_REGISTERS[3];
-        let bfar = SCB_REGISTERS[4];
-
-        let iaccviol = (cfsr & 0x01) == 0x01;
-        let daccviol = (cfsr & 0x02) == 0x02;
-        let munstkerr = (cfsr & 0x08) == 0x08;
-        let mstkerr = (cfsr & 0x10) == 0x10;
-        let mlsperr = (cfsr & 0x20) == 0x20;
-        let mmfarvalid = (cfsr & 0x80) == 0x80;
-
-        let ibuserr = ((cfsr >> 8) & 0x01) == 0x01;
-        let preciserr = ((cfsr >> 8) & 0x02) == 0x02;
-        let impreciserr = ((cfsr >> 8) & 0x04) == 0x04;
-        let unstkerr = ((cfsr >> 8) & 0x08) == 0x08;
-        let stkerr = ((cfsr >> 8) & 0x10) == 0x10;
-        let lsperr = ((cfsr >> 8) & 0x20) == 0x20;
-        let bfarvalid = ((cfsr >> 8) & 0x80) == 0x80;
-
-        let undefinstr = ((cfsr >> 16) & 0x01) == 0x01;
-        let invstate = ((cfsr >> 16) & 0x02) == 0x02;
-        let invpc = ((cfsr >> 16) & 0x04) == 0x04;
-        let nocp = ((cfsr >> 16) & 0x08) == 0x08;
-        let unaligned = ((cfsr >> 16) & 0x100) == 0x100;
-        let divbysero = ((cfsr >> 16) & 0x200) == 0x200;
-
-        let vecttbl = (hfsr & 0x02) == 0x02;
-        let forced = (hfsr & 0x40000000) == 0x40000000;
-
-
-        let _ = writer.write_fmt(format_args!("\r\n---| Fault Status |---\r\n"));
-
-        if iaccviol {
-            let _ =
-                writer.write_fmt(format_args!("Instruction Access Violation:       {}\r\n",
-                                              iaccviol));
-        }
-        if daccviol {
-            let _ =
-                writer.write_fmt(format_args!("Data Access Violation:              {}\r\n",
-                                              daccviol));
-        }
-        if munstkerr {
-            let _ =
-                writer.write_fmt(format_args!("Memory Management Unstacking Fault: {}\r\n",
-                                              munstkerr));
-        }
-        if mstkerr {
-            let _ = writer.write_fmt(format_args!("Memory Management Stacking Fault:   {}\r\n",
-                                                  mstkerr));
-        }
-        if mlsperr {
-            let _ = writer.write_fmt(format_args!("Memory Management Lazy FP Fault:    {}\r\n",
-                                                  mlsperr));
-        }
-
-        if ibuserr {
-            let _ = writer.write_fmt(format_args!("Instruction Bus Error:              {}\r\n",
-                                                  ibuserr));
-        }
-        if preciserr {
-            let _ =
-                writer.write_fmt(format_args!("Precise Data Bus Error:             {}\r\n",
-                                              preciserr));
-        }
-        if impreciserr {
-            let _ =
-                writer.write_fmt(format_args!("Imprecise Data Bus Error:           {}\r\n",
-                                              impreciserr));
-        }
-        if unstkerr {
-            let _ =
-                writer.write_fmt(format_args!("Bus Unstacking Fault:               {}\r\n",
-                                              unstkerr));
-        }
-        if stkerr {
-            let _ = writer.write_fmt(format_args!("Bus Stacking Fault:                 {}\r\n",
-                                                  stkerr));
-        }
-        if lsperr {
-            let _ = writer.write_fmt(format_args!("Bus Lazy FP Fault:                  {}\r\n",
-                                                  lsperr));
-        }
-
-        if undefinstr {
-            let _ =
-                writer.write_fmt(format_args!("Undefined Instruction Usage Fault:  {}\r\n",
-                                              undefinstr));
-        }
-        if invstate {
-            let _ =
-                writer.write_fmt(format_args!("Invalid State Usage Fault:          {}\r\n",
-                                              invstate));
-        }
-        if invpc {
-            let _ =
-                writer.write_fmt(format_args!("Invalid PC Load Usage Fault:        {}\r\n", invpc));
-        }
-        if nocp {
-            let _ =
-                writer.write_fmt(format_args!("No Coprocessor Usage Fault:         {}\r\n", nocp));
-        }
-        if unaligned {
-            let _ =
-                writer.write_fmt(format_args!("Unaligned Access Usage Fault:       {}\r\n",
-                                              unaligned));
-        }
-        if divbysero {
-            let _ =
-                writer.write_fmt(format_args!("Divide By Zero:                     {}\r\n",
-                                              divbysero));
-        }
-
-        if vecttbl {
-            let _ = writer.write_fmt(format_args!("Bus Fault on Vector Table Read:     {}\r\n",
-                                                  vecttbl));
-        }
-        if forced {
-            let _ = writer.write_fmt(format_args!("Forced Hard Fault:                  {}\r\n",
-                                                  forced));
-        }
-
-        if mmfarvalid {
-            let _ =
-                writer.write_fmt(format_args!("Faulting Memory Address:            {:#010X}\r\n",
-                                              mmfar));
-        }
-        if bfarvalid {
-            let _ =
-                writer.write_fmt(format_args!("Bus Fault Address:                  {:#010X}\r\n",
-                                              bfar));
-        }
-
-        if cfsr == 0 && hfsr == 0 {
-            let _ = writer.write_fmt(format_args!("No faults detected.\r\n"));
-        } else {
-            let _ =
-                writer.write_fmt(format_args!("Fault Status Register (CFSR):       {:#010X}\r\n",
-                                              cfsr));
-            let _ =
-                writer.write_fmt(format_args!("Hard Fault Status Register (HFSR):  {:#010X}\r\n",
-                                              hfsr));
+    pub fn fault_str<W: Write>(&mut self, writer: &mut W) {
+        match self.fault_kind {
+            Some(FaultKind::StackOverflow) => {
+                let _ = writer.write_fmt(format_args!("\r\n---| Fault Status |---\r\n\
+                                                       Stack Overflow:   SP {:#010X}, \
+                                                       stack bottom {:#010X}\r\n",
+                                                      self.cur_stack as usize,
+                                                      self.app_mem_start as usize));
+            }
+            _ => fault_status_str(writer),
         }
     }
 
-    pub unsafe fn statistics_str<W: Write>(&mut self, writer: &mut W) {
+    pub fn statistics_str<W: Write>(&mut self, writer: &mut W) {
 
-        if let Some(load_info) = parse_and_validate_load_info(self.text.as_ptr()) {
+        // The header was validated when the process was created
+        if let Some(load_info) = unsafe { parse_and_validate_load_info(self.text.as_ptr()) } {
             // Flash addresses
-            let flash_end = self.text.as_ptr().offset(self.text.len() as isize) as usize;
-            let flash_data_end = self.text
-                .as_ptr()
-                .offset(load_info.pkg_name_offset as isize +
-                        load_info.pkg_name_size as isize) as usize;
-            let flash_data_start =
-                self.text.as_ptr().offset(load_info.got_offset as isize) as usize;
-            let flash_text_start =
-                self.text.as_ptr().offset(load_info.text_offset as isize) as usize;
             let flash_start = self.text.as_ptr() as usize;
+            let flash_end = flash_start + self.text.len();
+            let flash_data_end = flash_start + load_info.pkg_name_offset as usize +
+                                 load_info.pkg_name_size as usize;
+            let flash_data_start = flash_start + load_info.got_offset as usize;
+            let flash_text_start = flash_start + load_info.text_offset as usize;
 
             // Flash sizes
             let flash_data_size = load_info.got_size + load_info.data_size +
-                                  load_info.pkg_name_size;
+                                  load_info.permissions_size + load_info.pkg_name_size;
             let flash_text_size = load_info.text_size;
-            let flash_header_size = mem::size_of::<LoadInfo>() + load_info.rel_data_size as usize;
+            let flash_header_size = load_info.header_size() + load_info.rel_data_size as usize;
 
             // SRAM addresses
-            let sram_end = self.memory.as_ptr().offset(self.memory.len() as isize) as usize;
+            let sram_end = self.memory.as_ptr() as usize + self.memory.len();
             let sram_grant_start = self.kernel_memory_break as usize;
             let sram_heap_end = self.app_memory_break as usize;
             let sram_heap_start = self.stack_heap_boundary as usize;
@@ -887,7 +1912,10 @@ impl<'a> Process<'a> {
             // You can thank the piece of garbage rustfmt for this.
             let _ = writer.write_fmt(format_args!("\
             App: {}\
-            \r\n [{:?}]  -  Events Queued: {}  Syscall Count: {}\
+            \r\n [{:?}]  -  Events Queued: {}  Syscall Count: {}  Restarts: {}\
+            \r\n CPU Time: {}us  Scheduled: {}  Timeslices Expired: {}\
+            \r\n Callback Queue: {}  Dropped Callbacks: {}\
+            \r\n Stack High Water Mark: {} of {} bytes\
             \r\n\
             \r\n ╔═══════════╤══════════════\
 ════════════════════════════╗\
@@ -944,6 +1972,14 @@ impl<'a> Process<'a> {
                                                   self.state,
                                                   events_queued,
                                                   syscall_count,
+                                                  self.restart_count,
+                                                  self.cpu_time_us.get(),
+                                                  self.scheduled_count.get(),
+                                                  self.timeslice_expirations.get(),
+                                                  self.callback_len,
+                                                  self.dropped_callbacks.get(),
+                                                  self.stack_high_water_mark(),
+                                                  self.stack_size(),
                                                   sram_end,
                                                   sram_grant_size,
                                                   sram_grant_allocated,
@@ -991,12 +2027,62 @@ impl<'a> Process<'a> {
                                                   self.yield_pc,
                                                   ypc_lst_relative,
                                                   ));
+
+            let num_ctrs = container::count();
+            for container_num in 0..num_ctrs {
+                let bytes = self.grant_bytes(container_num);
+                if bytes > 0 {
+                    let _ = writer.write_fmt(format_args!(" Grant {:2}: {:6} bytes\r\n",
+                                                          container_num,
+                                                          bytes));
+                }
+            }
         } else {
             let _ = writer.write_fmt(format_args!("Unknown Load Info\r\n"));
         }
     }
 }
 
+/// Lays out an empty grant region at the top of `memory`.
+///
+/// Reserves a null pointer for each container followed by a ring buffer that
+/// holds `callback_len` tasks. Returns the resulting kernel memory break and
+/// the task ring buffer.
+unsafe fn setup_grant_region<'a>(memory: &mut [u8],
+                                 callback_len: usize)
+                                 -> (*const u8, RingBuffer<'a, Task>) {
+    let mut kernel_memory_break = memory.as_mut_ptr().offset(memory.len() as isize);
+
+    // make room for container pointers
+    let pointer_size = mem::size_of::<*const usize>();
+    let num_ctrs = read_volatile(&container::CONTAINER_COUNTER);
+    let container_ptrs_size = num_ctrs * pointer_size;
+    kernel_memory_break = kernel_memory_break.offset(-(container_ptrs_size as isize));
+
+    // set all pointers to null
+    let opts = slice::from_raw_parts_mut(kernel_memory_break as *mut *const usize, num_ctrs);
+    for opt in opts.iter_mut() {
+        *opt = ptr::null()
+    }
+
+    // Allocate memory for callback ring buffer. A ring buffer holds one entry
+    // fewer than it has room for.
+    let ring_len = callback_len + 1;
+    let callback_size = mem::size_of::<Task>();
+    let callback_offset = ring_len * callback_size;
+    kernel_memory_break = kernel_memory_break.offset(-(callback_offset as isize));
+
+    // Set up ring buffer
+    let callback_buf = slice::from_raw_parts_mut(kernel_memory_break as *mut Task, ring_len);
+    let tasks = RingBuffer::new(callback_buf);
+
+    // Blocks allocated below must be aligned
+    let misalignment = kernel_memory_break as usize % GRANT_ALIGN;
+    kernel_memory_break = kernel_memory_break.offset(-(misalignment as isize));
+
+    (kernel_memory_break, tasks)
+}
+
 #[derive(Debug)]
 struct LoadResult {
     /// The absolute address of the process entry point (i.e. `_start`).
@@ -1011,6 +2097,9 @@ struct LoadResult {
 
     /// The process's package name (used for IPC)
     package_name: &'static str,
+
+    /// The drivers the process may use, `None` if unrestricted
+    permissions: Option<&'static [DriverPermission]>,
 }
 
 /// Loads the process into memory
@@ -1025,7 +2114,7 @@ struct LoadResult {
 ///
 /// The function returns a `LoadResult` containing metadata about the loaded
 /// process or None if loading failed.
-unsafe fn load(load_info: &'static LoadInfo,
+unsafe fn load(load_info: &LoadInfo,
                flash_start_addr: *const u8,
                mem_base: *mut u8,
                mem_size: usize)
@@ -1040,8 +2129,18 @@ unsafe fn load(load_info: &'static LoadInfo,
         app_name_str = name_str;
     });
 
+    let num_permissions = load_info.permissions_size as usize /
+                          mem::size_of::<DriverPermission>();
+    let permissions = if num_permissions == 0 {
+        None
+    } else {
+        let first = flash_start_addr.offset(load_info.permissions_offset as isize);
+        Some(slice::from_raw_parts(first as *const DriverPermission, num_permissions))
+    };
+
     let mut load_result = LoadResult {
         package_name: app_name_str,
+        permissions: permissions,
         init_fn: 0,
         app_mem_start: ptr::null(),
         data_len: 0,
@@ -1125,9 +2224,14 @@ unsafe fn load(load_info: &'static LoadInfo,
     // Entry point is offset from app code
     load_result.init_fn = flash_start_addr.offset(load_info.entry_offset as isize) as usize;
 
-    let aligned_mem_start = align8!(load_info.bss_mem_offset + load_info.bss_size);
-    load_result.app_mem_start = mem_base.offset(aligned_mem_start as isize);
-    load_result.data_len = aligned_mem_start;
+    let stack_bottom = stack_bottom_offset(load_info);
+    load_result.app_mem_start = mem_base.offset(stack_bottom as isize);
+    load_result.data_len = stack_bottom as u32;
+
+    let guard = load_result.app_mem_start.offset(-(STACK_GUARD_LEN as isize)) as *mut usize;
+    for i in 0..STACK_GUARD_LEN / mem::size_of::<usize>() {
+        write_volatile(guard.offset(i as isize), STACK_CANARY);
+    }
 
     Some(load_result)
 }
@@ -0,0 +1,234 @@
+//! Diagnostic report of the kernel state for panic handlers.
+//!
+//! `panic_str` prints the panic message, the decoded fault status registers
+//! and, for every process, its state, registers, memory map, statistics and
+//! queued tasks. Board panic handlers call it with the writer from their
+//! `io.rs`, followed by `trace::dump` to print the syscall trace if tracing
+//! is enabled:
+//!
+//! ```rust
+//! #[lang="panic_fmt"]
+//! pub unsafe extern "C" fn panic_fmt(args: Arguments, file: &'static str, line: u32) -> ! {
+//!     kernel::report::panic_str(&mut WRITER, args, file, line);
+//!     kernel::trace::dump(&mut WRITER);
+//!     // blink an LED
+//! }
+//! ```
+//!
+//! `panic_blob` writes the same report in a compact binary form to a
+//! `BlobSink`, for example a reserved flash page that is read back after a
+//! reset.
+//!
+//! # Binary format
+//!
+//! The blob is a sequence of little-endian 32-bit words:
+//!
+//! ```text
+//! magic                   0x52504b54 ("TKPR")
+//! version                 2
+//! line                    line of the panic
+//! file_len                followed by the file name, zero padded to a word
+//! message_len             followed by the panic message, zero padded to a word
+//! ccr cfsr hfsr mmfar bfar
+//! num_processes           followed by one record per process
+//! ```
+//!
+//! A process record is:
+//!
+//! ```text
+//! slot generation state
+//! pc lr sp yield_pc r0 r1 r2 r3 r12
+//! mem_start mem_end flash_start flash_end
+//! app_mem_start stack_heap_boundary app_memory_break grant_start
+//! syscall_count scheduled_count timeslice_expirations restart_count
+//! dropped_callbacks
+//! cpu_time_us_low cpu_time_us_high
+//! stack_size stack_high_water_mark
+//! num_tasks               followed by 6 words per queued task
+//! ```
+//!
+//! A queued task is `0 pc r0 r1 r2 r3` for a function call and
+//! `1 from_slot ipc_type 0 0 0` for an IPC notification, where `ipc_type` is 0
+//! for a service and 1 for a client notification. States are numbered in the
+//! order they are declared in `process::State`.
+
+use core::fmt::{self, Arguments, Write, write};
+use process::{self, FaultKind, IPCType, Process, State, Task};
+
+/// Identifies a binary report.
+pub const BLOB_MAGIC: u32 = 0x52504b54;
+
+/// Version of the binary report format.
+pub const BLOB_VERSION: u32 = 2;
+
+/// Receives the binary report.
+pub trait BlobSink {
+    fn write(&mut self, bytes: &[u8]);
+}
+
+/// Prints the report for a kernel panic to `writer`.
+pub unsafe fn panic_str<W: Write>(writer: &mut W, args: Arguments, file: &str, line: u32) {
+    let _ = writer.write_fmt(format_args!("Kernel panic at {}:{}:\r\n\t\"", file, line));
+    let _ = write(writer, args);
+    let _ = writer.write_str("\"\r\n");
+
+    process::fault_status_str(writer);
+
+    let _ = writer.write_fmt(format_args!("\r\n---| App Status |---\r\n"));
+    for (slot, process) in process::PROCS.iter_mut().enumerate() {
+        process.as_mut().map(|process| {
+            let _ = writer.write_fmt(format_args!("\r\nSlot {}  Generation {}\r\n",
+                                                  slot,
+                                                  process.generation()));
+            process.statistics_str(writer);
+            // Hardware faults are in the fault status above
+            if process.fault_kind() == Some(FaultKind::StackOverflow) {
+                process.fault_str(writer);
+            }
+            tasks_str(writer, process);
+        });
+    }
+}
+
+fn tasks_str<W: Write>(writer: &mut W, process: &Process) {
+    let _ = writer.write_fmt(format_args!(" Queued Tasks: {}\r\n", process.queued_task_count()));
+    for index in 0..process.queued_task_count() {
+        let _ = match process.queued_task(index) {
+            Some(Task::FunctionCall(call)) => {
+                writer.write_fmt(format_args!("  call {:#010X} ({:#x}, {:#x}, {:#x}, {:#x})\r\n",
+                                              call.pc,
+                                              call.r0,
+                                              call.r1,
+                                              call.r2,
+                                              call.r3))
+            }
+            Some(Task::IPC((from, IPCType::Service))) => {
+                writer.write_fmt(format_args!("  IPC service request from slot {}\r\n",
+                                              from.idx()))
+            }
+            Some(Task::IPC((from, IPCType::Client))) => {
+                writer.write_fmt(format_args!("  IPC client notification from slot {}\r\n",
+                                              from.idx()))
+            }
+            None => Ok(()),
+        };
+    }
+}
+
+/// Writes the report for a kernel panic to `sink` in the binary format
+/// described in the module documentation.
+pub unsafe fn panic_blob<S: BlobSink>(sink: &mut S, args: Arguments, file: &str, line: u32) {
+    word(sink, BLOB_MAGIC);
+    word(sink, BLOB_VERSION);
+    word(sink, line);
+
+    word(sink, file.len() as u32);
+    sink.write(file.as_bytes());
+    pad(sink, file.len());
+
+    // The message is formatted twice, once to learn its length
+    let mut counter = ByteCounter(0);
+    let _ = write(&mut counter, args);
+    word(sink, counter.0 as u32);
+    let _ = write(&mut SinkWriter(sink), args);
+    pad(sink, counter.0);
+
+    for register in process::SCB_REGISTERS.iter() {
+        word(sink, *register);
+    }
+
+    let procs = &process::PROCS;
+    word(sink, procs.iter().filter(|p| p.is_some()).count() as u32);
+    for (slot, process) in procs.iter().enumerate() {
+        process.as_ref().map(|process| process_blob(sink, slot, process));
+    }
+}
+
+fn process_blob<S: BlobSink>(sink: &mut S, slot: usize, process: &Process) {
+    let words = [slot,
+                 process.generation(),
+                 state_code(process.current_state()),
+                 process.pc(),
+                 process.lr(),
+                 process.sp(),
+                 process.yield_pc(),
+                 process.r0(),
+                 process.r1(),
+                 process.r2(),
+                 process.r3(),
+                 process.r12(),
+                 process.mem_start() as usize,
+                 process.mem_end() as usize,
+                 process.flash_start() as usize,
+                 process.flash_end() as usize,
+                 process.app_mem_start() as usize,
+                 process.stack_heap_boundary() as usize,
+                 process.app_memory_break() as usize,
+                 process.grant_start() as usize,
+                 process.syscall_count(),
+                 process.scheduled_count(),
+                 process.timeslice_expirations(),
+                 process.restart_count(),
+                 process.dropped_callbacks(),
+                 process.cpu_time_us() as usize,
+                 (process.cpu_time_us() >> 32) as usize,
+                 process.stack_size(),
+                 process.stack_high_water_mark(),
+                 process.queued_task_count()];
+    for w in words.iter() {
+        word(sink, *w as u32);
+    }
+
+    for index in 0..process.queued_task_count() {
+        let task = match process.queued_task(index) {
+            Some(Task::FunctionCall(call)) => [0, call.pc, call.r0, call.r1, call.r2, call.r3],
+            Some(Task::IPC((from, IPCType::Service))) => [1, from.idx(), 0, 0, 0, 0],
+            Some(Task::IPC((from, IPCType::Client))) => [1, from.idx(), 1, 0, 0, 0],
+            None => [0; 6],
+        };
+        for w in task.iter() {
+            word(sink, *w as u32);
+        }
+    }
+}
+
+fn state_code(state: State) -> usize {
+    match state {
+        State::Running => 0,
+        State::Yielded => 1,
+        State::StoppedRunning => 2,
+        State::StoppedYielded => 3,
+        State::Fault => 4,
+        State::Terminated => 5,
+    }
+}
+
+fn word<S: BlobSink>(sink: &mut S, w: u32) {
+    sink.write(&[w as u8, (w >> 8) as u8, (w >> 16) as u8, (w >> 24) as u8]);
+}
+
+/// Zero pads `len` bytes already written to a whole number of words.
+fn pad<S: BlobSink>(sink: &mut S, len: usize) {
+    let padding = (4 - len % 4) % 4;
+    sink.write(&[0; 3][..padding]);
+}
+
+/// Counts the bytes formatted into it.
+struct ByteCounter(usize);
+
+impl Write for ByteCounter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+/// Passes formatted text on to a sink.
+struct SinkWriter<'a, S: BlobSink + 'a>(&'a mut S);
+
+impl<'a, S: BlobSink> Write for SinkWriter<'a, S> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write(s.as_bytes());
+        Ok(())
+    }
+}
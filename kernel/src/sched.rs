@@ -4,33 +4,93 @@ use platform::systick::SysTick;
 use process;
 use process::{Process, Task};
 use returncode::ReturnCode;
+use scheduler::StoppedReason;
 use syscall;
+use trace;
 
+/// Don't start a process if less than this much of its timeslice is left.
+const MIN_QUANTA_THRESHOLD_US: u32 = 500;
+
+/// Runs `process` until it has no more work, its timeslice runs out or
+/// interrupts need to be serviced.
+///
+/// A `timeslice` of `None` disables preemption: the process runs until it
+/// yields.
 pub unsafe fn do_process<P: Platform, C: Chip>(platform: &P,
                                                chip: &mut C,
                                                process: &mut Process,
                                                appid: ::AppId,
-                                               ipc: &::ipc::IPC) {
+                                               ipc: &::ipc::IPC,
+                                               timeslice: Option<u32>)
+                                               -> StoppedReason {
     let systick = chip.systick();
     systick.reset();
-    systick.set_timer(10000);
-    systick.enable(true);
+    if let Some(us) = timeslice {
+        systick.set_timer(us);
+        systick.enable(true);
+    }
+    process.incr_scheduled_count();
 
+    // Set once the timeslice ran out while the process ran
+    let mut expired = false;
+    let reason;
     loop {
-        if chip.has_pending_interrupts() || systick.overflowed() || systick.value() <= 500 {
+        // A restart gives the process a new generation
+        let appid = ::AppId::new(appid.idx(), process.generation());
+
+        if chip.has_pending_interrupts() {
+            reason = StoppedReason::Interrupted;
+            break;
+        }
+        if timeslice.is_some() &&
+           (expired || systick.overflowed() || systick.value() <= MIN_QUANTA_THRESHOLD_US) {
+            reason = StoppedReason::TimesliceExpired;
             break;
         }
 
         match process.current_state() {
             process::State::Running => {
                 process.setup_mpu(chip.mpu());
+                let start = match timeslice {
+                    Some(_) => systick.value(),
+                    None => {
+                        // Without a timeslice the systick only accounts for
+                        // the CPU time the process uses, so it starts over
+                        // for every run. Its interrupt still stops the
+                        // process whenever the counter runs out.
+                        systick.set_timer(!0);
+                        systick.interval()
+                    }
+                };
                 systick.enable(true);
                 process.switch_to();
                 systick.enable(false);
+                let end = systick.value();
+
+                // The interrupt stops the process as soon as the counter runs
+                // out, so it reloads at most once while the process runs.
+                // Timers whose interrupt can be late stop at zero after one
+                // reload instead.
+                if systick.overflowed() {
+                    process.add_cpu_time(start as u64 + (systick.interval() - end) as u64);
+                    expired = true;
+                } else {
+                    process.add_cpu_time((start - end) as u64);
+                }
+
+                // An overflow that hit the MPU guard also shows as a fault
+                if process.stack_overflowed() {
+                    process.fault_state(process::FaultKind::StackOverflow);
+                    ipc.process_stopped(appid);
+                    continue;
+                }
             }
             process::State::Yielded => {
                 match process.dequeue_task() {
-                    None => break,
+                    None => {
+                        reason = StoppedReason::Idle;
+                        break;
+                    }
                     Some(cb) => {
                         match cb {
                             Task::FunctionCall(ccb) => {
@@ -44,13 +104,32 @@ pub unsafe fn do_process<P: Platform, C: Chip>(platform: &P,
                     }
                 }
             }
+            process::State::StoppedRunning |
+            process::State::StoppedYielded |
+            process::State::Terminated => {
+                reason = StoppedReason::Inactive;
+                break;
+            }
             process::State::Fault => {
-                // we should never be scheduling a process in fault
-                panic!("Attempted to schedule a faulty process");
+                // A faulted process only runs again once the main loop
+                // restarts it
+                reason = StoppedReason::Faulted;
+                break;
             }
         }
 
         if !process.syscall_fired() {
+            if timeslice.is_none() && !process.app_fault() {
+                // Without a timeslice only interrupts end the run, which the
+                // top of the loop checks for
+                continue;
+            }
+            // The process was preempted by the systick or an interrupt
+            reason = if chip.has_pending_interrupts() {
+                StoppedReason::Interrupted
+            } else {
+                StoppedReason::TimesliceExpired
+            };
             break;
         }
 
@@ -58,33 +137,31 @@ pub unsafe fn do_process<P: Platform, C: Chip>(platform: &P,
         if process.app_fault() {
 
             // let process deal with it as appropriate
-            process.fault_state();
+            process.fault_state(process::FaultKind::Hardware);
+            ipc.process_stopped(appid);
             continue;
         }
 
         // process had a system call, count it
         process.incr_syscall_count();
-        match process.svc_number() {
+        let svc_number = process.svc_number();
+        let args = [process.r0(), process.r1(), process.r2(), process.r3()];
+        match svc_number {
             Some(syscall::MEMOP) => {
                 let brk_type = process.r0();
                 let r1 = process.r1();
 
-                let res = match brk_type {
-                    0 /* BRK */ => {
-                        process.brk(r1 as *const u8)
-                            .map(|_| ReturnCode::SUCCESS)
-                            .unwrap_or(ReturnCode::ENOMEM)
-                    },
-                    1 /* SBRK */ => {
-                        process.sbrk(r1 as isize)
-                            .map(|addr| ReturnCode::SuccessWithValue { value: addr as usize })
-                            .unwrap_or(ReturnCode::ENOMEM)
-                    },
-                    _ => ReturnCode::ENOSUPPORT
-                };
+                let res = memop(process, brk_type, r1);
                 process.set_return_code(res);
             }
+            Some(syscall::EXIT) => {
+                trace::record(appid, syscall::EXIT, args, None);
+                process.terminate(Some(process.r0()));
+                ipc.process_stopped(appid);
+                continue;
+            }
             Some(syscall::YIELD) => {
+                trace::record(appid, syscall::YIELD, args, None);
                 process.yield_state();
                 process.pop_syscall_stack();
 
@@ -97,8 +174,23 @@ pub unsafe fn do_process<P: Platform, C: Chip>(platform: &P,
                 let callback_ptr_raw = process.r2() as *mut ();
                 let appdata = process.r3();
 
-                let res = if callback_ptr_raw as usize == 0 {
-                    ReturnCode::EINVAL
+                let res = if !process.may_use_driver(driver_num) {
+                    ReturnCode::ENODEVICE
+                } else if callback_ptr_raw as usize == 0 {
+                    platform.with_driver(driver_num, |driver| {
+                        match driver {
+                            Some(d) => {
+                                match d.unsubscribe(subdriver_num, appid) {
+                                    Ok(Some(previous)) => {
+                                        ReturnCode::SuccessWithValue { value: previous.fn_ptr() }
+                                    }
+                                    Ok(None) => ReturnCode::SUCCESS,
+                                    Err(err) => err,
+                                }
+                            }
+                            None => ReturnCode::ENODEVICE,
+                        }
+                    })
                 } else {
                     let callback_ptr = NonZero::new(callback_ptr_raw);
 
@@ -113,34 +205,133 @@ pub unsafe fn do_process<P: Platform, C: Chip>(platform: &P,
                 process.set_return_code(res);
             }
             Some(syscall::COMMAND) => {
-                let res = platform.with_driver(process.r0(), |driver| {
-                    match driver {
-                        Some(d) => d.command(process.r1(), process.r2(), appid),
-                        None => ReturnCode::ENODEVICE,
-                    }
-                });
+                let driver_num = process.r0();
+                let res = if !process.may_use_driver(driver_num) {
+                    ReturnCode::ENODEVICE
+                } else if !process.may_command(driver_num, process.r1()) {
+                    ReturnCode::ENOSUPPORT
+                } else {
+                    platform.with_driver(driver_num, |driver| {
+                        match driver {
+                            Some(d) => d.command(process.r1(), process.r2(), appid),
+                            None => ReturnCode::ENODEVICE,
+                        }
+                    })
+                };
                 process.set_return_code(res);
             }
             Some(syscall::ALLOW) => {
-                let res = platform.with_driver(process.r0(), |driver| {
-                    match driver {
-                        Some(d) => {
-                            let start_addr = process.r2() as *mut u8;
-                            let size = process.r3();
-                            if process.in_exposed_bounds(start_addr, size) {
-                                let slice = ::AppSlice::new(start_addr as *mut u8, size, appid);
-                                d.allow(appid, process.r1(), slice)
-                            } else {
-                                ReturnCode::EINVAL /* memory not allocated to process */
+                let res = if !process.may_use_driver(process.r0()) {
+                    ReturnCode::ENODEVICE
+                } else {
+                    platform.with_driver(process.r0(), |driver| {
+                        match driver {
+                            Some(d) => {
+                                let start_addr = process.r2() as *mut u8;
+                                let size = process.r3();
+                                if start_addr.is_null() || size == 0 {
+                                    // The previous buffer is dropped here
+                                    match d.unallow(appid, process.r1()) {
+                                        Ok(Some(previous)) => {
+                                            let addr = previous.ptr() as usize;
+                                            ReturnCode::SuccessWithValue { value: addr }
+                                        }
+                                        Ok(None) => ReturnCode::SUCCESS,
+                                        Err(err) => err,
+                                    }
+                                } else if process.in_exposed_bounds(start_addr, size) {
+                                    let slice = ::AppSlice::new(start_addr as *mut u8, size, appid);
+                                    d.allow(appid, process.r1(), slice)
+                                } else {
+                                    ReturnCode::EINVAL /* memory not allocated to process */
+                                }
                             }
+                            None => ReturnCode::ENODEVICE,
                         }
-                        None => ReturnCode::ENODEVICE,
-                    }
-                });
+                    })
+                };
                 process.set_return_code(res);
             }
             _ => {}
         }
+        svc_number.map(|svc| {
+            let result = match svc {
+                syscall::MEMOP | syscall::SUBSCRIBE | syscall::COMMAND | syscall::ALLOW => {
+                    Some(process.r0() as isize)
+                }
+                _ => None,
+            };
+            trace::record(appid, svc, args, result);
+        });
+    }
+    if reason == StoppedReason::TimesliceExpired {
+        process.incr_timeslice_expirations();
     }
     systick.reset();
+    reason
+}
+
+/// Handles the MEMOP system call, which lets a process manage and inspect its
+/// memory.
+fn memop(process: &mut Process, op: usize, r1: usize) -> ReturnCode {
+    match op {
+        0 /* BRK */ => {
+            process.brk(r1 as *const u8)
+                .map(|_| ReturnCode::SUCCESS)
+                .unwrap_or(ReturnCode::ENOMEM)
+        },
+        1 /* SBRK */ => {
+            process.sbrk(r1 as isize)
+                .map(|addr| ReturnCode::SuccessWithValue { value: addr as usize })
+                .unwrap_or(ReturnCode::ENOMEM)
+        },
+        2 /* CPU time used, in milliseconds */ => {
+            let ms = process.cpu_time_us() / 1000;
+            ReturnCode::SuccessWithValue { value: ms as usize }
+        },
+        3 /* Number of times scheduled */ => {
+            ReturnCode::SuccessWithValue { value: process.scheduled_count() }
+        },
+        4 /* Number of timeslices expired */ => {
+            ReturnCode::SuccessWithValue { value: process.timeslice_expirations() }
+        },
+        5 /* Start of process memory */ => {
+            ReturnCode::SuccessWithValue { value: process.mem_start() as usize }
+        },
+        6 /* End of process memory */ => {
+            ReturnCode::SuccessWithValue { value: process.mem_end() as usize }
+        },
+        7 /* Start of process flash */ => {
+            ReturnCode::SuccessWithValue { value: process.flash_start() as usize }
+        },
+        8 /* End of process flash */ => {
+            ReturnCode::SuccessWithValue { value: process.flash_end() as usize }
+        },
+        9 /* Start of the grant region */ => {
+            ReturnCode::SuccessWithValue { value: process.grant_start() as usize }
+        },
+        10 /* Stack/heap boundary */ => {
+            ReturnCode::SuccessWithValue { value: process.stack_heap_boundary() as usize }
+        },
+        11 /* Stack size */ => {
+            ReturnCode::SuccessWithValue { value: process.stack_size() }
+        },
+        12 /* Free memory */ => {
+            ReturnCode::SuccessWithValue { value: process.free_memory() }
+        },
+        13 /* Move the stack/heap boundary */ => {
+            match process.set_stack_heap_boundary(r1 as *const u8) {
+                Ok(()) => ReturnCode::SUCCESS,
+                Err(process::Error::OutOfMemory) => ReturnCode::ENOMEM,
+                Err(_) => ReturnCode::EINVAL,
+            }
+        },
+        14 /* Number of callbacks dropped */ => {
+            ReturnCode::SuccessWithValue { value: process.dropped_callbacks() }
+        },
+        15 /* Deepest stack use */ => {
+            ReturnCode::SuccessWithValue { value: process.stack_high_water_mark() }
+        },
+        _ => ReturnCode::ENOSUPPORT
+    }
 }
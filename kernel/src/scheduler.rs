@@ -0,0 +1,156 @@
+//! Process scheduling policies.
+//!
+//! The kernel main loop asks a `Scheduler` which process to run next and for
+//! how long. Boards pick a policy by passing an implementation to
+//! `kernel::main`.
+//!
+//! Three policies are provided:
+//!
+//!   * `RoundRobin` runs each process in turn with a fixed timeslice.
+//!
+//!   * `FixedPriority` always runs the highest priority process that has work
+//!   to do. Processes are prioritized by their position in the process array,
+//!   so the first app in flash has the highest priority.
+//!
+//!   * `Cooperative` runs each process in turn without preemption. A process
+//!   runs until it yields.
+
+use callback::AppId;
+use core::cell::Cell;
+use process::Process;
+
+/// The timeslice used by the provided schedulers, in microseconds.
+pub const DEFAULT_TIMESLICE_US: u32 = 10000;
+
+/// Why the kernel stopped running a process.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StoppedReason {
+    /// The process yielded and has no queued callbacks.
+    Idle,
+    /// The process used up its timeslice and was preempted.
+    TimesliceExpired,
+    /// The kernel has interrupts to service.
+    Interrupted,
+    /// The process is in the `Fault` state.
+    Faulted,
+    /// The process is stopped or has terminated.
+    Inactive,
+}
+
+pub trait Scheduler {
+    /// Chooses the next process to run.
+    ///
+    /// Returns the index of the process in `processes`, or `None` to end the
+    /// current pass of the main loop. Once a pass ends the kernel services
+    /// interrupts and sleeps if no process has work.
+    fn next(&self, processes: &[Option<Process<'static>>]) -> Option<usize>;
+
+    /// The timeslice, in microseconds, to give the process before preempting
+    /// it. `None` lets the process run until it yields.
+    fn timeslice(&self, appid: AppId) -> Option<u32>;
+
+    /// Called after the kernel stops running a process chosen by `next`.
+    ///
+    /// This is where a scheduler decides what happens when a timeslice runs
+    /// out, e.g. by moving the process to the back of a queue.
+    #[allow(unused_variables)]
+    fn stopped(&self, appid: AppId, reason: StoppedReason) {}
+}
+
+/// Runs every process once per pass, in process array order, with a fixed
+/// timeslice.
+pub struct RoundRobin {
+    timeslice: u32,
+    next: Cell<usize>,
+}
+
+impl RoundRobin {
+    pub const fn new(timeslice_us: u32) -> RoundRobin {
+        RoundRobin {
+            timeslice: timeslice_us,
+            next: Cell::new(0),
+        }
+    }
+}
+
+impl Scheduler for RoundRobin {
+    fn next(&self, processes: &[Option<Process<'static>>]) -> Option<usize> {
+        next_in_order(&self.next, processes)
+    }
+
+    fn timeslice(&self, _: AppId) -> Option<u32> {
+        Some(self.timeslice)
+    }
+}
+
+/// Always runs the highest priority process with work to do. A lower index
+/// in the process array means a higher priority.
+///
+/// A process that runs out of its timeslice is preempted only so that
+/// interrupts can be serviced. It keeps running for as long as no higher
+/// priority process has work.
+pub struct FixedPriority {
+    timeslice: u32,
+}
+
+impl FixedPriority {
+    pub const fn new(timeslice_us: u32) -> FixedPriority {
+        FixedPriority { timeslice: timeslice_us }
+    }
+}
+
+impl Scheduler for FixedPriority {
+    fn next(&self, processes: &[Option<Process<'static>>]) -> Option<usize> {
+        processes.iter()
+            .position(|p| p.as_ref().map_or(false, |process| process.ready()))
+    }
+
+    fn timeslice(&self, _: AppId) -> Option<u32> {
+        Some(self.timeslice)
+    }
+}
+
+/// Runs every process once per pass, in process array order, without
+/// preemption.
+///
+/// A process that the kernel stops to service interrupts runs again before
+/// any other process.
+pub struct Cooperative {
+    next: Cell<usize>,
+}
+
+impl Cooperative {
+    pub const fn new() -> Cooperative {
+        Cooperative { next: Cell::new(0) }
+    }
+}
+
+impl Scheduler for Cooperative {
+    fn next(&self, processes: &[Option<Process<'static>>]) -> Option<usize> {
+        next_in_order(&self.next, processes)
+    }
+
+    fn timeslice(&self, _: AppId) -> Option<u32> {
+        None
+    }
+
+    fn stopped(&self, appid: AppId, reason: StoppedReason) {
+        if reason == StoppedReason::Interrupted {
+            self.next.set(appid.idx());
+        }
+    }
+}
+
+/// Returns the next process with work at or after `next`, advancing `next`
+/// past it. Returns `None` and rewinds `next` once the end of the array is
+/// reached.
+fn next_in_order(next: &Cell<usize>, processes: &[Option<Process<'static>>]) -> Option<usize> {
+    for idx in next.get()..processes.len() {
+        if processes[idx].as_ref().map_or(false, |process| process.ready()) {
+            next.set(idx + 1);
+            return Some(idx);
+        }
+    }
+    next.set(0);
+    None
+}
@@ -3,3 +3,4 @@ pub const SUBSCRIBE: u8 = 1;
 pub const COMMAND: u8 = 2;
 pub const ALLOW: u8 = 3;
 pub const MEMOP: u8 = 4;
+pub const EXIT: u8 = 5;
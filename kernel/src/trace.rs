@@ -0,0 +1,198 @@
+//! Optional tracing of the system calls processes make.
+//!
+//! Tracing is off until a board calls `enable` with a buffer for the trace.
+//! The scheduler then records every system call, with its arguments and the
+//! value returned to the process, in a ring buffer. When the buffer is full
+//! the oldest record is dropped. `dump` prints the decoded trace to any
+//! `core::fmt::Write`, for example the writer in a board's `io.rs`:
+//!
+//! ```rust
+//! static mut TRACE_BUF: [kernel::trace::Record; 32] = [kernel::trace::Record::empty(); 32];
+//!
+//! kernel::trace::enable(&mut TRACE_BUF);
+//! // ...
+//! kernel::trace::dump(&mut io::WRITER);
+//! ```
+
+use callback::AppId;
+use common::{Queue, RingBuffer};
+use core::fmt::Write;
+use returncode::ReturnCode;
+use syscall;
+
+/// A single traced system call.
+#[derive(Copy, Clone)]
+pub struct Record {
+    pub appid: usize,
+    /// The SVC number of the system call.
+    pub syscall: u8,
+    /// r0-r3 as passed by the process.
+    pub args: [usize; 4],
+    /// The value returned to the process in r0. `None` for system calls that
+    /// do not return a value, like `yield`.
+    pub result: Option<isize>,
+}
+
+impl Record {
+    pub const fn empty() -> Record {
+        Record {
+            appid: 0,
+            syscall: 0,
+            args: [0; 4],
+            result: None,
+        }
+    }
+}
+
+struct Tracer {
+    records: RingBuffer<'static, Record>,
+    /// Bit `i` is set if app `i` is excluded from the trace.
+    excluded: usize,
+}
+
+static mut TRACER: Option<Tracer> = None;
+
+/// Starts tracing into `buffer`. All apps are traced.
+pub unsafe fn enable(buffer: &'static mut [Record]) {
+    TRACER = Some(Tracer {
+        records: RingBuffer::new(buffer),
+        excluded: 0,
+    });
+}
+
+/// Stops tracing and discards the trace.
+pub unsafe fn disable() {
+    TRACER = None;
+}
+
+/// Includes or excludes an app from the trace. Apps past the number of bits in
+/// a `usize` are always traced.
+pub fn set_traced(appid: AppId, traced: bool) {
+    let bit = 1usize.checked_shl(appid.idx() as u32).unwrap_or(0);
+    unsafe {
+        TRACER.as_mut().map(|tracer| if traced {
+            tracer.excluded &= !bit;
+        } else {
+            tracer.excluded |= bit;
+        });
+    }
+}
+
+/// Records a system call made by `appid`. Does nothing unless tracing is
+/// enabled and the app is traced.
+pub fn record(appid: AppId, syscall: u8, args: [usize; 4], result: Option<isize>) {
+    unsafe {
+        TRACER.as_mut().map(|tracer| {
+            let bit = 1usize.checked_shl(appid.idx() as u32).unwrap_or(0);
+            if tracer.excluded & bit != 0 {
+                return;
+            }
+            if tracer.records.is_full() {
+                tracer.records.dequeue();
+            }
+            tracer.records.enqueue(Record {
+                appid: appid.idx(),
+                syscall: syscall,
+                args: args,
+                result: result,
+            });
+        });
+    }
+}
+
+/// Prints the decoded trace to `writer`, oldest record first, and empties it.
+pub fn dump<W: Write>(writer: &mut W) {
+    unsafe {
+        TRACER.as_mut().map(|tracer| {
+            let _ = writer.write_fmt(format_args!("\r\n---| Syscall Trace |---\r\n"));
+            while let Some(record) = tracer.records.dequeue() {
+                write_record(writer, &record);
+            }
+        });
+    }
+}
+
+fn write_record<W: Write>(writer: &mut W, record: &Record) {
+    let args = record.args;
+    let _ = writer.write_fmt(format_args!("[app {}] ", record.appid));
+    let _ = match record.syscall {
+        syscall::YIELD => writer.write_fmt(format_args!("yield")),
+        syscall::SUBSCRIBE => {
+            writer.write_fmt(format_args!("subscribe driver {:#x} minor {} callback {:#010X} \
+                                           appdata {:#x}",
+                                          args[0],
+                                          args[1],
+                                          args[2],
+                                          args[3]))
+        }
+        syscall::COMMAND => {
+            writer.write_fmt(format_args!("command   driver {:#x} minor {} arg {:#x}",
+                                          args[0],
+                                          args[1],
+                                          args[2]))
+        }
+        syscall::ALLOW => {
+            writer.write_fmt(format_args!("allow     driver {:#x} minor {} buffer {:#010X} \
+                                           len {}",
+                                          args[0],
+                                          args[1],
+                                          args[2],
+                                          args[3]))
+        }
+        syscall::MEMOP => {
+            writer.write_fmt(format_args!("memop     op {} arg {:#x}", args[0], args[1]))
+        }
+        syscall::EXIT => writer.write_fmt(format_args!("exit      status {}", args[0])),
+        svc => writer.write_fmt(format_args!("unknown   svc {}", svc)),
+    };
+    match record.result {
+        Some(result) => {
+            match return_code_name(result) {
+                Some(name) => {
+                    let _ = writer.write_fmt(format_args!(" -> {}\r\n", name));
+                }
+                None => {
+                    let _ = writer.write_fmt(format_args!(" -> {}\r\n", result));
+                }
+            }
+        }
+        None => {
+            let _ = writer.write_str("\r\n");
+        }
+    }
+}
+
+/// The codes of `ReturnCode` a process can get back other than a value.
+const RETURN_CODES: [ReturnCode; 12] = [ReturnCode::SUCCESS,
+                                        ReturnCode::FAIL,
+                                        ReturnCode::EBUSY,
+                                        ReturnCode::EALREADY,
+                                        ReturnCode::EOFF,
+                                        ReturnCode::ERESERVE,
+                                        ReturnCode::EINVAL,
+                                        ReturnCode::ESIZE,
+                                        ReturnCode::ECANCEL,
+                                        ReturnCode::ENOMEM,
+                                        ReturnCode::ENOSUPPORT,
+                                        ReturnCode::ENODEVICE];
+
+/// Names the error codes of `ReturnCode` as returned to a process.
+fn return_code_name(result: isize) -> Option<&'static str> {
+    RETURN_CODES.iter()
+        .find(|&&code| isize::from(code) == result)
+        .map(|&code| match code {
+            ReturnCode::SuccessWithValue { .. } |
+            ReturnCode::SUCCESS => "SUCCESS",
+            ReturnCode::FAIL => "FAIL",
+            ReturnCode::EBUSY => "EBUSY",
+            ReturnCode::EALREADY => "EALREADY",
+            ReturnCode::EOFF => "EOFF",
+            ReturnCode::ERESERVE => "ERESERVE",
+            ReturnCode::EINVAL => "EINVAL",
+            ReturnCode::ESIZE => "ESIZE",
+            ReturnCode::ECANCEL => "ECANCEL",
+            ReturnCode::ENOMEM => "ENOMEM",
+            ReturnCode::ENOSUPPORT => "ENOSUPPORT",
+            ReturnCode::ENODEVICE => "ENODEVICE",
+        })
+}
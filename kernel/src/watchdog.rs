@@ -0,0 +1,71 @@
+//! Lets the kernel main loop own a hardware watchdog.
+//!
+//! Once a board enables it, the watchdog is serviced each time the kernel
+//! stops running a process and each time the main loop completes a pass. A
+//! capsule stuck in a busy loop, or an app that never yields under a
+//! scheduler without preemption, keeps the kernel from getting there and the
+//! watchdog resets the chip. The watchdog is stopped while the chip
+//! sleeps, since sleeping can take longer than any period.
+//!
+//! ```rust
+//! // in the board's reset handler, before kernel::main
+//! kernel::watchdog::enable(&sam4l::wdt::WDT, 1000);
+//!
+//! if kernel::watchdog::last_reset() == Some(kernel::watchdog::ResetReason::Watchdog) {
+//!     // report it
+//! }
+//! ```
+
+use hil::watchdog::Watchdog;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResetReason {
+    /// The watchdog fired because the main loop stopped making progress.
+    Watchdog,
+    /// Anything else, such as power-on or the reset pin.
+    Other,
+}
+
+static mut WATCHDOG: Option<&'static Watchdog> = None;
+static mut PERIOD_MS: usize = 0;
+static mut LAST_RESET: Option<ResetReason> = None;
+
+/// Starts `watchdog` with a timeout of `period_ms` milliseconds and hands it
+/// to the main loop. Also records whether it caused the last reset.
+pub unsafe fn enable(watchdog: &'static Watchdog, period_ms: usize) {
+    LAST_RESET = Some(if watchdog.caused_reset() {
+        ResetReason::Watchdog
+    } else {
+        ResetReason::Other
+    });
+    WATCHDOG = Some(watchdog);
+    PERIOD_MS = period_ms;
+    watchdog.start(period_ms);
+}
+
+/// Why the chip last reset. `None` if the board has not enabled a watchdog.
+pub fn last_reset() -> Option<ResetReason> {
+    unsafe { LAST_RESET }
+}
+
+/// Services the watchdog. Called by the main loop after every process it
+/// runs and after every pass.
+pub fn tickle() {
+    unsafe {
+        WATCHDOG.map(|watchdog| watchdog.tickle());
+    }
+}
+
+/// Stops the watchdog before the chip sleeps.
+pub fn suspend() {
+    unsafe {
+        WATCHDOG.map(|watchdog| watchdog.stop());
+    }
+}
+
+/// Restarts the watchdog after the chip wakes up.
+pub fn resume() {
+    unsafe {
+        WATCHDOG.map(|watchdog| watchdog.start(PERIOD_MS));
+    }
+}
@@ -0,0 +1,39 @@
+#include <string.h>
+
+#include "tock.h"
+#include "discovery.h"
+
+int discovery_count() {
+	return command(DRIVER_NUM_DISCOVERY, 0, 0);
+}
+
+int discovery_driver_num(int index) {
+	return command(DRIVER_NUM_DISCOVERY, 1, index);
+}
+
+int discovery_version(int driver_num) {
+	return command(DRIVER_NUM_DISCOVERY, 2, driver_num);
+}
+
+int discovery_name(int driver_num, char* buf, size_t len) {
+	int err = allow(DRIVER_NUM_DISCOVERY, 0, buf, len);
+	if (err < 0) return err;
+	return command(DRIVER_NUM_DISCOVERY, 3, driver_num);
+}
+
+int discovery_find(const char* name) {
+	char buf[16];
+	size_t name_len = strlen(name);
+	if (name_len > sizeof(buf)) return -1;
+
+	int count = discovery_count();
+	for (int i = 0; i < count; i++) {
+		int driver_num = discovery_driver_num(i);
+		if (driver_num < 0) return driver_num;
+		int len = discovery_name(driver_num, buf, sizeof(buf));
+		if (len == (int) name_len && memcmp(buf, name, name_len) == 0) {
+			return driver_num;
+		}
+	}
+	return -1;
+}
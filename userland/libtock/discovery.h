@@ -0,0 +1,22 @@
+#pragma once
+
+#include "tock.h"
+
+#define DRIVER_NUM_DISCOVERY 0xfe
+
+// Returns the number of drivers the platform serves this app.
+int discovery_count();
+
+// Returns the driver number of the driver at `index`, counting from 0.
+int discovery_driver_num(int index);
+
+// Returns the version of driver `driver_num`.
+int discovery_version(int driver_num);
+
+// Copies the name of driver `driver_num` into `buf`, truncated to `len`
+// bytes and not terminated. Returns the length of the whole name.
+int discovery_name(int driver_num, char* buf, size_t len);
+
+// Returns the number of the driver named `name`, or a negative value if the
+// platform does not serve it.
+int discovery_find(const char* name);
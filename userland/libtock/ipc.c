@@ -9,6 +9,68 @@ int ipc_discover(const char* pkg_name) {
   return res;
 }
 
+int ipc_register_service(const char* name) {
+  int len = strlen(name);
+  char* name_buf = (char*)malloc(len * sizeof(char));
+  memcpy(name_buf, name, len);
+  int res = allow(IPC_DRIVER_NUM, 0xff00, name_buf, len);
+  free(name_buf);
+  return res;
+}
+
+int ipc_unregister_service(void) {
+  return command(IPC_DRIVER_NUM, 0xff00, 0);
+}
+
+int ipc_service_count(void) {
+  return command(IPC_DRIVER_NUM, 0xff01, 0);
+}
+
+int ipc_service_at(int index) {
+  return command(IPC_DRIVER_NUM, 0xff02, index);
+}
+
+int ipc_service_name(int id, char* buf, size_t len) {
+  int res = allow(IPC_DRIVER_NUM, 0xff01, buf, len);
+  if (res < 0) {
+    return res;
+  }
+  return command(IPC_DRIVER_NUM, 0xff03, id);
+}
+
+static char* message_buf;
+static size_t message_len;
+
+int ipc_message_listen(subscribe_cb callback, void* ud, char* buf, size_t len) {
+  message_buf = buf;
+  message_len = len;
+  int res = subscribe(IPC_DRIVER_NUM, 0xff10, callback, ud);
+  if (res < 0) {
+    return res;
+  }
+  return allow(IPC_DRIVER_NUM, 0xff10, buf, len);
+}
+
+int ipc_message_done(void) {
+  return allow(IPC_DRIVER_NUM, 0xff10, message_buf, message_len);
+}
+
+static int message_copy(int command_num, int pid, const char* msg, size_t len) {
+  int res = allow(IPC_DRIVER_NUM, 0xff11, (void*)msg, len);
+  if (res < 0) {
+    return res;
+  }
+  return command(IPC_DRIVER_NUM, command_num, pid);
+}
+
+int ipc_message_send(int pid, const char* msg, size_t len) {
+  return message_copy(0xff10, pid, msg, len);
+}
+
+int ipc_message_reply(int pid, const char* msg, size_t len) {
+  return message_copy(0xff11, pid, msg, len);
+}
+
 int ipc_register_svc(subscribe_cb callback, void *ud) {
   return subscribe(IPC_DRIVER_NUM, 0, callback, ud);
 }
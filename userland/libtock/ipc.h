@@ -6,16 +6,53 @@
 
 #define IPC_DRIVER_NUM 0xff
 
+// The longest name a service can register under.
+#define IPC_MAX_NAME_LEN 32
+
+// The longest message the kernel copies.
+#define IPC_MAX_MESSAGE_LEN 256
+
+// What a message callback received.
+#define IPC_MESSAGE_REQUEST 0
+#define IPC_MESSAGE_REPLY   1
+// The service this process waits on a reply from faulted or exited.
+#define IPC_MESSAGE_STOPPED 2
+
 #ifdef __cplusplus
 extern "C" {
 #endif
 
 // Performs service discovery
 //
-// Returns the process identifier of the process with the given package name,
-// or a negative value on error.
+// Returns the process identifier of the service registered under the given
+// name or, failing that, of the process with the given package name. Returns
+// a negative value on error.
 int ipc_discover(const char* pkg_name);
 
+// Registers this process's service under `name`, replacing any name it
+// registered before.
+//
+// Names are at most IPC_MAX_NAME_LEN bytes long. Returns a negative value if
+// the name is too long or another process registered it.
+int ipc_register_service(const char* name);
+
+// Removes the name this process's service is registered under.
+int ipc_unregister_service(void);
+
+// Returns the number of registered services.
+int ipc_service_count(void);
+
+// Returns the process identifier of the registered service at `index`, in
+// process identifier order, or a negative value if `index` is out of range.
+int ipc_service_at(int index);
+
+// Copies the name of the service with process identifier `id` into `buf`,
+// truncating it to `len` bytes.
+//
+// Returns the length of the whole name, or a negative value on error. The
+// name is not NUL-terminated.
+int ipc_service_name(int id, char* buf, size_t len);
+
 // Registers a service callback for this process.
 //
 // Service callbacks are called in response to `notify`s from clients and take
@@ -50,6 +87,38 @@ int ipc_notify_client(int pid);
 // Send a notify to the service at the given process id
 int ipc_notify_svc(int pid);
 
+// Sets the callback for messages and the buffer they are received into.
+//
+// Message callbacks take the following arguments in order:
+//
+//   int pid  - the process id of the sender
+//   int len  - the length of the message in `buf`, or zero for
+//              IPC_MESSAGE_STOPPED
+//   int kind - IPC_MESSAGE_REQUEST, IPC_MESSAGE_REPLY or IPC_MESSAGE_STOPPED
+//   void* ud - `userdata`. same as the argument to this function.
+//
+// The buffer holds one message at a time. Once a message arrived, further
+// messages to this process are refused until `ipc_message_done` is called.
+int ipc_message_listen(subscribe_cb callback, void* ud, char* buf, size_t len);
+
+// Makes the receive buffer ready for the next message.
+int ipc_message_done(void);
+
+// Sends the `len` bytes at `msg` as a request to the service at process id
+// `pid`. The kernel copies the message, so `msg` can be reused right away.
+//
+// The reply, or a notice that the service stopped, arrives at the message
+// callback. Returns a negative value if the service faulted or exited, has a
+// full receive buffer, is not listening for messages, or if the message does
+// not fit its receive buffer.
+int ipc_message_send(int pid, const char* msg, size_t len);
+
+// Replies with the `len` bytes at `msg` to the client at process id `pid`.
+//
+// The client must be waiting on a reply from this process. Fails like
+// `ipc_message_send` otherwise.
+int ipc_message_reply(int pid, const char* msg, size_t len);
+
 // Share a buffer with the given process (either service or client)
 //
 // `pid` is the non-zero process id of the recipient.
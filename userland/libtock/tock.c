@@ -42,6 +42,11 @@ int memop(uint32_t op_type, int arg1) {
   return ret;
 }
 
+void tock_exit(uint32_t completion_code) {
+  asm volatile("svc 5" ::: "memory");
+  __builtin_unreachable();
+}
+
 bool driver_exists(uint32_t driver) {
   int ret = command(driver, 0, 0);
   return ret >= 0;
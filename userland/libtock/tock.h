@@ -14,6 +14,9 @@ typedef void (subscribe_cb)(int, int, int,void*);
 void yield();
 void yield_for(bool*);
 int command(uint32_t driver, uint32_t command, int data);
+// Subscribing a NULL callback unsubscribes. Allowing a NULL pointer or a zero
+// size revokes the buffer. Both return the callback or buffer the driver
+// held, or 0 if it held none.
 int subscribe(uint32_t driver, uint32_t subscribe,
               subscribe_cb cb, void* userdata);
 int allow(uint32_t driver, uint32_t allow, void* ptr, size_t size);
@@ -21,8 +24,29 @@ int allow(uint32_t driver, uint32_t allow, void* ptr, size_t size);
 // op_type can be:
 // 0: brk, arg1 is pointer to new memory break
 // 1: sbrk, arg1 is increment to increase/decrease memory break
+// 2: CPU time the process has used, in milliseconds
+// 3: number of times the process has been scheduled
+// 4: number of times the process ran out of its timeslice
+// 5: start of the process's memory
+// 6: end of the process's memory
+// 7: start of the process's flash
+// 8: end of the process's flash
+// 9: start of the grant region, the kernel's part of the process's memory
+// 10: stack/heap boundary
+// 11: size of the stack
+// 12: bytes left between the memory break and the grant region
+// 13: move the stack/heap boundary, arg1 is the new boundary
+// 14: number of callbacks the kernel dropped because the callback queue was
+//     full. Apps compare it with an earlier value to learn that events were
+//     lost and resynchronize.
+// 15: the deepest the stack has grown, in bytes
 int memop(uint32_t op_type, int arg1);
 
+// Ends the process. The kernel releases its resources and never schedules it
+// again.
+__attribute__ ((noreturn))
+void tock_exit(uint32_t completion_code);
+
 // Checks to see if the given driver number exists on this platform.
 bool driver_exists(uint32_t driver);
 
@@ -12,6 +12,9 @@ use std::mem;
 use std::path::Path;
 use std::slice;
 
+/// The deepest callback queue the kernel accepts, `MAX_CALLBACK_LEN` in
+/// kernel/src/process.rs.
+const MAX_CALLBACK_QUEUE_LEN: u32 = 256;
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
@@ -34,6 +37,9 @@ struct LoadInfo {
     min_kernel_heap_len: u32,
     package_name_offset: u32,
     package_name_size: u32,
+    callback_queue_len: u32,
+    permissions_offset: u32,
+    permissions_size: u32,
     checksum: u32,
 }
 
@@ -58,6 +64,9 @@ impl fmt::Display for LoadInfo {
 min_kernel_heap_len: {:>8} {:>#10X}
 package_name_offset: {:>8} {:>#10X}
   package_name_size: {:>8} {:>#10X}
+ callback_queue_len: {:>8} {:>#10X}
+ permissions_offset: {:>8} {:>#10X}
+   permissions_size: {:>8} {:>#10X}
            checksum: {:>8} {:>#10X}
 ",
         self.version, self.version,
@@ -78,6 +87,9 @@ package_name_offset: {:>8} {:>#10X}
         self.min_kernel_heap_len, self.min_kernel_heap_len,
         self.package_name_offset, self.package_name_offset,
         self.package_name_size, self.package_name_size,
+        self.callback_queue_len, self.callback_queue_len,
+        self.permissions_offset, self.permissions_offset,
+        self.permissions_size, self.permissions_size,
         self.checksum, self.checksum,
         )
     }
@@ -89,6 +101,15 @@ fn main() {
     let mut opts = Options::new();
     opts.optopt("o", "", "set output file name", "OUTFILE");
     opts.optopt("n", "", "set package name", "PACKAGE_NAME");
+    opts.optopt("c",
+                "",
+                "set callback queue length (default: chosen by the board)",
+                "CALLBACK_QUEUE_LEN");
+    opts.optmulti("p",
+                  "",
+                  "permit a driver, optionally only commands FIRST through LAST (default: all \
+                   drivers)",
+                  "DRIVER[:FIRST-LAST]");
     opts.optflag("v", "verbose", "be verbose");
 
     let matches = match opts.parse(&args[1..]) {
@@ -97,6 +118,22 @@ fn main() {
     };
     let output = matches.opt_str("o");
     let package_name = matches.opt_str("n");
+    let callback_queue_len = match matches.opt_str("c") {
+        Some(len) => {
+            let len = len.parse::<u32>().expect("Invalid callback queue length");
+            if len > MAX_CALLBACK_QUEUE_LEN {
+                panic!("Callback queue length {} exceeds the kernel maximum of {}",
+                       len,
+                       MAX_CALLBACK_QUEUE_LEN);
+            }
+            len
+        }
+        None => 0,
+    };
+    let permissions: Vec<[u32; 3]> = matches.opt_strs("p")
+        .iter()
+        .map(|permission| parse_permission(permission).expect("Invalid driver permission"))
+        .collect();
     let verbose = matches.opt_present("v");
     let input = if !matches.free.is_empty() {
         matches.free[0].clone()
@@ -115,11 +152,23 @@ fn main() {
     match output {
             None => {
                 let mut out = io::stdout();
-                do_work(&file, &mut out, package_name, verbose)
+                do_work(&file,
+                        &mut out,
+                        package_name,
+                        callback_queue_len,
+                        &permissions,
+                        verbose)
             }
             Some(name) => {
                 match File::create(Path::new(&name)) {
-                    Ok(mut f) => do_work(&file, &mut f, package_name, verbose),
+                    Ok(mut f) => {
+                        do_work(&file,
+                                &mut f,
+                                package_name,
+                                callback_queue_len,
+                                &permissions,
+                                verbose)
+                    }
                     Err(e) => panic!("Error: {:?}", e),
                 }
             }
@@ -127,6 +176,33 @@ fn main() {
         .expect("Failed to write output");
 }
 
+fn parse_number(number: &str) -> Option<u32> {
+    if number.starts_with("0x") {
+        u32::from_str_radix(&number[2..], 16).ok()
+    } else {
+        number.parse::<u32>().ok()
+    }
+}
+
+/// Parses `DRIVER` or `DRIVER:FIRST-LAST` into a permission entry.
+fn parse_permission(permission: &str) -> Option<[u32; 3]> {
+    let mut parts = permission.splitn(2, ':');
+    let driver = match parts.next().and_then(parse_number) {
+        Some(driver) => driver,
+        None => return None,
+    };
+    match parts.next() {
+        None => Some([driver, 0, u32::max_value()]),
+        Some(range) => {
+            let mut ends = range.splitn(2, '-');
+            match (ends.next().and_then(parse_number), ends.next().and_then(parse_number)) {
+                (Some(first), Some(last)) if first <= last => Some([driver, first, last]),
+                _ => None,
+            }
+        }
+    }
+}
+
 fn print_usage(program: &str, opts: Options) {
     let brief = format!("Usage: {} [-o OUTFILE] FILE", program);
     print!("{}", opts.usage(&brief));
@@ -167,6 +243,8 @@ unsafe fn as_byte_slice<'a, T: Copy>(input: &'a T) -> &'a [u8] {
 fn do_work(input: &elf::File,
            output: &mut Write,
            package_name: Option<String>,
+           callback_queue_len: u32,
+           permissions: &[[u32; 3]],
            verbose: bool)
            -> io::Result<()> {
     let package_name = package_name.unwrap_or(String::new());
@@ -187,8 +265,13 @@ fn do_work(input: &elf::File,
     let app_heap_len = get_section(input, ".app_heap").data.len() as u32;
     let kernel_heap_len = get_section(input, ".kernel_heap").data.len() as u32;
 
-    let mut total_size = (mem::size_of::<LoadInfo>() + rel_data.len() + text.data.len() +
-                          got.data.len() + data.data.len() +
+    // The permissions follow the data, aligned to a word
+    let data_end = mem::size_of::<LoadInfo>() + rel_data.len() + text.data.len() +
+                   got.data.len() + data.data.len();
+    let permissions_pad = (4 - data_end % 4) % 4;
+    let permissions_size = (permissions.len() * mem::size_of::<[u32; 3]>()) as u32;
+
+    let mut total_size = (data_end + permissions_pad + permissions_size as usize +
                           package_name.len()) as u32;
 
     let pad = if total_size.count_ones() > 1 {
@@ -207,10 +290,11 @@ fn do_work(input: &elf::File,
     let got_size = got.shdr.size as u32;
     let data_offset = got_offset + got_size;
     let data_size = data.shdr.size as u32;
-    let package_name_offset = data_offset + data_size;
+    let permissions_offset = data_offset + data_size + permissions_pad as u32;
+    let package_name_offset = permissions_offset + permissions_size;
     let package_name_size = package_name.len() as u32;
 
-    let load_info_version = 1;
+    let load_info_version = 3;
 
     let load_info = LoadInfo {
         version: load_info_version,
@@ -231,12 +315,16 @@ fn do_work(input: &elf::File,
         min_kernel_heap_len: kernel_heap_len,
         package_name_offset: package_name_offset,
         package_name_size: package_name_size,
+        callback_queue_len: callback_queue_len,
+        permissions_offset: permissions_offset,
+        permissions_size: permissions_size,
         checksum: load_info_version ^ total_size ^ entry_offset ^ rel_data_offset ^
                   rel_data_size as u32 ^ text_offset ^ text_size ^
                   got_offset ^ got_size ^ data_offset ^ data_size ^
                   bss.shdr.addr as u32 ^
                   bss.shdr.size as u32 ^ stack_len ^ app_heap_len ^
-                  kernel_heap_len ^ package_name_offset ^ package_name_size,
+                  kernel_heap_len ^ package_name_offset ^ package_name_size ^
+                  callback_queue_len ^ permissions_offset ^ permissions_size,
     };
 
     if verbose {
@@ -248,6 +336,10 @@ fn do_work(input: &elf::File,
     try!(output.write_all(text.data.as_ref()));
     try!(output.write_all(got.data.as_ref()));
     try!(output.write_all(data.data.as_ref()));
+    try!(output.write_all(&[0u8; 3][..permissions_pad]));
+    for permission in permissions {
+        try!(output.write_all(unsafe { as_byte_slice(permission) }));
+    }
     try!(output.write_all(package_name.as_ref()));
 
     let mut pad = pad as usize;